@@ -0,0 +1,116 @@
+// Benchmarks of the line diff, the character diff within lines and the
+// alignment of replaced blocks, each on inputs of a few sizes so that a
+// regression which only shows on large inputs is visible. Run with cargo bench.
+use std::hint::black_box;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use jiff::diff::{self, DiffOptions};
+
+const SIZES: [usize; 3] = [100, 1000, 10000];
+
+// Makes the texts before and after a change to a file of the given number of
+// lines.
+type Input = fn(usize) -> (String, String);
+
+// A line of code-like text, different for each number.
+fn line(i: usize) -> String {
+    format!("    let value_{} = compute(input[{}], {} * scale);", i, i % 17, i)
+}
+
+fn lines(count: usize) -> Vec<String> {
+    (0..count).map(line).collect()
+}
+
+fn text(lines: &[String]) -> String {
+    lines.iter().map(|line| format!("{}\n", line)).collect()
+}
+
+// A file with one line changed in the middle.
+fn small_edit(count: usize) -> (String, String) {
+    let before = lines(count);
+    let mut after = before.clone();
+    after[count / 2].push_str(" // changed");
+    (text(&before), text(&after))
+}
+
+// A file whose middle half is replaced by similar but edited lines.
+fn large_replace(count: usize) -> (String, String) {
+    let before = lines(count);
+    let mut after = before.clone();
+    for line in &mut after[count / 4..count * 3 / 4] {
+        *line = line.replace("compute", "recompute");
+    }
+    (text(&before), text(&after))
+}
+
+// A file with every tenth line changed, so that there are many small hunks.
+fn many_hunks(count: usize) -> (String, String) {
+    let before = lines(count);
+    let mut after = before.clone();
+    for line in after.iter_mut().step_by(10) {
+        line.push_str(" // changed");
+    }
+    (text(&before), text(&after))
+}
+
+fn bench_line_diff(c: &mut Criterion) {
+    let opts = DiffOptions::new();
+    let mut group = c.benchmark_group("calculate_line_diff");
+    // The largest inputs take around a second each, so fewer samples are taken.
+    group.sample_size(10);
+    let inputs: [(&str, Input); 3] = [
+        ("small_edit", small_edit),
+        ("large_replace", large_replace),
+        ("many_hunks", many_hunks),
+    ];
+    for (name, input) in inputs {
+        for size in SIZES {
+            let (before, after) = input(size);
+            group.bench_with_input(BenchmarkId::new(name, size), &(before, after),
+                                   |b, (before, after)| {
+                b.iter(|| diff::calculate_line_diff(black_box(before), black_box(after), &opts))
+            });
+        }
+    }
+    group.finish();
+}
+
+fn bench_char_diff(c: &mut Criterion) {
+    let mut group = c.benchmark_group("calculate_char_diff");
+    for size in [10, 100, 1000] {
+        // Lines of the given number of words, with every third word changed.
+        let before: Vec<String> = (0..size).map(|i| format!("word{}", i)).collect();
+        let after: Vec<String> = before.iter().enumerate()
+            .map(|(i, word)| if i % 3 == 0 { word.to_uppercase() } else { word.clone() })
+            .collect();
+        let (before, after) = (before.join(" "), after.join(" "));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &(before, after),
+                               |b, (before, after)| {
+            b.iter(|| diff::calculate_char_diff(black_box(before), black_box(after)))
+        });
+    }
+    group.finish();
+}
+
+// Aligning builds the alignment matrix of a replaced block and finds the
+// shortest path through it, which is quadratic in the block's length.
+fn bench_align(c: &mut Criterion) {
+    let mut group = c.benchmark_group("align");
+    group.sample_size(10);
+    for size in [10, 100, 300] {
+        let before = lines(size);
+        let after: Vec<String> = before.iter().enumerate()
+            .filter(|(i, _)| i % 7 != 0)
+            .map(|(_, line)| line.replace("scale", "factor"))
+            .collect();
+        let before: Vec<&str> = before.iter().map(String::as_str).collect();
+        let after: Vec<&str> = after.iter().map(String::as_str).collect();
+        group.bench_with_input(BenchmarkId::from_parameter(size), &(before, after),
+                               |b, (before, after)| {
+            b.iter(|| diff::align(black_box(before), black_box(after)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_line_diff, bench_char_diff, bench_align);
+criterion_main!(benches);
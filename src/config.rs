@@ -0,0 +1,80 @@
+// Defaults for the command line options, read from a TOML file so that they
+// don't have to be passed every time. Options given on the command line take
+// precedence over them.
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use serde::Deserialize;
+
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    pub color: Option<bool>,
+    pub pager: Option<bool>,
+    pub side_by_side: Option<bool>,
+    pub inline: Option<bool>,
+    pub show_whitespace: Option<bool>,
+    pub context: Option<usize>,
+    pub width: Option<usize>,
+    pub tab_width: Option<usize>,
+    pub theme: Option<String>,
+    pub highlight_style: Option<String>,
+    pub separator: Option<String>,
+    pub context_marker: Option<String>,
+    pub marker_add: Option<String>,
+    pub marker_remove: Option<String>,
+    pub marker_same: Option<String>,
+}
+
+// The file named by $JIFF_CONFIG, or else jiff/config.toml under the user's
+// config directory.
+fn config_path() -> Option<PathBuf> {
+    if let Some(path) = env::var_os("JIFF_CONFIG") {
+        return Some(PathBuf::from(path));
+    }
+    let config_dir = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_dir.join("jiff").join("config.toml"))
+}
+
+fn parse(text: &str) -> Result<Config, String> {
+    toml::from_str(text).map_err(|error| error.to_string())
+}
+
+// Reads the config file, or the defaults if there isn't one.
+pub fn load() -> Result<Config, String> {
+    let path = match config_path() {
+        Some(path) => path,
+        None => return Ok(Config::default()),
+    };
+    match fs::read_to_string(&path) {
+        Ok(text) => parse(&text).map_err(|error| format!("{}: {}", path.display(), error)),
+        Err(ref error) if error.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+        Err(error) => Err(format!("{}: {}", path.display(), error)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_config() {
+        let config = parse("color = false\nside-by-side = true\ncontext = 3\nseparator = \"|\"\n");
+        assert_eq!(Ok(Config {
+            color:        Some(false),
+            side_by_side: Some(true),
+            context:      Some(3),
+            separator:    Some("|".to_string()),
+            ..Config::default()
+        }), config);
+        assert_eq!(Ok(Config::default()), parse(""));
+    }
+
+    #[test]
+    fn parse_config_unknown_option() {
+        assert!(parse("colour = false\n").is_err());
+    }
+}
@@ -31,8 +31,8 @@ impl AlignmentNode {
     fn new(x: usize, y: usize, weight: i32) -> AlignmentNode {
         AlignmentNode {
             id: Point { x, y },
-            weight: weight,
-            relax_weight: std::i32::MAX,
+            weight,
+            relax_weight: i32::MAX,
             relax_parent: Point { x: 0, y: 0 },
         }
     }
@@ -65,7 +65,7 @@ struct AlignmentMatrix {
 }
 
 impl AlignmentMatrix {
-    fn new(lines_b: &Vec<&str>, lines_a: &Vec<&str>) -> AlignmentMatrix {
+    fn new(lines_b: &[&str], lines_a: &[&str], max_line_length: Option<usize>) -> AlignmentMatrix {
         let lines_b_len = lines_b.len();
         let lines_a_len = lines_a.len();
         let line_matrix_x_len = lines_b_len * 2 + 1;
@@ -83,6 +83,11 @@ impl AlignmentMatrix {
         for line_a in lines_a {
             unalign_a_weights.push(line_a.len() as i32);
         }
+        // Lines too long to diff in reasonable time are never paired, which is
+        // ensured by pairing them costing more than leaving both lines unpaired.
+        let too_long = |line: &&str| max_line_length.is_some_and(|max| line.chars().count() > max);
+        let long_b: Vec<bool> = lines_b.iter().map(too_long).collect();
+        let long_a: Vec<bool> = lines_a.iter().map(too_long).collect();
         // Next, compute the edit distance for all lines to one another - i.e.
         // if every line were aligned with one another.
         let mut line_matrix = Vec::with_capacity(line_matrix_x_len);
@@ -95,6 +100,9 @@ impl AlignmentMatrix {
                     (false, false) => -1,
                     (true, false) => unalign_b_weights[x/2],
                     (false, true) => unalign_a_weights[y/2],
+                    (true, true) if long_b[x/2] || long_a[y/2] => {
+                        unalign_b_weights[x/2] + unalign_a_weights[y/2] + 1
+                    },
                     (true, true) => {
                         let line_b = lines_b[x/2];
                         let line_a = lines_a[y/2];
@@ -114,12 +122,13 @@ impl AlignmentMatrix {
     }
 
     fn root_adjacency(&self) -> Vec<Point> {
-        let mut adjacency = Vec::with_capacity(3);
-        adjacency.push(Point { x: 0, y: 1 });
-        adjacency.push(Point { x: 1, y: 0 });
-        adjacency.push(Point { x: 1, y: 1 });
+        let adjacency = vec![
+            Point { x: 0, y: 1 },
+            Point { x: 1, y: 0 },
+            Point { x: 1, y: 1 },
+        ];
         // The nodes in the output are guaranteed to be in topological order.
-        return adjacency;
+        adjacency
     }
 
     fn adjacency(&self, node: &AlignmentNode) -> Vec<Point> {
@@ -140,7 +149,7 @@ impl AlignmentMatrix {
             adjacency.push(Point { x: next_x_aligned, y: next_y_aligned });
         }
         // The nodes in the output are guaranteed to be in topological order.
-        return adjacency;
+        adjacency
     }
 
     fn walk_path(&self, exit: &AlignmentNode) -> Vec<Point> {
@@ -152,15 +161,16 @@ impl AlignmentMatrix {
             pos = &self.line_matrix[next.x][next.y];
         }
         path.reverse();
-        return path;
+        path
     }
 
     fn shortest_path(&mut self) -> Vec<Point> {
         // Initialize the root adjacency nodes (i.e. those accessible from
-        // the single source node).
+        // the single source node) with their own weights, since the first
+        // pairing (or not) costs as much as any other.
         for adj in self.root_adjacency() {
             let vertex = &mut self.line_matrix[adj.x][adj.y];
-            vertex.relax_weight = 0;
+            vertex.relax_weight = vertex.weight;
         }
         // Walk all nodes.
         // The line matrix is iterated in topological order, line by line, since
@@ -196,47 +206,169 @@ impl AlignmentMatrix {
         let exit_x  = &self.line_matrix[self.line_matrix_x_len-2][self.line_matrix_y_len-1];
         let exit_y  = &self.line_matrix[self.line_matrix_x_len-1][self.line_matrix_y_len-2];
         if exit_x.relax_weight < exit_y.relax_weight && exit_x.relax_weight < exit_xy.relax_weight {
-            return self.walk_path(exit_x);
+            self.walk_path(exit_x)
         } else if exit_y.relax_weight < exit_xy.relax_weight {
-            return self.walk_path(exit_y);
+            self.walk_path(exit_y)
         } else {
-            return self.walk_path(exit_xy);
+            self.walk_path(exit_xy)
         }
     }
 }
 
 impl fmt::Display for AlignmentMatrix {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Alignment matrix ({} x {}):\n", self.line_matrix_x_len, self.line_matrix_y_len)?;
+        writeln!(f, "Alignment matrix ({} x {}):", self.line_matrix_x_len, self.line_matrix_y_len)?;
         for x in 0..self.line_matrix_x_len {
             for y in 0..self.line_matrix_y_len {
                 write!(f, " {:4}", self.line_matrix[x][y].weight)?;
             }
-            write!(f, "\n")?;
+            writeln!(f)?;
         }
         Ok(())
     }
 }
 
-pub fn align<'a>(lines_b: &Vec<&'a str>, lines_a: &Vec<&'a str>) ->
-        Vec<(Option<&'a str>, Option<&'a str>)> {
-    let mut matrix = AlignmentMatrix::new(lines_b, lines_a);
+// Aligns the before and after lines of a replacement, in order, pairing up the
+// lines which are most alike. Each line is either paired with one on the other
+// side or left unpaired, and the alignment with the lowest total cost is found
+// as the shortest path through a grid of the choices:
+//   - leaving a line unpaired costs its length in bytes, and
+//   - pairing two lines costs their character edit distance scaled up by the
+//     number of separate changes, so that one contiguous edit is preferred over
+//     a scattering of small ones.
+// Two lines are therefore only paired when that costs no more than showing one
+// as removed and the other as added.
+pub fn align<'a>(before: &[&'a str], after: &[&'a str]) -> Vec<(Option<&'a str>, Option<&'a str>)> {
+    align_with_threshold(before, after, 1.0)
+}
+
+// Aligns the lines as align does, but never pairs two lines whose edit ratio is
+// above the threshold.
+pub fn align_with_threshold<'a>(lines_b: &[&'a str], lines_a: &[&'a str],
+                                threshold: f64) -> Vec<(Option<&'a str>, Option<&'a str>)> {
+    align_with_limits(lines_b, lines_a, threshold, None)
+}
+
+// Aligns the lines as align_with_threshold does, but also never pairs a line of
+// more than `max_line_length` chars, since finding its edit distance to every
+// line on the other side would take too long.
+pub fn align_with_limits<'a>(lines_b: &[&'a str], lines_a: &[&'a str], threshold: f64,
+                             max_line_length: Option<usize>)
+        -> Vec<(Option<&'a str>, Option<&'a str>)> {
+    // With nothing on one side there is nothing to pair (and no path to find).
+    if lines_b.is_empty() || lines_a.is_empty() {
+        return lines_b.iter().map(|&line_b| (Some(line_b), None))
+                      .chain(lines_a.iter().map(|&line_a| (None, Some(line_a))))
+                      .collect();
+    }
+    let mut matrix = AlignmentMatrix::new(lines_b, lines_a, max_line_length);
     let path = matrix.shortest_path();
     let mut alignment = Vec::with_capacity(lines_b.len() + lines_a.len());
     for point in path {
-        let before;
-        let after;
-        if point.x & 1 > 0 {
-            before = Some(lines_b[point.x / 2]);
+        let before = if point.x & 1 > 0 {
+            Some(lines_b[point.x / 2])
         } else {
-            before = None;
-        }
-        if point.y & 1 > 0 {
-            after = Some(lines_a[point.y / 2]);
+            None
+        };
+        let after = if point.y & 1 > 0 {
+            Some(lines_a[point.y / 2])
         } else {
-            after = None;
+            None
+        };
+        match (before, after) {
+            (Some(line_b), Some(line_a))
+                    if threshold < 1.0 && edit_ratio(line_b, line_a) > threshold => {
+                alignment.push((before, None));
+                alignment.push((None, after));
+            },
+            _ => alignment.push((before, after)),
         }
-        alignment.push((before, after));
     }
-    return alignment;
+    alignment
+}
+
+// Describes how the lines of a replacement are aligned, for debugging: the grid
+// of weights followed by the path chosen through it.
+pub fn dump_alignment(lines_b: &[&str], lines_a: &[&str]) -> String {
+    if lines_b.is_empty() || lines_a.is_empty() {
+        return "Alignment matrix (empty): nothing to align\n".to_string();
+    }
+    let mut matrix = AlignmentMatrix::new(lines_b, lines_a, None);
+    let path = matrix.shortest_path();
+    format!("{}Path: {:?}\n", matrix, path)
+}
+
+// The proportion of the two lines' characters which would have to be inserted
+// or deleted to turn one into the other, from 0.0 (identical) to 1.0 (nothing in
+// common).
+fn edit_ratio(line_b: &str, line_a: &str) -> f64 {
+    let total = line_b.chars().count() + line_a.chars().count();
+    if total == 0 {
+        return 0.0;
+    }
+    Changeset::new(line_b, line_a, "").distance as f64 / total as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dump_alignment_shows_matrix_and_path() {
+        let dump = dump_alignment(&["abc"], &["abd"]);
+        assert!(dump.starts_with("Alignment matrix (3 x 3):\n"), "{}", dump);
+        assert!(dump.ends_with("Path: [(1,1)]\n"), "{}", dump);
+        assert_eq!("Alignment matrix (empty): nothing to align\n", dump_alignment(&[], &["x"]));
+    }
+
+    #[test]
+    fn align_pairs_similar_lines() {
+        let lines_b = ["fn main() {", "    let x = 1;"];
+        let lines_a = ["    let x = 2;"];
+        assert_eq!(vec![(Some("fn main() {"), None), (Some("    let x = 1;"), Some("    let x = 2;"))],
+                   align(&lines_b, &lines_a));
+    }
+
+    #[test]
+    fn align_counts_first_pairing() {
+        // Pairing the first lines would otherwise come for free, however
+        // unalike they are.
+        assert_eq!(vec![(Some("xyz"), None), (Some("hello world"), Some("hello there world"))],
+                   align(&["xyz", "hello world"], &["hello there world"]));
+    }
+
+    #[test]
+    fn align_pairs_most_alike_line() {
+        assert_eq!(vec![(Some("hello world"), Some("hello there world")), (Some("xyz"), None)],
+                   align(&["hello world", "xyz"], &["hello there world"]));
+        assert_eq!(vec![(None, Some("a"))], align(&[], &["a"]));
+    }
+
+    #[test]
+    fn align_threshold_splits_dissimilar_lines() {
+        let lines_b = vec!["foo = bar(x)"];
+        let lines_a = vec!["foo = baz(y)"];
+        assert_eq!(vec![(Some(lines_b[0]), Some(lines_a[0]))],
+                   align_with_threshold(&lines_b, &lines_a, 0.5));
+        assert_eq!(vec![(Some(lines_b[0]), None), (None, Some(lines_a[0]))],
+                   align_with_threshold(&lines_b, &lines_a, 0.1));
+    }
+
+    #[test]
+    fn align_threshold_keeps_similar_lines() {
+        let lines_b = vec!["let value = compute(1);"];
+        let lines_a = vec!["let value = compute(2);"];
+        assert_eq!(vec![(Some(lines_b[0]), Some(lines_a[0]))],
+                   align_with_threshold(&lines_b, &lines_a, 0.2));
+    }
+
+    #[test]
+    fn align_limits_leave_long_lines_unpaired() {
+        let lines_b = ["short", "a longer line"];
+        let lines_a = ["short!", "a longer line!"];
+        assert_eq!(vec![(Some("short"), Some("short!")), (Some("a longer line"), None),
+                        (None, Some("a longer line!"))],
+                   align_with_limits(&lines_b, &lines_a, 1.0, Some(10)));
+        assert_eq!(align(&lines_b, &lines_a), align_with_limits(&lines_b, &lines_a, 1.0, None));
+    }
 }
@@ -0,0 +1,66 @@
+use super::Diff;
+
+// The line with the characters in each of the inclusive, 1-based column ranges
+// cut out of it, for comparing lines without their volatile fields.
+pub(super) fn mask_columns(line: &str, columns: &[(usize, usize)]) -> String {
+    line.chars().enumerate()
+        .filter(|&(i, _)| !columns.iter().any(|&(first, last)| first <= i + 1 && i < last))
+        .map(|(_, c)| c)
+        .collect()
+}
+
+// Puts the original lines back into diffs which were found between the masked
+// lines. Runs of lines which only matched once masked are kept as ignored
+// replacements, so that each side's lines stay as they were.
+pub(super) fn unmask<'a>(diffs: Vec<Diff>, lines_l: &[&'a str], lines_r: &[&'a str]) -> Vec<Diff> {
+    let (mut lines_l, mut lines_r) = (lines_l.iter().copied(), lines_r.iter().copied());
+    let take = |lines: &mut dyn Iterator<Item = &'a str>, count| -> Vec<&'a str> {
+        lines.take(count).collect()
+    };
+    let mut unmasked = Vec::new();
+    for change in diffs {
+        let (count_l, count_r) = change.line_counts();
+        match change {
+            Diff::Same(_) => {
+                let same_l = take(&mut lines_l, count_l);
+                let same_r = take(&mut lines_r, count_r);
+                let mut pairs = same_l.iter().zip(&same_r).peekable();
+                while let Some(&(first_l, first_r)) = pairs.peek() {
+                    let identical = first_l == first_r;
+                    let (mut run_l, mut run_r) = (Vec::new(), Vec::new());
+                    while let Some((line_l, line_r)) = pairs.next_if(|(l, r)| (l == r) == identical) {
+                        run_l.push(*line_l);
+                        run_r.push(*line_r);
+                    }
+                    unmasked.push(if identical {
+                        Diff::Same(run_l.join("\n"))
+                    } else {
+                        Diff::Ignored(Box::new(Diff::Replace(run_l.join("\n"), run_r.join("\n"))))
+                    });
+                }
+            },
+            Diff::Add(_) => unmasked.push(Diff::Add(take(&mut lines_r, count_r).join("\n"))),
+            Diff::Remove(_) => unmasked.push(Diff::Remove(take(&mut lines_l, count_l).join("\n"))),
+            Diff::Replace(..) => {
+                let rem = take(&mut lines_l, count_l).join("\n");
+                let add = take(&mut lines_r, count_r).join("\n");
+                unmasked.push(Diff::Replace(rem, add));
+            },
+            Diff::Ignored(_) | Diff::Unmatched(_) => unmasked.push(change),
+        }
+    }
+    unmasked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mask_column_ranges() {
+        assert_eq!("cdefg", mask_columns("abcdefg", &[(1, 2)]));
+        assert_eq!("ag", mask_columns("abcdefg", &[(2, 3), (4, 6)]));
+        assert_eq!("ab", mask_columns("ab", &[(5, 10)]));
+        assert_eq!("a", mask_columns("abc", &[(2, usize::MAX)]));
+    }
+}
@@ -0,0 +1,108 @@
+use regex::Regex;
+use super::Diff;
+
+// The lines which match the regex, along with the index of each in the file.
+pub(super) fn matching_lines<'a>(lines: &[&'a str], regex: &Regex) -> (Vec<&'a str>, Vec<usize>) {
+    lines.iter().enumerate()
+        .filter(|(_, line)| regex.is_match(line))
+        .map(|(i, &line)| (line, i))
+        .unzip()
+}
+
+// Appends the change, joining it onto the last one where they're of the same
+// kind, and making a removal followed directly by an addition a replacement.
+fn push(diffs: &mut Vec<Diff>, change: Diff) {
+    let join = |text: &mut String, more: &str| {
+        text.push('\n');
+        text.push_str(more);
+    };
+    match (diffs.last_mut(), change) {
+        (Some(Diff::Same(last)), Diff::Same(same)) => join(last, &same),
+        (Some(Diff::Remove(last)), Diff::Remove(rem)) => join(last, &rem),
+        (Some(Diff::Add(last)), Diff::Add(add))
+        | (Some(Diff::Replace(_, last)), Diff::Add(add)) => join(last, &add),
+        (Some(Diff::Remove(_)), Diff::Add(add)) => {
+            if let Some(Diff::Remove(rem)) = diffs.pop() {
+                diffs.push(Diff::Replace(rem, add));
+            }
+        },
+        (_, change) => diffs.push(change),
+    }
+}
+
+// The lines between the cursor and the end, moving the cursor on past them.
+fn skip<'a>(lines: &[&'a str], cursor: &mut usize, end: usize) -> Vec<&'a str> {
+    let skipped = lines[*cursor..end].to_vec();
+    *cursor = end;
+    skipped
+}
+
+// Appends a change which was ignored, joining it onto the last one where that
+// was ignored too.
+fn push_ignored(diffs: &mut Vec<Diff>, change: Diff) {
+    let mut joined = Vec::new();
+    if let Some(Diff::Ignored(_)) = diffs.last() {
+        if let Some(Diff::Ignored(last)) = diffs.pop() {
+            joined.push(*last);
+        }
+    }
+    push(&mut joined, change);
+    diffs.extend(joined.into_iter().map(|change| Diff::Ignored(Box::new(change))));
+}
+
+// The lines either side which didn't match and were left out, if any.
+fn unmatched(lines_l: &[&str], lines_r: &[&str]) -> Option<Diff> {
+    let change = match (lines_l.is_empty(), lines_r.is_empty()) {
+        (true, true) => return None,
+        (false, true) => Diff::Remove(lines_l.join("\n")),
+        (true, false) => Diff::Add(lines_r.join("\n")),
+        (false, false) => Diff::Replace(lines_l.join("\n"), lines_r.join("\n")),
+    };
+    Some(Diff::Unmatched(Box::new(change)))
+}
+
+// Weaves the lines which didn't match back into diffs found between just the
+// lines which did, as unmatched changes, so that every line of both files is
+// accounted for and keeps its line number. `matched_l` and `matched_r` are the
+// indices the matching lines had in the files.
+pub(super) fn weave(diffs: Vec<Diff>, lines_l: &[&str], matched_l: &[usize], lines_r: &[&str],
+                    matched_r: &[usize]) -> Vec<Diff> {
+    let mut woven = Vec::new();
+    let (mut cursor_l, mut cursor_r) = (0, 0);
+    let (mut next_l, mut next_r) = (matched_l.iter(), matched_r.iter());
+    for change in diffs {
+        let (count_l, count_r) = change.line_counts();
+        match change {
+            Diff::Same(_) => {
+                for (&i, &j) in next_l.by_ref().take(count_l).zip(next_r.by_ref().take(count_r)) {
+                    let gap_l = skip(lines_l, &mut cursor_l, i);
+                    let gap_r = skip(lines_r, &mut cursor_r, j);
+                    woven.extend(unmatched(&gap_l, &gap_r));
+                    push(&mut woven, Diff::Same(skip(lines_l, &mut cursor_l, i + 1).join("\n")));
+                    cursor_r = j + 1;
+                }
+            },
+            _ => {
+                // A change which was ignored (by -I, say) stays ignored, apart
+                // from the lines which didn't match within it.
+                let push_line = match change {
+                    Diff::Ignored(_) | Diff::Unmatched(_) => push_ignored,
+                    _ => push,
+                };
+                for &i in next_l.by_ref().take(count_l) {
+                    woven.extend(unmatched(&skip(lines_l, &mut cursor_l, i), &[]));
+                    let line = skip(lines_l, &mut cursor_l, i + 1).join("\n");
+                    push_line(&mut woven, Diff::Remove(line));
+                }
+                for &j in next_r.by_ref().take(count_r) {
+                    woven.extend(unmatched(&[], &skip(lines_r, &mut cursor_r, j)));
+                    let line = skip(lines_r, &mut cursor_r, j + 1).join("\n");
+                    push_line(&mut woven, Diff::Add(line));
+                }
+            },
+        }
+    }
+    woven.extend(unmatched(&skip(lines_l, &mut cursor_l, lines_l.len()),
+                           &skip(lines_r, &mut cursor_r, lines_r.len())));
+    woven
+}
@@ -0,0 +1,276 @@
+use std::cmp::max;
+use regex::Regex;
+use super::Diff;
+
+// A contiguous region of the diff, along with the (1-based) line numbers it
+// starts at and the number of lines it covers on each side.
+#[derive(Debug, PartialEq)]
+pub struct Hunk {
+    pub left_start: usize,
+    pub left_len: usize,
+    pub right_start: usize,
+    pub right_len: usize,
+    pub diffs: Vec<Diff>,
+    // The nearest line before the hunk which looks like the start of a
+    // function or section, if known.
+    pub function: Option<String>,
+}
+
+impl Hunk {
+    pub(super) fn new(left_start: usize, right_start: usize) -> Hunk {
+        Hunk {
+            left_start, left_len: 0, right_start, right_len: 0, diffs: Vec::new(), function: None,
+        }
+    }
+
+    pub(super) fn push(&mut self, change: Diff) {
+        let (left_len, right_len) = change.line_counts();
+        self.left_len += left_len;
+        self.right_len += right_len;
+        self.diffs.push(change);
+    }
+
+    fn has_changes(&self) -> bool {
+        super::has_changes(&self.diffs)
+    }
+
+    // Formats the hunk's range in the same way as a unified diff, where an
+    // empty side is given as starting at the line before it.
+    pub fn header(&self) -> String {
+        let left_start = if self.left_len == 0 { self.left_start - 1 } else { self.left_start };
+        let right_start = if self.right_len == 0 { self.right_start - 1 } else { self.right_start };
+        let header = format!("@@ -{},{} +{},{} @@",
+                             left_start, self.left_len, right_start, self.right_len);
+        match &self.function {
+            Some(function) => format!("{} {}", header, function),
+            None => header,
+        }
+    }
+}
+
+// Splits the diffs into hunks, keeping only `context` unchanged lines either
+// side of each change, as well as the first and last `edges` lines of the file.
+// As in GNU diff, changes with no more than `2 * context` unchanged lines
+// between them share a hunk, rather than their contexts overlapping. With no
+// context the whole diff is a single hunk. A run of no more than
+// `collapse_over` unchanged lines between two changes is always kept whole,
+// rather than splitting the hunk. Line numbers are counted from `start` on each
+// side.
+pub fn hunks(diffs: &[Diff], context: Option<usize>, collapse_over: usize, edges: (usize, usize),
+             start: (usize, usize)) -> Vec<Hunk> {
+    let context = match context {
+        Some(context) => context,
+        None => {
+            let mut hunk = Hunk::new(start.0, start.1);
+            for change in diffs {
+                hunk.push(change.clone());
+            }
+            return if hunk.diffs.is_empty() { vec![] } else { vec![hunk] };
+        },
+    };
+    // The edges are measured in lines of the left file.
+    let total_len: usize = diffs.iter().map(|change| change.line_counts().0).sum();
+    let tail_from = total_len.saturating_sub(edges.1);
+    let mut hunks = Vec::new();
+    let mut hunk = Hunk::new(start.0, start.1);
+    // Whether the current hunk holds part of an edge, so must be kept even
+    // without any changes.
+    let mut pinned = false;
+    let mut pos = 0;
+    for (i, change) in diffs.iter().enumerate() {
+        let same = match change {
+            Diff::Same(same) => same,
+            _ => {
+                pos += change.line_counts().0;
+                hunk.push(change.clone());
+                continue;
+            },
+        };
+        // Nothing before the first change or after the last needs to be kept
+        // for context, unless it falls within an edge.
+        let lines: Vec<&str> = same.split('\n').collect();
+        let head_edge = edges.0.saturating_sub(pos).min(lines.len());
+        let tail_edge = (pos + lines.len()).saturating_sub(max(tail_from, pos));
+        pos += lines.len();
+        let keep_head = max(head_edge, if i == 0 { 0 } else { context });
+        let keep_tail = max(tail_edge, if i == diffs.len() - 1 { 0 } else { context });
+        let between_changes = i != 0 && i != diffs.len() - 1;
+        if lines.len() <= keep_head + keep_tail || (between_changes && lines.len() <= collapse_over) {
+            pinned |= head_edge > 0 || tail_edge > 0;
+            hunk.push(change.clone());
+            continue;
+        }
+        if keep_head > 0 {
+            pinned |= head_edge > 0;
+            hunk.push(Diff::Same(lines[..keep_head].join("\n")));
+        }
+        let tail_start = lines.len() - keep_tail;
+        let skipped = tail_start - keep_head;
+        let next = Hunk::new(hunk.left_start + hunk.left_len + skipped,
+                             hunk.right_start + hunk.right_len + skipped);
+        let finished = std::mem::replace(&mut hunk, next);
+        if finished.has_changes() || pinned {
+            hunks.push(finished);
+        }
+        pinned = tail_edge > 0;
+        if keep_tail > 0 {
+            hunk.push(Diff::Same(lines[tail_start..].join("\n")));
+        }
+    }
+    if hunk.has_changes() || pinned {
+        hunks.push(hunk);
+    }
+    hunks
+}
+
+// Labels each hunk with the last unchanged line before it which matches the
+// regex, in the manner of diff's --show-function-line. The diffs are those the
+// hunks were made from, starting on line `start`.
+pub fn annotate_functions(hunks: &mut [Hunk], diffs: &[Diff], start: (usize, usize),
+                          regex: &Regex) {
+    let mut functions = Vec::new();
+    let mut lineno = start.0;
+    for change in diffs {
+        if let Diff::Same(same) = change {
+            for (offset, line) in same.split('\n').enumerate() {
+                if regex.is_match(line) {
+                    functions.push((lineno + offset, line.trim_end()));
+                }
+            }
+        }
+        lineno += change.line_counts().0;
+    }
+    for hunk in hunks {
+        hunk.function = functions.iter().rev()
+                                 .find(|(lineno, _)| *lineno < hunk.left_start)
+                                 .map(|(_, line)| line.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn same(s: &str) -> Diff { Diff::Same(s.to_string()) }
+    fn add(s: &str) -> Diff { Diff::Add(s.to_string()) }
+    fn rem(s: &str) -> Diff { Diff::Remove(s.to_string()) }
+    fn rep(b: &str, a: &str) -> Diff { Diff::Replace(b.to_string(), a.to_string()) }
+
+    #[test]
+    fn hunks_without_context() {
+        let diffs = vec![same("a\nb"), add("c"), same("d")];
+        let hunks = hunks(&diffs, None, 0, (0, 0), (1, 1));
+        assert_eq!(1, hunks.len());
+        assert_eq!((1, 3, 1, 4), (hunks[0].left_start, hunks[0].left_len,
+                                  hunks[0].right_start, hunks[0].right_len));
+        assert_eq!(diffs, hunks[0].diffs);
+    }
+
+    #[test]
+    fn hunks_trim_leading_and_trailing_context() {
+        let diffs = vec![same("1\n2\n3\n4"), rem("5"), same("6\n7\n8\n9")];
+        let hunks = hunks(&diffs, Some(1), 0, (0, 0), (1, 1));
+        assert_eq!(vec![Hunk {
+            left_start: 4, left_len: 3, right_start: 4, right_len: 2,
+            diffs: vec![same("4"), rem("5"), same("6")], function: None,
+        }], hunks);
+        assert_eq!("@@ -4,3 +4,2 @@", hunks[0].header());
+    }
+
+    #[test]
+    fn hunks_split_on_long_unchanged_runs() {
+        let diffs = vec![add("a"), same("1\n2\n3\n4\n5"), add("b")];
+        let hunks = hunks(&diffs, Some(1), 0, (0, 0), (1, 1));
+        assert_eq!(2, hunks.len());
+        assert_eq!("@@ -1,1 +1,2 @@", hunks[0].header());
+        assert_eq!(vec![add("a"), same("1")], hunks[0].diffs);
+        assert_eq!("@@ -5,1 +6,2 @@", hunks[1].header());
+        assert_eq!(vec![same("5"), add("b")], hunks[1].diffs);
+    }
+
+    #[test]
+    fn hunks_keep_short_gaps_whole() {
+        let numbers = |count: usize| (1..=count).map(|i| i.to_string()).collect::<Vec<_>>().join("\n");
+        let diffs = vec![add("a"), same(&numbers(3)), add("b"), same(&numbers(30)), add("c")];
+        assert_eq!(3, hunks(&diffs, Some(1), 0, (0, 0), (1, 1)).len());
+        let collapsed = hunks(&diffs, Some(1), 5, (0, 0), (1, 1));
+        assert_eq!(2, collapsed.len());
+        assert_eq!(vec![add("a"), same(&numbers(3)), add("b"), same("1")], collapsed[0].diffs);
+        assert_eq!(vec![same("30"), add("c")], collapsed[1].diffs);
+        // The gaps before the first change and after the last are still cut.
+        let diffs = vec![same("1\n2\n3"), add("a"), same("1\n2\n3")];
+        assert_eq!(vec![same("3"), add("a"), same("1")],
+                   hunks(&diffs, Some(1), 5, (0, 0), (1, 1))[0].diffs);
+    }
+
+    #[test]
+    fn hunks_merge_nearby_changes() {
+        let diffs = vec![add("a"), same("1\n2\n3\n4"), add("b")];
+        let merged = hunks(&diffs, Some(2), 0, (0, 0), (1, 1));
+        assert_eq!(1, merged.len());
+        assert_eq!(diffs, merged[0].diffs);
+        assert_eq!("@@ -1,4 +1,6 @@", merged[0].header());
+        assert_eq!(2, hunks(&diffs, Some(1), 0, (0, 0), (1, 1)).len());
+        let diffs = vec![add("a"), same("1\n2\n3\n4\n5"), add("b")];
+        assert_eq!(2, hunks(&diffs, Some(2), 0, (0, 0), (1, 1)).len());
+    }
+
+    #[test]
+    fn hunks_identical() {
+        let diffs = vec![same("1\n2\n3")];
+        assert!(hunks(&diffs, Some(3), 0, (0, 0), (1, 1)).is_empty());
+    }
+
+    #[test]
+    fn hunks_offset_start() {
+        let diffs = vec![same("1\n2\n3"), add("a")];
+        let hunks = hunks(&diffs, Some(1), 0, (0, 0), (101, 201));
+        assert_eq!("@@ -103,1 +203,2 @@", hunks[0].header());
+    }
+
+    #[test]
+    fn hunks_function_context() {
+        let diffs = vec![same("fn a() {\n    1\n}\nfn b() {\n    2\n    3"), rem("    4"),
+                         same("}")];
+        let mut hunks = hunks(&diffs, Some(1), 0, (0, 0), (1, 1));
+        annotate_functions(&mut hunks, &diffs, (1, 1), &Regex::new("^fn").unwrap());
+        assert_eq!("@@ -6,3 +6,2 @@ fn b() {", hunks[0].header());
+    }
+
+    #[test]
+    fn hunks_keep_edges() {
+        let diffs = vec![same("1\n2\n3\n4\n5"), rem("6"), same("7\n8\n9\n10\n11")];
+        let hunks = hunks(&diffs, Some(1), 0, (2, 1), (1, 1));
+        assert_eq!(3, hunks.len());
+        assert_eq!(vec![same("1\n2")], hunks[0].diffs);
+        assert_eq!("@@ -5,3 +5,2 @@", hunks[1].header());
+        assert_eq!(vec![same("11")], hunks[2].diffs);
+        assert_eq!("@@ -11,1 +10,1 @@", hunks[2].header());
+    }
+
+    #[test]
+    fn hunks_zero_context() {
+        let diffs = vec![same("1\n2"), rep("3", "x"), same("4\n5\n6"), rem("7\n8"), same("9")];
+        let hunks = hunks(&diffs, Some(0), 0, (0, 0), (1, 1));
+        assert_eq!(vec![vec![rep("3", "x")], vec![rem("7\n8")]],
+                   hunks.iter().map(|hunk| hunk.diffs.clone()).collect::<Vec<Vec<Diff>>>());
+        assert_eq!("@@ -3,1 +3,1 @@", hunks[0].header());
+        assert_eq!("@@ -7,2 +6,0 @@", hunks[1].header());
+    }
+
+    #[test]
+    fn hunks_edges_merge_with_context() {
+        let diffs = vec![same("1\n2"), add("a"), same("3")];
+        let hunks = hunks(&diffs, Some(0), 0, (1, 1), (1, 1));
+        assert_eq!(vec![same("1"), add("a"), same("3")],
+                   hunks.iter().flat_map(|hunk| hunk.diffs.clone()).collect::<Vec<Diff>>());
+    }
+
+    #[test]
+    fn hunks_edges_of_identical_files() {
+        let diffs = vec![same("1\n2\n3\n4")];
+        let hunks = hunks(&diffs, Some(3), 0, (1, 1), (1, 1));
+        assert_eq!(vec![vec![same("1")], vec![same("4")]],
+                   hunks.into_iter().map(|hunk| hunk.diffs).collect::<Vec<Vec<Diff>>>());
+    }
+}
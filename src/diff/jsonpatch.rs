@@ -0,0 +1,157 @@
+use std::io::{self, Write};
+use serde_json::{json, Value};
+use super::{align_lines, calculate_char_diff, Diff, RenderOptions};
+
+// The [start, end) character offsets of the spans which changed within a pair
+// of replaced lines, on the old and new side.
+type Spans = Vec<(usize, usize)>;
+
+fn intraline_spans(before: &str, after: &str) -> (Spans, Spans) {
+    let mut spans_b = Vec::new();
+    let mut spans_a = Vec::new();
+    let (mut offset_b, mut offset_a) = (0, 0);
+    for char_change in calculate_char_diff(before, after) {
+        match char_change {
+            Diff::Same(same) => {
+                offset_b += same.chars().count();
+                offset_a += same.chars().count();
+            },
+            Diff::Add(add) => {
+                let len = add.chars().count();
+                spans_a.push((offset_a, offset_a + len));
+                offset_a += len;
+            },
+            Diff::Remove(rem) => {
+                let len = rem.chars().count();
+                spans_b.push((offset_b, offset_b + len));
+                offset_b += len;
+            },
+            Diff::Replace(rem, add) => {
+                let (len_b, len_a) = (rem.chars().count(), add.chars().count());
+                spans_b.push((offset_b, offset_b + len_b));
+                spans_a.push((offset_a, offset_a + len_a));
+                offset_b += len_b;
+                offset_a += len_a;
+            },
+            Diff::Ignored(_) | Diff::Unmatched(_) => {},
+        }
+    }
+    (spans_b, spans_a)
+}
+
+// Resolves the diffs into one operation per changed line, in the style of a
+// JSON Patch (RFC 6902) document. Removals and replacements are keyed by their
+// line in the left file, additions by their line in the right file, and
+// replacements are split into the lines they align as. With offsets, each
+// replacement also gives the [start, end) character offsets of the changes
+// within the old and new line. For example:
+//
+//   [{"op":"remove","line":2,"old":"b"},
+//    {"op":"replace","line":4,"new_line":3,"old":"d","value":"D"},
+//    {"op":"add","line":5,"value":"f"}]
+pub fn json_patch(diffs: &[Diff], opts: &RenderOptions) -> Value {
+    let mut ops = Vec::new();
+    let (mut lineno_l, mut lineno_r) = opts.start_line;
+    let remove = |ops: &mut Vec<Value>, lineno: usize, line: &str| {
+        ops.push(json!({"op": "remove", "line": lineno, "old": line}));
+    };
+    let add = |ops: &mut Vec<Value>, lineno: usize, line: &str| {
+        ops.push(json!({"op": "add", "line": lineno, "value": line}));
+    };
+    for change in diffs {
+        match change {
+            Diff::Same(_) | Diff::Ignored(_) | Diff::Unmatched(_) => {},
+            Diff::Add(add_lines) => {
+                for (offset, line) in add_lines.split('\n').enumerate() {
+                    add(&mut ops, lineno_r + offset, line);
+                }
+            },
+            Diff::Remove(rem_lines) => {
+                for (offset, line) in rem_lines.split('\n').enumerate() {
+                    remove(&mut ops, lineno_l + offset, line);
+                }
+            },
+            Diff::Replace(before, after) => {
+                let lines_b: Vec<&str> = before.split('\n').collect();
+                let lines_a: Vec<&str> = after.split('\n').collect();
+                let (mut line_l, mut line_r) = (lineno_l, lineno_r);
+                for aligned in align_lines(&lines_b, &lines_a, opts) {
+                    match aligned {
+                        (Some(before), Some(after)) => {
+                            let mut op = json!({"op": "replace", "line": line_l,
+                                                "new_line": line_r,
+                                                "old": before, "value": after});
+                            if opts.offsets {
+                                let (spans_b, spans_a) = intraline_spans(before, after);
+                                op["old_spans"] = json!(spans_b);
+                                op["new_spans"] = json!(spans_a);
+                            }
+                            ops.push(op);
+                            line_l += 1;
+                            line_r += 1;
+                        },
+                        (Some(before), None) => {
+                            remove(&mut ops, line_l, before);
+                            line_l += 1;
+                        },
+                        (None, Some(after)) => {
+                            add(&mut ops, line_r, after);
+                            line_r += 1;
+                        },
+                        (None, None) => {},
+                    }
+                }
+            },
+        }
+        let (count_l, count_r) = change.line_counts();
+        lineno_l += count_l;
+        lineno_r += count_r;
+    }
+    Value::Array(ops)
+}
+
+pub fn print_json_patch(diffs: &[Diff], opts: &RenderOptions) {
+    println!("{}", json_patch(diffs, opts));
+}
+
+pub fn write_json_patch<W: Write>(w: &mut W, diffs: &[Diff], opts: &RenderOptions)
+        -> io::Result<()> {
+    writeln!(w, "{}", json_patch(diffs, opts))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_patch_mixed() {
+        let diffs = vec![Diff::Same("a".to_string()),
+                         Diff::Remove("b".to_string()),
+                         Diff::Same("c".to_string()),
+                         Diff::Replace("d".to_string(), "D".to_string()),
+                         Diff::Same("e".to_string()),
+                         Diff::Add("f".to_string())];
+        assert_eq!(json!([
+            {"op": "remove", "line": 2, "old": "b"},
+            {"op": "replace", "line": 4, "new_line": 3, "old": "d", "value": "D"},
+            {"op": "add", "line": 5, "value": "f"},
+        ]), json_patch(&diffs, &RenderOptions::new()));
+    }
+
+    #[test]
+    fn json_patch_unaligned_replace() {
+        let diffs = vec![Diff::Replace("x".to_string(), "x\ny".to_string())];
+        assert_eq!(json!([
+            {"op": "replace", "line": 1, "new_line": 1, "old": "x", "value": "x"},
+            {"op": "add", "line": 2, "value": "y"},
+        ]), json_patch(&diffs, &RenderOptions::new()));
+    }
+
+    #[test]
+    fn json_patch_offsets() {
+        let diffs = vec![Diff::Replace("let x = 1;".to_string(), "let y = 10;".to_string())];
+        let patch = json_patch(&diffs, &RenderOptions::new().offsets(true));
+        assert_eq!(json!([[4, 5]]), patch[0]["old_spans"]);
+        assert_eq!(json!([[4, 5], [9, 10]]), patch[0]["new_spans"]);
+    }
+}
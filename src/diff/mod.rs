@@ -1,21 +1,77 @@
 mod align;
+mod columns;
+mod filter;
+mod hunk;
+mod jsonpatch;
+mod moves;
+mod options;
+mod stats;
+mod stream;
+mod syntax;
+mod unified;
+mod whitespace;
 mod wrap;
 
-use align::align;
+use std::cmp::{max, min};
+use std::collections::HashMap;
+use std::io::{self, Write};
+pub use align::{align, align_with_limits, align_with_threshold, dump_alignment};
+pub use hunk::Hunk;
+pub use jsonpatch::{json_patch, print_json_patch, write_json_patch};
+use hunk::{annotate_functions, hunks};
+pub use moves::{detect_moves, MovedBlock, MIN_MOVED_CHARS};
+pub use options::{Algorithm, ColorMoved, DiffOptions, Emphasis, Granularity, HighlightStyle,
+                  RenderOptions, Side, Tabs, Theme, DEFAULT_MAX_ALIGN_LINES,
+                  DEFAULT_MAX_LINE_LENGTH, DEFAULT_TAB_WIDTH};
+pub use stats::{diff_stats, diff_stats_with_options, diff_stats_with_threshold, numstat,
+                DiffStats};
+pub use stream::{StreamingDiff, DEFAULT_WINDOW};
+pub use unified::{is_unified_diff, parse_unified, strip_colors, PatchChunk, PatchChunks,
+                  PatchFile};
 use ansi_term::{ANSIString, ANSIStrings};
-use ansi_term::Color::{Red, Green, Black, Fixed};
+use ansi_term::Color::{Red, Green, Black, Blue, Cyan, Purple, Yellow, Fixed};
 use ansi_term::Style;
 use difference::{Changeset, Difference};
+use similar::DiffTag;
+use unicode_segmentation::UnicodeSegmentation;
 use itertools::EitherOrBoth;
 use itertools::Itertools;
-use wrap::{wrap_str, wrap_ansistrings};
+use wrap::{display_width, wrap_ansistrings};
 
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Diff {
     Same(String),
     Add(String),
     Remove(String),
     Replace(String, String),
+    // A change which has been deliberately ignored (e.g. because every changed
+    // line matched an ignore pattern). It is rendered as unchanged content,
+    // but keeps the original change so each side's lines stay accounted for.
+    Ignored(Box<Diff>),
+    // Lines left out of the diff because they didn't match the regex given to
+    // only diff matching lines. They're rendered as ignored changes are, but
+    // can be hidden altogether.
+    Unmatched(Box<Diff>),
+}
+
+impl Diff {
+    // The number of lines the change covers in the left and right files.
+    pub fn line_counts(&self) -> (usize, usize) {
+        let count = |text: &str| text.split('\n').count();
+        match self {
+            Diff::Same(same) => (count(same), count(same)),
+            Diff::Add(add) => (0, count(add)),
+            Diff::Remove(rem) => (count(rem), 0),
+            Diff::Replace(rem, add) => (count(rem), count(add)),
+            Diff::Ignored(ignored) | Diff::Unmatched(ignored) => ignored.line_counts(),
+        }
+    }
+
+    // Whether the lines were added, removed or replaced, rather than unchanged
+    // or ignored.
+    pub fn is_change(&self) -> bool {
+        !matches!(self, Diff::Same(_) | Diff::Ignored(_) | Diff::Unmatched(_))
+    }
 }
 
 struct DiffStyling {
@@ -26,207 +82,897 @@ struct DiffStyling {
     remove_highlight: Style,
 }
 
-pub fn calculate_line_diff(left: &str, right: &str) -> Vec<Diff> {
-    calculate_diff(left, right, "\n")
+impl DiffStyling {
+    fn plain() -> DiffStyling {
+        DiffStyling {
+            same:             Style::default(),
+            add:              Style::default(),
+            add_highlight:    Style::default(),
+            remove:           Style::default(),
+            remove_highlight: Style::default(),
+        }
+    }
+}
+
+// The note diff prints after the last line of a file which lacks a newline at
+// its end.
+pub const NO_NEWLINE_MARKER: &str = "\\ No newline at end of file";
+
+// Which of the two texts lacks a newline at its end, when just one of them does.
+pub fn missing_newline(left: &str, right: &str) -> Option<Side> {
+    let missing = |text: &str| !text.is_empty() && !text.ends_with('\n');
+    match (missing(left), missing(right)) {
+        (true, false) => Some(Side::Left),
+        (false, true) => Some(Side::Right),
+        _ => None,
+    }
+}
+
+// Diffs two texts by line. When just one of them lacks a newline at its end, its
+// last line differs from any line of the other, as it does for diff, so that the
+// difference shows.
+pub fn calculate_line_diff(left: &str, right: &str, opts: &DiffOptions) -> Vec<Diff> {
+    let diffs = calculate_lines_diff(&split_lines(left), &split_lines(right), opts);
+    match missing_newline(left, right) {
+        Some(side) => change_last_line(diffs, side),
+        None => diffs,
+    }
+}
+
+// Makes a change of the last line on the side, if it went unchanged. Anything
+// after it can only be lines from the other side, so it's left as it is.
+fn change_last_line(mut diffs: Vec<Diff>, side: Side) -> Vec<Diff> {
+    let on_side = |change: &Diff| {
+        let (count_l, count_r) = change.line_counts();
+        if side == Side::Left { count_l > 0 } else { count_r > 0 }
+    };
+    if let Some(index) = diffs.iter().rposition(on_side) {
+        if let Diff::Same(same) = &diffs[index] {
+            let (rest, last) = match same.rfind('\n') {
+                Some(split) => (Some(same[..split].to_string()), same[split + 1..].to_string()),
+                None => (None, same.clone()),
+            };
+            let changed = Diff::Replace(last.clone(), last);
+            diffs.splice(index..=index, rest.map(Diff::Same).into_iter().chain(Some(changed)));
+        }
+    }
+    diffs
+}
+
+// Diffs two files which have already been split into lines.
+pub fn calculate_lines_diff(lines_l: &[&str], lines_r: &[&str], opts: &DiffOptions) -> Vec<Diff> {
+    if let Some(regex) = &opts.only_matching_lines {
+        let (filtered_l, matched_l) = filter::matching_lines(lines_l, regex);
+        let (filtered_r, matched_r) = filter::matching_lines(lines_r, regex);
+        let opts = DiffOptions { only_matching_lines: None, ..opts.clone() };
+        let diffs = calculate_lines_diff(&filtered_l, &filtered_r, &opts);
+        return filter::weave(diffs, lines_l, &matched_l, lines_r, &matched_r);
+    }
+    let diffs = if opts.ignore_cr {
+        calculate_masked_diff(&strip_cr(lines_l), &strip_cr(lines_r), opts)
+    } else {
+        calculate_masked_diff(lines_l, lines_r, opts)
+    };
+    if !opts.ignore_blank_lines && opts.ignore_matching_lines.is_none() {
+        return diffs;
+    }
+    ignore_changes(diffs, |line| {
+        (opts.ignore_blank_lines && line.trim().is_empty())
+            || opts.ignore_matching_lines.as_ref().is_some_and(|regex| regex.is_match(line))
+    })
+}
+
+// Diffs the lines as they compare with the ignored columns cut out of them,
+// while keeping the whole lines in the diffs.
+fn calculate_masked_diff(lines_l: &[&str], lines_r: &[&str], opts: &DiffOptions) -> Vec<Diff> {
+    if opts.ignore_columns.is_empty() {
+        return calculate_diff(lines_l, lines_r, "\n", opts.algorithm, opts.minimal);
+    }
+    let mask = |lines: &[&str]| -> Vec<String> {
+        lines.iter().map(|line| columns::mask_columns(line, &opts.ignore_columns)).collect()
+    };
+    let (masked_l, masked_r) = (mask(lines_l), mask(lines_r));
+    let masked_l: Vec<&str> = masked_l.iter().map(String::as_str).collect();
+    let masked_r: Vec<&str> = masked_r.iter().map(String::as_str).collect();
+    let diffs = calculate_diff(&masked_l, &masked_r, "\n", opts.algorithm, opts.minimal);
+    columns::unmask(diffs, lines_l, lines_r)
+}
+
+fn strip_cr<'a>(lines: &[&'a str]) -> Vec<&'a str> {
+    lines.iter().map(|line| line.strip_suffix('\r').unwrap_or(line)).collect()
 }
 
+// Aligns the lines of a replacement as the options say to. Since alignment takes
+// time proportional to the product of the two sides' lengths, any replacement
+// longer than the limit is left unaligned, as a block of removed lines followed
+// by a block of added ones.
+fn align_lines<'a>(lines_b: &[&'a str], lines_a: &[&'a str], opts: &RenderOptions)
+        -> Vec<(Option<&'a str>, Option<&'a str>)> {
+    if opts.max_align_lines.is_some_and(|max_lines| lines_b.len() + lines_a.len() > max_lines) {
+        return lines_b.iter().map(|&line_b| (Some(line_b), None))
+                      .chain(lines_a.iter().map(|&line_a| (None, Some(line_a))))
+                      .collect();
+    }
+    align_with_limits(lines_b, lines_a, opts.align_threshold, opts.max_line_length)
+}
+
+// Whether either line is longer than the limit, and so too long to diff within.
+fn _exceeds_length(before: &str, after: &str, max_line_length: Option<usize>) -> bool {
+    max_line_length.is_some_and(|max| before.chars().count() > max || after.chars().count() > max)
+}
+
+// Splits the text into lines, where a trailing newline ends the last line
+// rather than starting an empty one. Empty text has no lines at all.
+pub fn split_lines(text: &str) -> Vec<&str> {
+    if text.is_empty() {
+        return Vec::new();
+    }
+    text.strip_suffix('\n').unwrap_or(text).split('\n').collect()
+}
+
+// Diffs two lines by grapheme cluster, so that a change never splits a visible
+// character (such as a letter from its combining accent).
 pub fn calculate_char_diff(left: &str, right: &str) -> Vec<Diff> {
-    calculate_diff(left, right, "")
+    let graphemes_l: Vec<&str> = left.graphemes(true).collect();
+    let graphemes_r: Vec<&str> = right.graphemes(true).collect();
+    calculate_diff(&graphemes_l, &graphemes_r, "", Algorithm::Lcs, false)
 }
 
-fn calculate_diff(left: &str, right: &str, split: &str) -> Vec<Diff> {
-    let mut changeset = Changeset::new(left, right, split);
-    let mut diffs = Vec::new();
-    let mut previous: Option<Difference> = None;
+// Diffs two lines by word, so that every changed span is made of whole words
+// (along with the spaces and punctuation between them, which are tokens too).
+pub fn calculate_word_diff(left: &str, right: &str) -> Vec<Diff> {
+    let words_l: Vec<&str> = left.split_word_bounds().collect();
+    let words_r: Vec<&str> = right.split_word_bounds().collect();
+    calculate_diff(&words_l, &words_r, "", Algorithm::Lcs, false)
+}
 
-    for change in changeset.diffs.drain(..) {
-        match change {
-            Difference::Same(same) => {
-                match previous {
-                    Some(last_change) => {
-                        diffs.push(match last_change {
-                            Difference::Same(_) => panic!("Invalid state"),
-                            Difference::Add(add) => Diff::Add(add),
-                            Difference::Rem(rem) => Diff::Remove(rem),
-                        });
-                        previous = None;
-                    },
-                    None => {},
-                }
-                diffs.push(Diff::Same(same));
-            },
-            Difference::Add(add) => {
-                match previous {
-                    Some(last_change) => {
-                        diffs.push(match last_change {
-                            Difference::Same(_) => panic!("Invalid state"),
-                            Difference::Add(_) => panic!("Invalid state"),
-                            Difference::Rem(rem) => Diff::Replace(rem, add),
-                        });
-                        previous = None;
-                    },
-                    None => {
-                        previous = Some(Difference::Add(add));
-                    },
-                }
+// Diffs two lines at the granularity their changes are highlighted at.
+fn calculate_intraline_diff(left: &str, right: &str, granularity: Granularity) -> Vec<Diff> {
+    match granularity {
+        Granularity::Char => calculate_char_diff(left, right),
+        Granularity::Word => calculate_word_diff(left, right),
+    }
+}
+
+// The start of the Supplementary Private Use Areas, which have room for over
+// 130000 distinct tokens per diff.
+const PRIVATE_USE_START: u32 = 0xF0000;
+
+// Diffs two sequences of tokens (lines or graphemes), joining runs of tokens
+// back together with `join`.
+fn calculate_diff(tokens_l: &[&str], tokens_r: &[&str], join: &str, algorithm: Algorithm,
+                  minimal: bool) -> Vec<Diff> {
+    let differences = match algorithm {
+        Algorithm::Lcs => lcs_differences(tokens_l, tokens_r, join),
+        Algorithm::Myers => similar_differences(similar::Algorithm::Myers, tokens_l, tokens_r, join),
+        Algorithm::Patience => {
+            similar_differences(similar::Algorithm::Patience, tokens_l, tokens_r, join)
+        },
+    };
+    let diffs = coalesce_differences(differences, join);
+    if minimal {
+        minimize_diffs(diffs, join)
+    } else {
+        diffs
+    }
+}
+
+// Changeset can only diff strings split by a separator, which can't tell no
+// tokens from a single empty one, so each distinct token is stood in for by a
+// private use char and the chars diffed. If there are too many distinct tokens
+// to stand in for, the joined strings are split directly instead.
+fn lcs_differences(tokens_l: &[&str], tokens_r: &[&str], join: &str) -> Vec<Difference> {
+    let mut codes = HashMap::new();
+    let mut tokens = Vec::new();
+    for &token in tokens_l.iter().chain(tokens_r) {
+        if !codes.contains_key(token) {
+            match std::char::from_u32(PRIVATE_USE_START + tokens.len() as u32) {
+                Some(code) => codes.insert(token, code),
+                None => return split_differences(tokens_l, tokens_r, join),
+            };
+            tokens.push(token);
+        }
+    }
+    let encode = |line: &[&str]| -> String { line.iter().map(|token| codes[token]).collect() };
+    let decode = |encoded: String| -> String {
+        encoded.chars()
+               .map(|code| tokens[(code as u32 - PRIVATE_USE_START) as usize])
+               .collect::<Vec<&str>>()
+               .join(join)
+    };
+    let changeset = Changeset::new(&encode(tokens_l), &encode(tokens_r), "");
+    changeset.diffs.into_iter().map(|change| match change {
+        Difference::Same(same) => Difference::Same(decode(same)),
+        Difference::Add(add) => Difference::Add(decode(add)),
+        Difference::Rem(rem) => Difference::Rem(decode(rem)),
+    }).collect()
+}
+
+// A token which can't start a line of text, put in front of both sides by
+// split_differences.
+const SPLIT_SENTINEL: &str = "\u{0}";
+
+// Diffs the joined tokens by splitting them with Changeset. An empty string
+// splits into no tokens at all, so a side made of a single empty token (a file
+// of one blank line) would vanish, leaving the blank line out of the diff or
+// pairing it with a change on the other side. To keep it, both sides are
+// started with a sentinel token, which is stripped from the shared first chunk.
+fn split_differences(tokens_l: &[&str], tokens_r: &[&str], join: &str) -> Vec<Difference> {
+    let sentinel_l = format!("{}{}{}", SPLIT_SENTINEL, if tokens_l.is_empty() { "" } else { join },
+                             tokens_l.join(join));
+    let sentinel_r = format!("{}{}{}", SPLIT_SENTINEL, if tokens_r.is_empty() { "" } else { join },
+                             tokens_r.join(join));
+    let mut differences = Changeset::new(&sentinel_l, &sentinel_r, join).diffs;
+    if let Some(Difference::Same(first)) = differences.first() {
+        let rest = first[SPLIT_SENTINEL.len()..].strip_prefix(join).unwrap_or("").to_string();
+        if rest.is_empty() {
+            differences.remove(0);
+        } else {
+            differences[0] = Difference::Same(rest);
+        }
+    }
+    differences
+}
+
+// Runs one of similar's algorithms over the tokens, in the form Changeset gives
+// so that every algorithm's results are coalesced the same way.
+fn similar_differences(algorithm: similar::Algorithm, tokens_l: &[&str], tokens_r: &[&str],
+                       join: &str) -> Vec<Difference> {
+    let mut differences = Vec::new();
+    for op in similar::capture_diff_slices(algorithm, tokens_l, tokens_r) {
+        let (tag, range_l, range_r) = op.as_tag_tuple();
+        let removed = tokens_l[range_l].join(join);
+        let added = tokens_r[range_r].join(join);
+        match tag {
+            DiffTag::Equal => differences.push(Difference::Same(removed)),
+            DiffTag::Delete => differences.push(Difference::Rem(removed)),
+            DiffTag::Insert => differences.push(Difference::Add(added)),
+            DiffTag::Replace => {
+                differences.push(Difference::Rem(removed));
+                differences.push(Difference::Add(added));
             },
-            Difference::Rem(rem) => {
-                match previous {
-                    Some(last_change) => {
-                        diffs.push(match last_change {
-                            Difference::Same(_) => panic!("Invalid state"),
-                            Difference::Add(add) => Diff::Replace(rem, add),
-                            Difference::Rem(_) => panic!("Invalid state"),
-                        });
-                        previous = None;
-                    },
-                    None => {
-                        previous = Some(Difference::Rem(rem));
-                    },
-                }
-            }
         }
     }
-    match previous {
-        Some(uncommitted) => {
-            diffs.push(match uncommitted {
-                Difference::Same(_) => panic!("Invalid state"),
-                Difference::Add(add) => Diff::Add(add),
-                Difference::Rem(rem) => Diff::Remove(rem),
-            });
-        },
-        None => {},
+    differences
+}
+
+// Converts the changeset into diffs, pairing up adjacent removals and additions
+// into replacements. Consecutive differences of the same kind are merged rather
+// than assumed never to occur.
+fn coalesce_differences(differences: Vec<Difference>, split: &str) -> Vec<Diff> {
+    let mut diffs: Vec<Diff> = Vec::new();
+    for change in differences {
+        let merged = match (diffs.pop(), change) {
+            (Some(Diff::Same(same)), Difference::Same(more)) => {
+                Diff::Same(same + split + &more)
+            },
+            (Some(Diff::Add(add)), Difference::Add(more)) => {
+                Diff::Add(add + split + &more)
+            },
+            (Some(Diff::Remove(rem)), Difference::Rem(more)) => {
+                Diff::Remove(rem + split + &more)
+            },
+            (Some(Diff::Remove(rem)), Difference::Add(add))
+            | (Some(Diff::Add(add)), Difference::Rem(rem)) => Diff::Replace(rem, add),
+            (Some(Diff::Replace(before, after)), Difference::Add(more)) => {
+                Diff::Replace(before, after + split + &more)
+            },
+            (Some(Diff::Replace(before, after)), Difference::Rem(more)) => {
+                Diff::Replace(before + split + &more, after)
+            },
+            (last, change) => {
+                if let Some(last) = last {
+                    diffs.push(last);
+                }
+                match change {
+                    Difference::Same(same) => Diff::Same(same),
+                    Difference::Add(add) => Diff::Add(add),
+                    Difference::Rem(rem) => Diff::Remove(rem),
+                }
+            },
+        };
+        diffs.push(merged);
     }
     diffs
 }
 
-pub fn print_diffs(diffs: &Vec<Diff>, context: usize, color: bool) {
-    let margin_styling = DiffStyling {
-        same:             Style::default(),
-        add:              Style::default(),
-        add_highlight:    Style::default(),
-        remove:           Style::default(),
-        remove_highlight: Style::default(),
-    };
-    let line_styling = DiffStyling {
-        same:             Style::default(),
-        add:              Green.normal(),
-        add_highlight:    Black.on(Green),
-        remove:           Red.normal(),
-        remove_highlight: Black.on(Red),
-    };
+// Strips a carriage return from the end of every line so that CRLF and LF
+// files compare (and display) identically.
+pub fn strip_cr_at_eol(text: &str) -> String {
+    text.split('\n')
+        .map(|line| line.strip_suffix('\r').unwrap_or(line))
+        .collect::<Vec<&str>>()
+        .join("\n")
+}
+
+pub fn uses_crlf(text: &str) -> bool {
+    text.contains("\r\n")
+}
+
+// Takes the 1-based, inclusive range of lines from the text, clamped to the
+// lines it has. Returns the lines along with the range actually taken, which
+// is empty (ending before it starts) if the range lies past the end.
+pub fn slice_lines(text: &str, first: usize, last: usize) -> (String, (usize, usize)) {
+    let lines = split_lines(text);
+    let first = first.clamp(1, lines.len() + 1);
+    let last = last.min(lines.len());
+    if last < first {
+        return (String::new(), (first, first - 1));
+    }
+    (lines[first - 1..last].join("\n"), (first, last))
+}
+
+pub fn has_changes(diffs: &[Diff]) -> bool {
+    diffs.iter().any(Diff::is_change)
+}
+
+// The number of changed regions, not counting ignored ones.
+pub fn count_changes(diffs: &[Diff]) -> usize {
+    diffs.iter().filter(|change| change.is_change()).count()
+}
+
+fn split_tokens<'a>(s: &'a str, split: &str) -> Vec<&'a str> {
+    if split.is_empty() {
+        s.char_indices().map(|(i, c)| &s[i..i + c.len_utf8()]).collect()
+    } else {
+        s.split(split).collect()
+    }
+}
+
+fn push_same(diffs: &mut Vec<Diff>, same: String, split: &str) {
+    if let Some(Diff::Same(previous)) = diffs.last_mut() {
+        previous.push_str(split);
+        previous.push_str(&same);
+    } else {
+        diffs.push(Diff::Same(same));
+    }
+}
+
+fn flush_change(diffs: &mut Vec<Diff>, removed: &mut Vec<String>,
+                added: &mut Vec<String>, split: &str) {
+    // Any tokens common to the start or end of both sides are not really part
+    // of the change, so move them out into the surrounding Same blocks.
+    let prefix_len = removed.iter().zip(added.iter())
+                            .take_while(|(r, a)| r == a).count();
+    let suffix_len = removed[prefix_len..].iter().rev()
+                            .zip(added[prefix_len..].iter().rev())
+                            .take_while(|(r, a)| r == a).count();
+    if prefix_len > 0 {
+        push_same(diffs, added[..prefix_len].join(split), split);
+    }
+    let rem = &removed[prefix_len..removed.len() - suffix_len];
+    let add = &added[prefix_len..added.len() - suffix_len];
+    match (rem.is_empty(), add.is_empty()) {
+        (false, false) => diffs.push(Diff::Replace(rem.join(split), add.join(split))),
+        (false, true)  => diffs.push(Diff::Remove(rem.join(split))),
+        (true, false)  => diffs.push(Diff::Add(add.join(split))),
+        (true, true)   => {},
+    }
+    if suffix_len > 0 {
+        push_same(diffs, added[added.len() - suffix_len..].join(split), split);
+    }
+    removed.clear();
+    added.clear();
+}
 
+/// Merges runs of adjacent changes into a single change and trims any common
+/// leading or trailing tokens out of each change into the neighbouring Same
+/// blocks, so every change covers as few tokens as possible.
+fn minimize_diffs(diffs: Vec<Diff>, split: &str) -> Vec<Diff> {
+    let mut minimized = Vec::with_capacity(diffs.len());
+    let mut removed = Vec::new();
+    let mut added = Vec::new();
     for change in diffs {
         match change {
             Diff::Same(same) => {
-                for line in same.split('\n') {
-                    let margin = margin_styling.same.paint("  ");
-                    let fmt = line_styling.same.paint(line);
-                    println!("{}{}", margin, fmt);
-                }
+                flush_change(&mut minimized, &mut removed, &mut added, split);
+                push_same(&mut minimized, same, split);
             },
             Diff::Add(add) => {
-                for line in add.split('\n') {
-                    let margin = margin_styling.add.paint("+ ");
-                    let fmt = line_styling.add.paint(line);
-                    println!("{}{}", margin, fmt);
-                }
+                added.extend(split_tokens(&add, split).into_iter().map(String::from));
             },
             Diff::Remove(rem) => {
-                for line in rem.split('\n') {
-                    let margin = margin_styling.remove.paint("- ");
-                    let fmt = line_styling.remove.paint(line);
-                    println!("{}{}", margin, fmt);
-                }
+                removed.extend(split_tokens(&rem, split).into_iter().map(String::from));
             },
-            Diff::Replace(before, after) => {
-                let lines_b = before.split('\n').collect();
-                let lines_a = after.split('\n').collect();
-                let alignment = align(&lines_b, &lines_a);
-                let mut fmts_b = Vec::new();
-                let mut fmts_a = Vec::new();
-                for aligned in alignment {
-                    match aligned {
-                        (Some(before), None) => {
-                            fmts_b.push(margin_styling.remove_highlight.paint("- "));
-                            fmts_b.push(line_styling.remove_highlight.paint(before));
-                            fmts_b.push(Style::default().paint("\n"));
-                        },
-                        (None, Some(after)) => {
-                            fmts_a.push(margin_styling.add_highlight.paint("+ "));
-                            fmts_a.push(line_styling.add_highlight.paint(after));
-                            fmts_a.push(Style::default().paint("\n"));
-                        },
-                        (Some(before), Some(after)) => {
-                            fmts_b.push(margin_styling.remove.paint("- "));
-                            fmts_a.push(margin_styling.add.paint("+ "));
-                            _style_diff_line(before, after, &line_styling,
-                                             &mut fmts_b, &mut fmts_a);
-                            fmts_b.push(Style::default().paint("\n"));
-                            fmts_a.push(Style::default().paint("\n"));
-                        },
-                        (None, None) => {},
-                    }
-                }
-                print!("{}", ANSIStrings(&fmts_b));
-                print!("{}", ANSIStrings(&fmts_a));
+            Diff::Replace(rem, add) => {
+                removed.extend(split_tokens(&rem, split).into_iter().map(String::from));
+                added.extend(split_tokens(&add, split).into_iter().map(String::from));
+            },
+            Diff::Ignored(_) | Diff::Unmatched(_) => {
+                flush_change(&mut minimized, &mut removed, &mut added, split);
+                minimized.push(change);
             },
         }
     }
+    flush_change(&mut minimized, &mut removed, &mut added, split);
+    minimized
+}
+
+// Ignores any change in which every changed line is ignorable, in the manner of
+// diff's -B and -I options.
+fn ignore_changes<F: Fn(&str) -> bool>(diffs: Vec<Diff>, ignorable: F) -> Vec<Diff> {
+    let all_match = |text: &str| text.split('\n').all(&ignorable);
+    diffs.into_iter().map(|change| {
+        let ignore = match &change {
+            Diff::Add(add) => all_match(add),
+            Diff::Remove(rem) => all_match(rem),
+            Diff::Replace(rem, add) => all_match(rem) && all_match(add),
+            Diff::Same(_) | Diff::Ignored(_) | Diff::Unmatched(_) => false,
+        };
+        if ignore {
+            Diff::Ignored(Box::new(change))
+        } else {
+            change
+        }
+    }).collect()
+}
+
+// Cuts the diffs off before the change after the first `max_changes`, returning
+// whether any were cut off.
+fn _limit_changes(diffs: &[Diff], max_changes: Option<usize>) -> (&[Diff], bool) {
+    let max_changes = match max_changes {
+        Some(max_changes) => max_changes,
+        None => return (diffs, false),
+    };
+    let mut changes = 0;
+    for (i, change) in diffs.iter().enumerate() {
+        if change.is_change() {
+            if changes == max_changes {
+                return (&diffs[..i], true);
+            }
+            changes += 1;
+        }
+    }
+    (diffs, false)
+}
+
+// Notes that a file lacks a newline at its end, unless its last line went unshown
+// (its change omitted, or its side not shown).
+fn _write_missing_newline(w: &mut dyn Write, omitted: bool, opts: &RenderOptions)
+        -> io::Result<()> {
+    let shown = opts.side.is_none() || opts.side == opts.missing_newline;
+    if opts.missing_newline.is_some() && shown && !omitted {
+        writeln!(w, "{}", NO_NEWLINE_MARKER)?;
+    }
+    Ok(())
+}
+
+fn _write_omitted(w: &mut dyn Write, omitted: bool) -> io::Result<()> {
+    if omitted {
+        writeln!(w, "(\u{2026} more changes omitted)")?;
+    }
+    Ok(())
+}
+
+fn _write_header(w: &mut dyn Write, left: &str, right: &str, opts: &RenderOptions)
+        -> io::Result<()> {
+    let header_styling = if opts.color { Style::new().bold() } else { Style::default() };
+    writeln!(w, "{}", header_styling.paint(format!("--- {}", left)))?;
+    writeln!(w, "{}", header_styling.paint(format!("+++ {}", right)))
+}
+
+pub fn print_patch_header(lines: &[String], opts: &RenderOptions) {
+    _print_to_stdout(|w| write_patch_header(w, lines, opts));
+}
+
+// Writes the lines introducing a file in an existing patch, as git colors
+// them: the commit line in yellow and the lines describing the file (diff,
+// index, mode, rename and so on) in bold. Anything else, such as a commit's
+// message, is written as it is.
+pub fn write_patch_header<W: Write>(w: &mut W, lines: &[String], opts: &RenderOptions)
+        -> io::Result<()> {
+    const META: [&str; 14] = ["diff ", "index ", "--- ", "+++ ", "old mode ", "new mode ",
+                              "deleted file mode ", "new file mode ", "similarity index ",
+                              "dissimilarity index ", "rename ", "copy ", "Binary files ",
+                              "GIT binary patch"];
+    let commit_styling = match opts.theme {
+        _ if !opts.color => Style::default(),
+        Theme::Mono => Style::new().bold(),
+        Theme::Dark | Theme::Light => Yellow.normal(),
+    };
+    let meta_styling = if opts.color { Style::new().bold() } else { Style::default() };
+    for line in lines {
+        let styling = if line.starts_with("commit ") {
+            commit_styling
+        } else if META.iter().any(|meta| line.starts_with(meta)) {
+            meta_styling
+        } else {
+            Style::default()
+        };
+        writeln!(w, "{}", styling.paint(line.as_str()))?;
+    }
+    Ok(())
+}
+
+fn _make_hunks(diffs: &[Diff], opts: &RenderOptions) -> Vec<Hunk> {
+    let mut hunks = hunks(diffs, opts.context, opts.collapse_over, opts.edges, opts.start_line);
+    if let Some(regex) = &opts.function_context {
+        annotate_functions(&mut hunks, diffs, opts.start_line, regex);
+    }
+    // The ending markers are only added once the functions have been found, so
+    // as not to get in the way of the regex.
+    if opts.show_line_endings {
+        for hunk in &mut hunks {
+            hunk.diffs = hunk.diffs.iter().map(_mark_line_endings).collect();
+        }
+    }
+    hunks
+}
+
+fn _mark_line_endings(change: &Diff) -> Diff {
+    let mark = whitespace::mark_line_endings;
+    match change {
+        Diff::Same(same) => Diff::Same(mark(same)),
+        Diff::Add(add) => Diff::Add(mark(add)),
+        Diff::Remove(rem) => Diff::Remove(mark(rem)),
+        Diff::Replace(before, after) => Diff::Replace(mark(before), mark(after)),
+        Diff::Ignored(ignored) => Diff::Ignored(Box::new(_mark_line_endings(ignored))),
+        Diff::Unmatched(unmatched) => Diff::Unmatched(Box::new(_mark_line_endings(unmatched))),
+    }
+}
+
+// Prints to stdout, failing just as println! does.
+fn _print_to_stdout<F: FnOnce(&mut io::StdoutLock) -> io::Result<()>>(write: F) {
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    if let Err(error) = write(&mut stdout) {
+        panic!("failed printing to stdout: {}", error);
+    }
+}
+
+pub fn print_diffs(diffs: &[Diff], opts: &RenderOptions) {
+    _print_to_stdout(|w| write_diffs(w, diffs, opts));
+}
+
+pub fn write_diffs<W: Write>(w: &mut W, diffs: &[Diff], opts: &RenderOptions) -> io::Result<()> {
+    if let Some((left, right)) = &opts.header {
+        if has_changes(diffs) {
+            _write_header(w, left, right, opts)?;
+        }
+    }
+    let (diffs, omitted) = _limit_changes(diffs, opts.max_changes);
+    _write_hunks(w, &_make_hunks(diffs, opts), opts.context.is_some(), opts)?;
+    _write_missing_newline(w, omitted, opts)?;
+    _write_omitted(w, omitted)
+}
+
+pub fn print_moves(moves: &[MovedBlock], opts: &RenderOptions) {
+    _print_to_stdout(|w| write_moves(w, moves, opts));
+}
+
+// Writes just the moved blocks, each headed by the lines it was moved from and
+// to in the style of a hunk's range.
+pub fn write_moves<W: Write>(w: &mut W, moves: &[MovedBlock], opts: &RenderOptions)
+        -> io::Result<()> {
+    if let (Some((left, right)), false) = (&opts.header, moves.is_empty()) {
+        _write_header(w, left, right, opts)?;
+    }
+    let hunk_styling = _hunk_styling(opts);
+    let (moved_styling, alternate_styling) = _moved_styling(opts);
+    let (_, _, same_margin, _) = _margins(opts);
+    for (i, moved) in moves.iter().enumerate() {
+        let moved_styling = if i % 2 == 1 { alternate_styling } else { moved_styling };
+        let len = moved.lines.len();
+        writeln!(w, "{}", hunk_styling.paint(format!("@@ -{},{} +{},{} @@ moved",
+                                                     moved.left_start, len, moved.right_start, len)))?;
+        for line in &moved.lines {
+            _write_line(w, Style::default().paint(same_margin.as_str()),
+                        &[moved_styling.paint(line.as_str())], opts)?;
+        }
+    }
+    Ok(())
+}
+
+pub fn print_hunks(hunks: &[Hunk], opts: &RenderOptions) {
+    _print_to_stdout(|w| write_hunks(w, hunks, opts));
+}
+
+pub fn write_hunks<W: Write>(w: &mut W, hunks: &[Hunk], opts: &RenderOptions) -> io::Result<()> {
+    _write_hunks(w, hunks, true, opts)
+}
+
+fn _write_hunks(w: &mut dyn Write, hunks: &[Hunk], headers: bool, opts: &RenderOptions)
+        -> io::Result<()> {
+    let margin_styling = DiffStyling::plain();
+    let line_styling = _unified_styling(opts);
+    let hunk_styling = _hunk_styling(opts);
+    let (add_margin, remove_margin, same_margin, inline_margin) = _margins(opts);
+
+    for hunk in hunks {
+        if headers {
+            writeln!(w, "{}", hunk_styling.paint(_hunk_marker(hunk, opts)))?;
+        }
+        for change in &hunk.diffs {
+            match change {
+                // Showing one side only shows its changes.
+                Diff::Same(_) | Diff::Ignored(_) | Diff::Unmatched(_) if opts.side.is_some() => {},
+                Diff::Add(_) if opts.side == Some(Side::Left) => {},
+                Diff::Remove(_) if opts.side == Some(Side::Right) => {},
+                Diff::Same(same) => {
+                    for line in _style_same_lines(same, &line_styling, opts) {
+                        _write_line(w, margin_styling.same.paint(same_margin.as_str()),
+                                    &line, opts)?;
+                    }
+                },
+                Diff::Add(add) => {
+                    for line in add.split('\n') {
+                        _write_line(w, margin_styling.add.paint(add_margin.as_str()),
+                                    &[line_styling.add.paint(line)], opts)?;
+                    }
+                },
+                Diff::Remove(rem) => {
+                    for line in rem.split('\n') {
+                        _write_line(w, margin_styling.remove.paint(remove_margin.as_str()),
+                                    &[line_styling.remove.paint(line)], opts)?;
+                    }
+                },
+                Diff::Replace(before, after) if opts.inline && opts.side.is_none()
+                        && !before.contains('\n') && !after.contains('\n')
+                        && !_exceeds_length(before, after, opts.max_line_length) => {
+                    _write_line(w, margin_styling.same.paint(inline_margin.as_str()),
+                                &_style_inline_line(before, after, &line_styling, opts.color,
+                                                   opts.granularity),
+                                opts)?;
+                },
+                Diff::Replace(before, after) if !opts.intraline => {
+                    // Without intraline highlighting there is no need to pair
+                    // up the lines, so just print the lines either side.
+                    if opts.side != Some(Side::Right) {
+                        for line in before.split('\n') {
+                            _write_line(w, margin_styling.remove.paint(remove_margin.as_str()),
+                                        &[line_styling.remove.paint(line)], opts)?;
+                        }
+                    }
+                    if opts.side != Some(Side::Left) {
+                        for line in after.split('\n') {
+                            _write_line(w, margin_styling.add.paint(add_margin.as_str()),
+                                        &[line_styling.add.paint(line)], opts)?;
+                        }
+                    }
+                },
+                Diff::Replace(before, after) => {
+                    let lines_b: Vec<&str> = before.split('\n').collect();
+                    let lines_a: Vec<&str> = after.split('\n').collect();
+                    let alignment = align_lines(&lines_b, &lines_a, opts);
+                    let mut fmts_b = Vec::new();
+                    let mut fmts_a = Vec::new();
+                    for aligned in alignment {
+                        match aligned {
+                            (Some(before), None) => {
+                                fmts_b.push((margin_styling.remove_highlight.paint(remove_margin.as_str()),
+                                             vec![line_styling.remove_highlight.paint(before)]));
+                            },
+                            (None, Some(after)) => {
+                                fmts_a.push((margin_styling.add_highlight.paint(add_margin.as_str()),
+                                             vec![line_styling.add_highlight.paint(after)]));
+                            },
+                            (Some(before), Some(after)) => {
+                                let mut fmt_b = Vec::new();
+                                let mut fmt_a = Vec::new();
+                                _style_diff_line(before, after, &line_styling, opts, &mut fmt_b,
+                                                 &mut fmt_a);
+                                fmts_b.push((margin_styling.remove.paint(remove_margin.as_str()), fmt_b));
+                                fmts_a.push((margin_styling.add.paint(add_margin.as_str()), fmt_a));
+                            },
+                            (None, None) => {},
+                        }
+                    }
+                    match opts.side {
+                        Some(Side::Left) => fmts_a.clear(),
+                        Some(Side::Right) => fmts_b.clear(),
+                        None => {},
+                    }
+                    for (margin, fmt) in fmts_b.into_iter().chain(fmts_a) {
+                        _write_line(w, margin, &fmt, opts)?;
+                    }
+                },
+                Diff::Unmatched(_) if opts.hide_unmatched => {},
+                Diff::Ignored(ignored) | Diff::Unmatched(ignored) => {
+                    // Show the ignored change as it now stands in the right file.
+                    let after = match ignored.as_ref() {
+                        Diff::Add(add) | Diff::Replace(_, add) => add,
+                        _ => continue,
+                    };
+                    for line in after.split('\n') {
+                        _write_line(w, margin_styling.same.paint(same_margin.as_str()),
+                                    &[line_styling.same.paint(line)], opts)?;
+                    }
+                },
+            }
+        }
+    }
+    Ok(())
+}
+
+// The margins of added, removed, unchanged and inline lines, each padded to the
+// widest so that the lines after them line up. When annotating, each is
+// preceded by the tag of the file its line came from, with inline lines (which
+// show both files) tagged as unchanged ones are.
+fn _margins(opts: &RenderOptions) -> (String, String, String, String) {
+    let markers = [opts.marker_add.as_str(), opts.marker_remove.as_str(),
+                   opts.marker_same.as_str(), "~"];
+    let width = markers.iter().map(|marker| marker.chars().count()).max().unwrap_or(0);
+    let tags = if opts.annotate {
+        [opts.tag_right.as_str(), opts.tag_left.as_str(), opts.tag_same.as_str(),
+         opts.tag_same.as_str()]
+    } else {
+        [""; 4]
+    };
+    let tag_width = tags.iter().map(|tag| tag.chars().count()).max().unwrap_or(0);
+    let pad = |i: usize| {
+        if opts.annotate {
+            format!("{:tw$} {:w$} ", tags[i], markers[i], tw=tag_width, w=width)
+        } else {
+            format!("{:w$} ", markers[i], w=width)
+        }
+    };
+    (pad(0), pad(1), pad(2), pad(3))
+}
+
+// Styles each of the unchanged lines, syntax highlighting them if asked to.
+fn _style_same_lines<'a>(same: &'a str, styling: &DiffStyling, opts: &RenderOptions)
+        -> Vec<Vec<ANSIString<'a>>> {
+    let lines: Vec<&str> = same.split('\n').collect();
+    if let (true, Some(language)) = (opts.color, &opts.syntax) {
+        if let Some(highlighted) = syntax::highlight(&lines, language) {
+            return highlighted;
+        }
+    }
+    lines.into_iter().map(|line| vec![styling.same.paint(line)]).collect()
 }
 
-fn calc_max_line_width(diffs: &Vec<Diff>) -> (usize, usize){
+fn _write_line(w: &mut dyn Write, margin: ANSIString, line: &[ANSIString], opts: &RenderOptions)
+        -> io::Result<()> {
+    if opts.show_whitespace {
+        writeln!(w, "{}{}", margin, ANSIStrings(&whitespace::show_whitespace(line)))
+    } else if opts.tabs == Some(Tabs::Expand) {
+        writeln!(w, "{}{}", margin, ANSIStrings(&whitespace::expand_tabs(line, opts.tab_width, false)))
+    } else {
+        writeln!(w, "{}{}", margin, ANSIStrings(line))
+    }
+}
+
+// The widest line on each side, measured in the columns it will take up when
+// printed.
+fn calc_max_line_width(diffs: &[Diff], opts: &RenderOptions) -> (usize, usize) {
+    // Shown whitespace draws each tab as a single marker.
+    let tab_width = if opts.show_whitespace { 1 } else { opts.tab_width };
+    let widest = |text: &str| {
+        text.split('\n').map(|line| display_width(line, tab_width)).max().unwrap_or(0)
+    };
     let mut max_width = (0, 0);
     for change in diffs {
         match change {
             Diff::Same(same) => {
-                let len = same.split('\n').map(|l| l.chars().count()).max().unwrap_or(0);
-                if len > max_width.0 {
-                    max_width.0 = len;
-                }
-                if len > max_width.1 {
-                    max_width.1 = len;
-                }
+                let len = widest(same);
+                max_width = (max(max_width.0, len), max(max_width.1, len));
             }
             Diff::Add(add) => {
-                let len = add.split('\n').map(|l| l.chars().count()).max().unwrap_or(0);
-                if len > max_width.0 {
-                    max_width.0 = len;
-                }
+                max_width.1 = max(max_width.1, widest(add));
             }
             Diff::Remove(rem) => {
-                let len = rem.split('\n').map(|l| l.chars().count()).max().unwrap_or(0);
-                if len > max_width.1 {
-                    max_width.1 = len;
-                }
+                max_width.0 = max(max_width.0, widest(rem));
             }
             Diff::Replace(before, after) => {
-                let len = before.split('\n').map(|l| l.chars().count()).max().unwrap_or(0);
-                if len > max_width.0 {
-                    max_width.0 = len;
-                }
-                let len =  after.split('\n').map(|l| l.chars().count()).max().unwrap_or(0);
-                if len > max_width.1 {
-                    max_width.1 = len;
-                }
+                max_width = (max(max_width.0, widest(before)), max(max_width.1, widest(after)));
+            }
+            Diff::Ignored(ignored) | Diff::Unmatched(ignored) => {
+                let len = calc_max_line_width(std::slice::from_ref(ignored.as_ref()), opts);
+                max_width = (max(max_width.0, len.0), max(max_width.1, len.1));
             }
         }
     }
-    return max_width;
+    max_width
+}
+
+fn calc_lineno_width(max_line_count: usize) -> usize {
+    // The digits are counted exactly, as a float's logarithm can land either
+    // side of a power of ten.
+    match max_line_count.checked_ilog10() {
+        Some(log) => log as usize + 1,
+        // An empty file has no lines to number, but leave space for a single
+        // digit anyway so the margins stay consistent.
+        None => 1,
+    }
+}
+
+// The margin of a side-by-side row: its line number and a colon, or for a row
+// without one (a wrapped row, or the empty side of an added or removed line)
+// blanks just as wide, so that the two kinds of row always line up.
+fn _lineno_margin(lineno: Option<usize>, lineno_width: usize) -> String {
+    match lineno {
+        Some(lineno) => format!("{:w$}:", lineno, w=lineno_width),
+        None => format!("{:w$} ", "", w=lineno_width),
+    }
+}
+
+// The terminal width given by the COLUMNS environment variable, as a fallback
+// for when stdout isn't a terminal.
+fn env_columns() -> Option<usize> {
+    parse_columns(std::env::var("COLUMNS").ok()?.as_str())
+}
+
+fn parse_columns(columns: &str) -> Option<usize> {
+    columns.trim().parse::<usize>().ok().filter(|&columns| columns > 0)
+}
+
+// Whether the locale's character set is UTF-8, going by the first of LC_ALL,
+// LC_CTYPE and LANG to be set as the C library does. Without any of them the
+// locale is C, which isn't.
+pub fn utf8_locale() -> bool {
+    let locale = ["LC_ALL", "LC_CTYPE", "LANG"].iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|locale| !locale.is_empty());
+    locale.is_some_and(|locale| is_utf8_locale(&locale))
+}
+
+fn is_utf8_locale(locale: &str) -> bool {
+    let locale = locale.to_ascii_lowercase();
+    locale.contains("utf-8") || locale.contains("utf8")
+}
+
+// The narrowest each column of side-by-side output can be and still be
+// readable.
+pub const MIN_SIDE_BY_SIDE_WIDTH: usize = 20;
+
+fn calc_line_width(term_width: usize, sep_width: usize, lineno_width: usize) -> (usize, usize) {
+    // Split the width between the columns, giving any odd column left over to
+    // the left so that every row is exactly as wide as the terminal. Each
+    // column needs room for the line number, its colon and a space. On a
+    // terminal too narrow for that, still draw at least one character per row
+    // rather than underflowing.
+    let available = term_width.saturating_sub(sep_width);
+    let column_r = available / 2;
+    let column_l = available - column_r;
+    (max(1, column_l.saturating_sub(lineno_width + 2)),
+     max(1, column_r.saturating_sub(lineno_width + 2)))
 }
 
-fn _print_side_by_side_line(lineno_l: ANSIString,
-                            lineno_r: ANSIString,
-                            wrapno_l: ANSIString,
-                            wrapno_r: ANSIString,
-                            line_l:   &Vec<ANSIString>,
-                            line_r:   &Vec<ANSIString>,
-                            line_width: (usize, usize),
-                            separator: &str) {
+// Formats a pair of lines into rows, wrapping each side to its column width.
+// Each side is wrapped independently, with the shorter side padded out to the
+// same number of rows as the longer.
+#[allow(clippy::too_many_arguments)]
+fn _format_side_by_side_line(lineno_l: ANSIString,
+                             lineno_r: ANSIString,
+                             wrapno_l: ANSIString,
+                             wrapno_r: ANSIString,
+                             line_l:   &Vec<ANSIString>,
+                             line_r:   &Vec<ANSIString>,
+                             line_width: (usize, usize),
+                             opts: &RenderOptions) -> Vec<String> {
+    let mut rows = Vec::new();
     let mut margin_l = &lineno_l;
     let mut margin_r = &lineno_r;
-    let line_l_iter = wrap_ansistrings(line_l, line_width.0);
-    let line_r_iter = wrap_ansistrings(line_r, line_width.1);
+    // A line which already holds the placeholders for preserved tabs has its
+    // tabs expanded instead, so that the placeholders are left as they are.
+    let preserve_tabs = |line: &[ANSIString]| {
+        opts.tabs == Some(Tabs::Preserve) && !opts.show_whitespace
+            && !whitespace::has_tab_placeholders(line)
+    };
+    let (preserve_l, preserve_r) = (preserve_tabs(line_l), preserve_tabs(line_r));
+    // Control chars are drawn as symbols, as a terminal would otherwise act on
+    // them (moving the cursor or changing the colors) rather than take up the
+    // columns they're padded to. The CR of a CRLF ending isn't one of them.
+    let (controls_l, controls_r) =
+        (whitespace::show_controls(&whitespace::strip_line_ending(line_l)),
+         whitespace::show_controls(&whitespace::strip_line_ending(line_r)));
+    let (line_l, line_r) = (&controls_l, &controls_r);
+    let (shown_l, shown_r);
+    let (line_l, line_r) = if opts.show_whitespace {
+        shown_l = whitespace::show_whitespace(line_l);
+        shown_r = whitespace::show_whitespace(line_r);
+        (&shown_l, &shown_r)
+    } else {
+        // Tabs are expanded here rather than by the terminal so that the
+        // columns can be wrapped and padded to the width they're measured at.
+        // Preserved tabs are expanded to placeholders which are turned back
+        // into tabs once the rows have been laid out.
+        shown_l = whitespace::expand_tabs(line_l, opts.tab_width, preserve_l);
+        shown_r = whitespace::expand_tabs(line_r, opts.tab_width, preserve_r);
+        (&shown_l, &shown_r)
+    };
+    let line_l_iter = wrap_ansistrings(line_l, line_width.0).indent(opts.wrap_indent);
+    let line_r_iter = wrap_ansistrings(line_r, line_width.1).indent(opts.wrap_indent);
     let mut first_iteration = true;
     for zipped in line_l_iter.zip_longest(line_r_iter) {
         let (wrapped_l, wrapped_r) = match zipped {
@@ -234,23 +980,51 @@ fn _print_side_by_side_line(lineno_l: ANSIString,
             EitherOrBoth::Left(l)    => (l, " ".repeat(line_width.1)),
             EitherOrBoth::Right(r)   => (" ".repeat(line_width.0), r),
         };
+        let wrapped_l = if preserve_l { whitespace::restore_tabs(&wrapped_l) } else { wrapped_l };
+        let wrapped_r = if preserve_r { whitespace::restore_tabs(&wrapped_r) } else { wrapped_r };
 
         // TODO: optimize to expoit ANSIStrings
-        println!("{} {}{}{} {}",
-                 margin_l, wrapped_l, separator, margin_r, wrapped_r);
+        rows.push(format!("{} {}{}{} {}",
+                          margin_l, wrapped_l, opts.separator, margin_r, wrapped_r));
         if first_iteration {
             margin_l = &wrapno_l;
             margin_r = &wrapno_r;
-            first_iteration = true;
+            first_iteration = false;
         }
     }
+    rows
 }
 
+// Styles a pair of replaced lines, highlighting the changes within them. Lines
+// too long to diff in reasonable time are styled as wholly removed and added, as
+// are lines differing only in whitespace if that isn't to be highlighted.
 fn _style_diff_line<'u>(before: &'u str, after: &'u str, styling: &DiffStyling,
+        opts: &RenderOptions,
         before_fmts: &mut Vec<ANSIString<'u>>,
         after_fmts: &mut Vec<ANSIString<'u>>) {
-    for char_change in calculate_char_diff(before, after) {
+    if _exceeds_length(before, after, opts.max_line_length) {
+        before_fmts.push(styling.remove.paint(before));
+        after_fmts.push( styling.add.paint(after));
+        return;
+    }
+    let char_changes = calculate_intraline_diff(before, after, opts.granularity);
+    if !opts.highlight_whitespace_changes && only_whitespace_changed(&char_changes) {
+        before_fmts.push(styling.remove.paint(before));
+        after_fmts.push( styling.add.paint(after));
+        return;
+    }
+    let last = char_changes.len().saturating_sub(1);
+    for (i, char_change) in char_changes.into_iter().enumerate() {
+        let char_change = match (char_change, opts.inline_context) {
+            (Diff::Same(same), Some(context)) => Diff::Same(_elide_span(&same, context, i == 0,
+                                                                        i == last)),
+            (char_change, _) => char_change,
+        };
         match char_change {
+            Diff::Same(same) if opts.emphasis == Emphasis::Changes => {
+                before_fmts.push(styling.same.paint(same.clone()));
+                after_fmts.push( styling.same.paint(same));
+            },
             Diff::Same(same) => {
                 before_fmts.push(styling.remove.paint(same.clone()));
                 after_fmts.push( styling.add.paint(same));
@@ -265,158 +1039,1419 @@ fn _style_diff_line<'u>(before: &'u str, after: &'u str, styling: &DiffStyling,
                 before_fmts.push(styling.remove_highlight.paint(rem));
                 after_fmts.push( styling.add_highlight.paint(add));
             }
+            Diff::Ignored(_) | Diff::Unmatched(_) => {},
         }
     }
 }
 
-pub fn print_diffs_side_by_side(diffs: &Vec<Diff>, max_line_count: usize,
-                                context: usize, color: bool) {
-    // Define styling constants.
-    let lineno_styling = DiffStyling {
-        same:             Black.bold(),
-        add:              Green.bold(),
-        add_highlight:    Green.bold(),
-        remove:           Red.bold(),
-        remove_highlight: Red.bold(),
-    };
-    let line_styling = DiffStyling {
-        same:             Style::default(),
-        // add:              Fixed(10).normal(),
-        // remove:           Fixed( 9).normal(),
-        // add_highlight:    Style::default().on(Fixed(22)),
-        // remove_highlight: Style::default().on(Fixed(88)),
-
-        // add:              Black.on(Fixed(114)),
-        // remove:           Black.on(Fixed(203)),
-        // add_highlight:    Black.on(Fixed( 40)),
-        // remove_highlight: Black.on(Fixed(160)),
-
-        add:              Fixed(157).normal(), // 194
-        remove:           Fixed(217).normal(), // 224
-        // add_highlight:    Fixed( 40).on(Fixed(235)),
-        // remove_highlight: Fixed(160).on(Fixed(235)),
-        add_highlight:    Fixed(157).reverse(),
-        remove_highlight: Fixed(217).reverse(),
-    };
+// Whether the changes within a line, as found by calculate_char_diff, only
+// added, removed or replaced whitespace.
+pub(crate) fn only_whitespace_changed(char_changes: &[Diff]) -> bool {
+    let is_whitespace = |span: &str| span.chars().all(char::is_whitespace);
+    char_changes.iter().all(|change| match change {
+        Diff::Same(_) | Diff::Ignored(_) | Diff::Unmatched(_) => true,
+        Diff::Add(span) | Diff::Remove(span) => is_whitespace(span),
+        Diff::Replace(rem, add) => is_whitespace(rem) && is_whitespace(add),
+    })
+}
 
-    // Define separation characters.
-    let sep = "\u{2502}";
-    let sep_width = sep.len();
+// Elides the middle of an unchanged span within a replaced line, keeping
+// `context` graphemes beside the changes either side of it. The first span has
+// no change before it and the last none after it, so they keep only one end.
+fn _elide_span(span: &str, context: usize, first: bool, last: bool) -> String {
+    let graphemes: Vec<&str> = span.graphemes(true).collect();
+    let head = if first { 0 } else { context };
+    let tail = if last { 0 } else { context };
+    if graphemes.len() <= head + tail + 1 {
+        return span.to_string();
+    }
+    format!("{}\u{2026}{}", graphemes[..head].concat(), graphemes[graphemes.len() - tail..].concat())
+}
 
-    // Caclulcate widths to draw to.
-    let lineno_width = (max_line_count as f32).log(10.0).floor() as usize + 1;
-    let line_width = match term_size::dimensions_stdout() {
-        Some((term_width, _)) => {
-            let line_width = ((term_width - sep_width) / 2) - (lineno_width + 2);
-            (line_width, line_width)
-        },
-        None => {
-            calc_max_line_width(diffs)
-        },
+// Styles a replaced line as a single line, with removed spans struck through
+// ahead of the spans which replaced them. Without color the spans are marked up
+// as [-removed-]{+added+} instead.
+fn _style_inline_line(before: &str, after: &str, styling: &DiffStyling,
+        color: bool, granularity: Granularity) -> Vec<ANSIString<'static>> {
+    _style_char_diffs(&calculate_intraline_diff(before, after, granularity), styling, color)
+}
+
+fn _style_char_diffs(char_diffs: &[Diff], styling: &DiffStyling,
+        color: bool) -> Vec<ANSIString<'static>> {
+    let removed = |rem: &str| if color {
+        styling.remove_highlight.strikethrough().paint(rem.to_string())
+    } else {
+        Style::default().paint(format!("[-{}-]", rem))
+    };
+    let added = |add: &str| if color {
+        styling.add_highlight.paint(add.to_string())
+    } else {
+        Style::default().paint(format!("{{+{}+}}", add))
     };
+    let mut fmt = Vec::new();
+    for char_change in char_diffs {
+        match char_change {
+            Diff::Same(same) => fmt.push(styling.same.paint(same.clone())),
+            Diff::Add(add) => fmt.push(added(add)),
+            Diff::Remove(rem) => fmt.push(removed(rem)),
+            Diff::Replace(rem, add) => {
+                fmt.push(removed(rem));
+                fmt.push(added(add));
+            },
+            Diff::Ignored(_) | Diff::Unmatched(_) => {},
+        }
+    }
+    fmt
+}
 
-    // Print all diffs.
-    let mut lineno_l = 1;
-    let mut lineno_r = 1;
-    let empty_lineno = " ".repeat(lineno_width + 1);
-    for change in diffs {
-        match change {
-            Diff::Same(same) => {
-                for line in same.split('\n') {
-                    let lineno_l_fmt = format!("{:w$}:", lineno_l, w=lineno_width);
-                    let lineno_r_fmt = format!("{:w$}:", lineno_r, w=lineno_width);
-                    _print_side_by_side_line(
-                            lineno_styling.same.paint(&lineno_l_fmt),
-                            lineno_styling.same.paint(&lineno_r_fmt),
-                            lineno_styling.same.paint(&empty_lineno),
-                            lineno_styling.same.paint(&empty_lineno),
-                            &vec![line_styling.same.paint(line)],
-                            &vec![line_styling.same.paint(line)],
-                            line_width, sep);
-                    lineno_l += 1;
-                    lineno_r += 1;
-                }
-            },
-            Diff::Add(add) => {
-                for line_r in add.split('\n') {
-                    let lineno_r_fmt = format!("{:w$}:", lineno_r, w=lineno_width);
-                    _print_side_by_side_line(
-                            lineno_styling.same.paint(&empty_lineno),
-                            lineno_styling.add_highlight.paint(&lineno_r_fmt),
-                            lineno_styling.same.paint(&empty_lineno),
-                            lineno_styling.add_highlight.paint(&empty_lineno),
-                            &vec![line_styling.same.paint("")],
-                            &vec![line_styling.add_highlight.paint(line_r)],
-                            line_width, sep);
-                    lineno_r += 1;
-                }
-            },
-            Diff::Remove(rem) => {
-                for line_l in rem.split('\n') {
-                    let lineno_l_fmt = format!("{:w$}:", lineno_l, w=lineno_width);
-                    _print_side_by_side_line(
-                            lineno_styling.remove_highlight.paint(&lineno_l_fmt),
-                            lineno_styling.same.paint(&empty_lineno),
-                            lineno_styling.remove_highlight.paint(&empty_lineno),
-                            lineno_styling.same.paint(&empty_lineno),
-                            &vec![line_styling.remove_highlight.paint(line_l)],
-                            &vec![line_styling.same.paint("")],
-                            line_width, sep);
-                    lineno_l += 1;
-                }
-            },
-            Diff::Replace(before, after) => {
-                let lines_b = before.split('\n').collect();
-                let lines_a = after.split('\n').collect();
-                let alignment = align(&lines_b, &lines_a);
-                for aligned in alignment {
-                    match aligned {
-                        (Some(line_l), None) => {
-                            let lineno_l_fmt = format!("{:w$}:", lineno_l, w=lineno_width);
-                            _print_side_by_side_line(
-                                    lineno_styling.remove_highlight.paint(&lineno_l_fmt),
-                                    lineno_styling.same.paint(&empty_lineno),
-                                    lineno_styling.remove_highlight.paint(&empty_lineno),
-                                    lineno_styling.same.paint(&empty_lineno),
-                                    &vec![line_styling.remove_highlight.paint(line_l)],
-                                    &vec![line_styling.same.paint("")],
-                                    line_width, sep);
-                            lineno_l += 1;
-                        },
-                        (None, Some(line_r)) => {
-                            let lineno_r_fmt = format!("{:w$}:", lineno_r, w=lineno_width);
-                            _print_side_by_side_line(
-                                    lineno_styling.same.paint(&empty_lineno),
-                                    lineno_styling.add_highlight.paint(&lineno_r_fmt),
-                                    lineno_styling.same.paint(&empty_lineno),
-                                    lineno_styling.add_highlight.paint(&empty_lineno),
-                                    &vec![line_styling.same.paint("")],
-                                    &vec![line_styling.add_highlight.paint(line_r)],
-                                    line_width, sep);
-                            lineno_r += 1;
-                        },
-                        (Some(line_l), Some(line_r)) => {
-                            let lineno_l_fmt = format!("{:w$}:", lineno_l, w=lineno_width);
-                            let lineno_r_fmt = format!("{:w$}:", lineno_r, w=lineno_width);
-                            let mut fmt_l = Vec::new();
-                            let mut fmt_r = Vec::new();
-                            _style_diff_line(line_l, line_r, &line_styling,
-                                             &mut fmt_l, &mut fmt_r);
-                            _print_side_by_side_line(
-                                    lineno_styling.remove.paint(&lineno_l_fmt),
-                                    lineno_styling.add.paint(&lineno_r_fmt),
-                                    lineno_styling.remove.paint(&empty_lineno),
-                                    lineno_styling.add.paint(&empty_lineno),
-                                    &fmt_l,
-                                    &fmt_r,
-                                    line_width, sep);
-                            lineno_l += 1;
-                            lineno_r += 1;
-                        },
-                        (None, None) => {},
+// Prints a character level changeset (from calculate_char_diff) as one run of
+// text with the changes interleaved, just as for an inline replacement, since
+// there are no lines to lay it out by.
+pub fn print_char_diffs(diffs: &[Diff], opts: &RenderOptions) {
+    _print_to_stdout(|w| write_char_diffs(w, diffs, opts));
+}
+
+pub fn write_char_diffs<W: Write>(w: &mut W, diffs: &[Diff], opts: &RenderOptions)
+        -> io::Result<()> {
+    let styling = _unified_styling(opts);
+    let fmt = _style_char_diffs(diffs, &styling, opts.color);
+    let text = ANSIStrings(&fmt).to_string();
+    if text.ends_with('\n') {
+        write!(w, "{}", text)
+    } else {
+        writeln!(w, "{}", text)
+    }
+}
+
+pub fn print_diffs_side_by_side(diffs: &[Diff], opts: &RenderOptions) {
+    _print_to_stdout(|w| write_diffs_side_by_side(w, diffs, opts));
+}
+
+pub fn write_diffs_side_by_side<W: Write>(w: &mut W, diffs: &[Diff], opts: &RenderOptions)
+        -> io::Result<()> {
+    let (diffs, omitted) = _limit_changes(diffs, opts.max_changes);
+    let hunks = _make_hunks(diffs, opts);
+    _write_hunks_side_by_side(w, &hunks, opts.context.is_some(), opts)?;
+    _write_missing_newline(w, omitted, opts)?;
+    _write_omitted(w, omitted)
+}
+
+pub fn print_hunks_side_by_side(hunks: &[Hunk], opts: &RenderOptions) {
+    _print_to_stdout(|w| write_hunks_side_by_side(w, hunks, opts));
+}
+
+pub fn write_hunks_side_by_side<W: Write>(w: &mut W, hunks: &[Hunk], opts: &RenderOptions)
+        -> io::Result<()> {
+    _write_hunks_side_by_side(w, hunks, true, opts)
+}
+
+fn _write_hunks_side_by_side(w: &mut dyn Write, hunks: &[Hunk], headers: bool,
+        opts: &RenderOptions) -> io::Result<()> {
+    for row in _format_hunks_side_by_side(hunks, headers, opts) {
+        writeln!(w, "{}", row)?;
+    }
+    Ok(())
+}
+
+// The styles for the lines of unified and inline output. The dark theme keeps to
+// the terminal's basic palette, while the light one uses the deep shades of
+// side-by-side output.
+fn _unified_styling(opts: &RenderOptions) -> DiffStyling {
+    if !opts.color {
+        return DiffStyling::plain();
+    }
+    let styling = match opts.theme {
+        Theme::Dark => DiffStyling {
+            same:             Style::default(),
+            add:              Green.normal(),
+            add_highlight:    Black.on(Green),
+            remove:           Red.normal(),
+            remove_highlight: Black.on(Red),
+        },
+        Theme::Light => _light_styling().1,
+        Theme::Mono => _mono_styling().1,
+    };
+    _with_highlight_style(styling, opts)
+}
+
+// The styling with its highlights made in the chosen style from the styles of
+// the lines they're within, if a style was chosen.
+fn _with_highlight_style(styling: DiffStyling, opts: &RenderOptions) -> DiffStyling {
+    let highlight_style = match opts.highlight_style {
+        Some(highlight_style) => highlight_style,
+        None => return styling,
+    };
+    let highlight = |line: Style| match (highlight_style, line.foreground) {
+        // Text which is already bold, as added lines are in mono, is
+        // underlined too so that its highlights still stand out.
+        (HighlightStyle::Fg, _) if line.is_bold => line.underline(),
+        (HighlightStyle::Fg, _) => line.bold(),
+        (HighlightStyle::Bg, Some(color)) => Black.on(color),
+        // Without a color to put behind the text, as in mono, a background is
+        // the text's own reversed.
+        (HighlightStyle::Bg, None) | (HighlightStyle::Reverse, _) => line.reverse(),
+        (HighlightStyle::Underline, _) => line.underline(),
+    };
+    DiffStyling {
+        add_highlight: highlight(styling.add),
+        remove_highlight: highlight(styling.remove),
+        ..styling
+    }
+}
+
+// The line heading a hunk, which is its range unless a marker was given.
+fn _hunk_marker(hunk: &Hunk, opts: &RenderOptions) -> String {
+    match &opts.context_marker {
+        Some(marker) => marker.clone(),
+        None => hunk.header(),
+    }
+}
+
+// The styles for moved blocks and, when they alternate, every other block.
+fn _moved_styling(opts: &RenderOptions) -> (Style, Style) {
+    let moved = match opts.theme {
+        _ if !opts.color => Style::default(),
+        _ if opts.color_moved == ColorMoved::Plain => _unified_styling(opts).add,
+        Theme::Mono => Style::new().italic(),
+        Theme::Dark | Theme::Light => Purple.normal(),
+    };
+    let alternate = match opts.theme {
+        _ if !opts.color || opts.color_moved != ColorMoved::Zebra => moved,
+        Theme::Mono => moved.bold(),
+        Theme::Dark | Theme::Light => Blue.normal(),
+    };
+    (moved, alternate)
+}
+
+fn _hunk_styling(opts: &RenderOptions) -> Style {
+    match opts.theme {
+        _ if !opts.color => Style::default(),
+        Theme::Mono => Style::new().bold(),
+        Theme::Dark | Theme::Light => Cyan.normal(),
+    }
+}
+
+// The styles for the line numbers and the lines of side-by-side output.
+fn _side_by_side_styling(opts: &RenderOptions) -> (DiffStyling, DiffStyling) {
+    if !opts.color {
+        return (DiffStyling::plain(), DiffStyling::plain());
+    }
+    let (lineno_styling, line_styling) = match opts.theme {
+        Theme::Dark => _dark_styling(),
+        Theme::Light => _light_styling(),
+        Theme::Mono => _mono_styling(),
+    };
+    (lineno_styling, _with_highlight_style(line_styling, opts))
+}
+
+// Pale tints, which stand out against a dark background.
+fn _dark_styling() -> (DiffStyling, DiffStyling) {
+    let lineno_styling = DiffStyling {
+        same:             Black.bold(),
+        add:              Green.bold(),
+        add_highlight:    Green.bold(),
+        remove:           Red.bold(),
+        remove_highlight: Red.bold(),
+    };
+    let line_styling = DiffStyling {
+        same:             Style::default(),
+        add:              Fixed(157).normal(),
+        remove:           Fixed(217).normal(),
+        add_highlight:    Fixed(157).reverse(),
+        remove_highlight: Fixed(217).reverse(),
+    };
+    (lineno_styling, line_styling)
+}
+
+// Deep shades, which stay readable on a light background.
+fn _light_styling() -> (DiffStyling, DiffStyling) {
+    let lineno_styling = DiffStyling {
+        same:             Fixed(244).normal(),
+        add:              Fixed(28).bold(),
+        add_highlight:    Fixed(28).bold(),
+        remove:           Fixed(124).bold(),
+        remove_highlight: Fixed(124).bold(),
+    };
+    let line_styling = DiffStyling {
+        same:             Style::default(),
+        add:              Fixed(22).normal(),
+        remove:           Fixed(88).normal(),
+        add_highlight:    Fixed(22).reverse(),
+        remove_highlight: Fixed(88).reverse(),
+    };
+    (lineno_styling, line_styling)
+}
+
+// No color at all: additions are bold, and the changes within lines underlined.
+fn _mono_styling() -> (DiffStyling, DiffStyling) {
+    let lineno_styling = DiffStyling {
+        same:             Style::default(),
+        add:              Style::new().bold(),
+        add_highlight:    Style::new().bold(),
+        remove:           Style::default(),
+        remove_highlight: Style::default(),
+    };
+    let line_styling = DiffStyling {
+        same:             Style::default(),
+        add:              Style::new().bold(),
+        remove:           Style::default(),
+        add_highlight:    Style::new().bold().underline(),
+        remove_highlight: Style::new().underline(),
+    };
+    (lineno_styling, line_styling)
+}
+
+// The width of the widest line number in the hunks.
+fn _hunks_lineno_width(hunks: &[Hunk]) -> usize {
+    let last_lineno = hunks.iter()
+                           .map(|hunk| max(hunk.left_start + hunk.left_len,
+                                           hunk.right_start + hunk.right_len) - 1)
+                           .max().unwrap_or(0);
+    calc_lineno_width(last_lineno)
+}
+
+// The width to draw side-by-side output to, or None to fit it to its lines.
+fn _term_width(opts: &RenderOptions) -> Option<usize> {
+    opts.width
+        .or_else(|| term_size::dimensions_stdout().map(|(term_width, _)| term_width))
+        .or_else(env_columns)
+}
+
+// Whether the diff's columns would each be at least MIN_SIDE_BY_SIDE_WIDTH wide
+// side by side, once the line numbers and separator are taken out of the width.
+// Output fitted to its lines always fits.
+pub fn fits_side_by_side(diffs: &[Diff], opts: &RenderOptions) -> bool {
+    hunks_fit_side_by_side(&_make_hunks(_limit_changes(diffs, opts.max_changes).0, opts), opts)
+}
+
+pub fn hunks_fit_side_by_side(hunks: &[Hunk], opts: &RenderOptions) -> bool {
+    _term_width(opts).is_none_or(|term_width| {
+        let sep_width = opts.separator.chars().count();
+        let (width_l, width_r) = calc_line_width(term_width, sep_width, _hunks_lineno_width(hunks));
+        min(width_l, width_r) >= MIN_SIDE_BY_SIDE_WIDTH
+    })
+}
+
+// Formats the hunks into the rows of side-by-side output.
+fn _format_hunks_side_by_side(hunks: &[Hunk], headers: bool, opts: &RenderOptions) -> Vec<String> {
+    // Define styling constants.
+    let (lineno_styling, line_styling) = _side_by_side_styling(opts);
+    let hunk_styling = _hunk_styling(opts);
+
+    // Define separation characters.
+    let sep_width = opts.separator.chars().count();
+
+    // Caclulcate widths to draw to.
+    let lineno_width = _hunks_lineno_width(hunks);
+    let line_width = match _term_width(opts) {
+        Some(term_width) => {
+            calc_line_width(term_width, sep_width, lineno_width)
+        },
+        None => {
+            hunks.iter().map(|hunk| calc_max_line_width(&hunk.diffs, opts))
+                 .fold((0, 0), |widest, width| (max(widest.0, width.0), max(widest.1, width.1)))
+        },
+    };
+
+    // Format all diffs.
+    let mut rows = Vec::new();
+    let empty_lineno = _lineno_margin(None, lineno_width);
+    for hunk in hunks {
+        if headers {
+            rows.push(hunk_styling.paint(_hunk_marker(hunk, opts)).to_string());
+        }
+        let mut lineno_l = hunk.left_start;
+        let mut lineno_r = hunk.right_start;
+        for change in &hunk.diffs {
+            match change {
+                // Showing one side only shows its changes, but the lines skipped
+                // still count towards the line numbers.
+                Diff::Same(_) | Diff::Ignored(_) | Diff::Unmatched(_) if opts.side.is_some() => {
+                    let (count_l, count_r) = change.line_counts();
+                    lineno_l += count_l;
+                    lineno_r += count_r;
+                },
+                Diff::Add(add) if opts.side == Some(Side::Left) => {
+                    lineno_r += add.split('\n').count();
+                },
+                Diff::Remove(rem) if opts.side == Some(Side::Right) => {
+                    lineno_l += rem.split('\n').count();
+                },
+                Diff::Same(same) => {
+                    for line in _style_same_lines(same, &line_styling, opts) {
+                        let (lineno_l_fmt, lineno_r_fmt) = if opts.number_changes_only {
+                            (empty_lineno.clone(), empty_lineno.clone())
+                        } else {
+                            (_lineno_margin(Some(lineno_l), lineno_width),
+                             _lineno_margin(Some(lineno_r), lineno_width))
+                        };
+                        rows.extend(_format_side_by_side_line(
+                                lineno_styling.same.paint(&lineno_l_fmt),
+                                lineno_styling.same.paint(&lineno_r_fmt),
+                                lineno_styling.same.paint(&empty_lineno),
+                                lineno_styling.same.paint(&empty_lineno),
+                                &line,
+                                &line,
+                                line_width, opts));
+                        lineno_l += 1;
+                        lineno_r += 1;
                     }
-                }
-            },
+                },
+                Diff::Add(add) => {
+                    for line_r in add.split('\n') {
+                        let lineno_r_fmt = _lineno_margin(Some(lineno_r), lineno_width);
+                        rows.extend(_format_side_by_side_line(
+                                lineno_styling.same.paint(&empty_lineno),
+                                lineno_styling.add_highlight.paint(&lineno_r_fmt),
+                                lineno_styling.same.paint(&empty_lineno),
+                                lineno_styling.add_highlight.paint(&empty_lineno),
+                                &vec![line_styling.same.paint("")],
+                                &vec![line_styling.add_highlight.paint(line_r)],
+                                line_width, opts));
+                        lineno_r += 1;
+                    }
+                },
+                Diff::Remove(rem) => {
+                    for line_l in rem.split('\n') {
+                        let lineno_l_fmt = _lineno_margin(Some(lineno_l), lineno_width);
+                        rows.extend(_format_side_by_side_line(
+                                lineno_styling.remove_highlight.paint(&lineno_l_fmt),
+                                lineno_styling.same.paint(&empty_lineno),
+                                lineno_styling.remove_highlight.paint(&empty_lineno),
+                                lineno_styling.same.paint(&empty_lineno),
+                                &vec![line_styling.remove_highlight.paint(line_l)],
+                                &vec![line_styling.same.paint("")],
+                                line_width, opts));
+                        lineno_l += 1;
+                    }
+                },
+                Diff::Replace(before, after) => {
+                    let lines_b: Vec<&str> = before.split('\n').collect();
+                    let lines_a: Vec<&str> = after.split('\n').collect();
+                    let alignment = align_lines(&lines_b, &lines_a, opts);
+                    for aligned in alignment {
+                        match aligned {
+                            (Some(_), None) if opts.side == Some(Side::Right) => lineno_l += 1,
+                            (None, Some(_)) if opts.side == Some(Side::Left) => lineno_r += 1,
+                            (Some(line_l), None) => {
+                                let lineno_l_fmt = _lineno_margin(Some(lineno_l), lineno_width);
+                                rows.extend(_format_side_by_side_line(
+                                        lineno_styling.remove_highlight.paint(&lineno_l_fmt),
+                                        lineno_styling.same.paint(&empty_lineno),
+                                        lineno_styling.remove_highlight.paint(&empty_lineno),
+                                        lineno_styling.same.paint(&empty_lineno),
+                                        &vec![line_styling.remove_highlight.paint(line_l)],
+                                        &vec![line_styling.same.paint("")],
+                                        line_width, opts));
+                                lineno_l += 1;
+                            },
+                            (None, Some(line_r)) => {
+                                let lineno_r_fmt = _lineno_margin(Some(lineno_r), lineno_width);
+                                rows.extend(_format_side_by_side_line(
+                                        lineno_styling.same.paint(&empty_lineno),
+                                        lineno_styling.add_highlight.paint(&lineno_r_fmt),
+                                        lineno_styling.same.paint(&empty_lineno),
+                                        lineno_styling.add_highlight.paint(&empty_lineno),
+                                        &vec![line_styling.same.paint("")],
+                                        &vec![line_styling.add_highlight.paint(line_r)],
+                                        line_width, opts));
+                                lineno_r += 1;
+                            },
+                            (Some(line_l), Some(line_r)) => {
+                                let mut lineno_l_fmt = _lineno_margin(Some(lineno_l), lineno_width);
+                                let mut lineno_r_fmt = _lineno_margin(Some(lineno_r), lineno_width);
+                                let mut fmt_l = Vec::new();
+                                let mut fmt_r = Vec::new();
+                                if opts.intraline {
+                                    _style_diff_line(line_l, line_r, &line_styling, opts,
+                                                     &mut fmt_l, &mut fmt_r);
+                                } else {
+                                    fmt_l.push(line_styling.remove.paint(line_l));
+                                    fmt_r.push(line_styling.add.paint(line_r));
+                                }
+                                // Blank out the side not being shown.
+                                match opts.side {
+                                    Some(Side::Left) => {
+                                        lineno_r_fmt = empty_lineno.clone();
+                                        fmt_r = vec![line_styling.same.paint("")];
+                                    },
+                                    Some(Side::Right) => {
+                                        lineno_l_fmt = empty_lineno.clone();
+                                        fmt_l = vec![line_styling.same.paint("")];
+                                    },
+                                    None => {},
+                                }
+                                rows.extend(_format_side_by_side_line(
+                                        lineno_styling.remove.paint(&lineno_l_fmt),
+                                        lineno_styling.add.paint(&lineno_r_fmt),
+                                        lineno_styling.remove.paint(&empty_lineno),
+                                        lineno_styling.add.paint(&empty_lineno),
+                                        &fmt_l,
+                                        &fmt_r,
+                                        line_width, opts));
+                                lineno_l += 1;
+                                lineno_r += 1;
+                            },
+                            (None, None) => {},
+                        }
+                    }
+                },
+                Diff::Unmatched(unmatched) if opts.hide_unmatched => {
+                    let (count_l, count_r) = unmatched.line_counts();
+                    lineno_l += count_l;
+                    lineno_r += count_r;
+                },
+                Diff::Ignored(ignored) | Diff::Unmatched(ignored) => {
+                    // Show both sides of the ignored change as unchanged content.
+                    let (before, after) = match ignored.as_ref() {
+                        Diff::Add(add) => (None, Some(add)),
+                        Diff::Remove(rem) => (Some(rem), None),
+                        Diff::Replace(rem, add) => (Some(rem), Some(add)),
+                        _ => continue,
+                    };
+                    let lines_b: Vec<&str> = before.map_or_else(Vec::new, |b| b.split('\n').collect());
+                    let lines_a: Vec<&str> = after.map_or_else(Vec::new, |a| a.split('\n').collect());
+                    for zipped in lines_b.iter().zip_longest(lines_a.iter()) {
+                        let (line_l, line_r) = match zipped {
+                            EitherOrBoth::Both(l, r) => (Some(l), Some(r)),
+                            EitherOrBoth::Left(l)    => (Some(l), None),
+                            EitherOrBoth::Right(r)   => (None, Some(r)),
+                        };
+                        let lineno_l_fmt = match line_l {
+                            Some(_) if !opts.number_changes_only =>
+                                _lineno_margin(Some(lineno_l), lineno_width),
+                            _ => empty_lineno.clone(),
+                        };
+                        let lineno_r_fmt = match line_r {
+                            Some(_) if !opts.number_changes_only =>
+                                _lineno_margin(Some(lineno_r), lineno_width),
+                            _ => empty_lineno.clone(),
+                        };
+                        rows.extend(_format_side_by_side_line(
+                                lineno_styling.same.paint(&lineno_l_fmt),
+                                lineno_styling.same.paint(&lineno_r_fmt),
+                                lineno_styling.same.paint(&empty_lineno),
+                                lineno_styling.same.paint(&empty_lineno),
+                                &vec![line_styling.same.paint(*line_l.unwrap_or(&""))],
+                                &vec![line_styling.same.paint(*line_r.unwrap_or(&""))],
+                                line_width, opts));
+                        lineno_l += line_l.map_or(0, |_| 1);
+                        lineno_r += line_r.map_or(0, |_| 1);
+                    }
+                },
+            }
+        }
+    }
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+
+    fn same(s: &str) -> Diff { Diff::Same(s.to_string()) }
+    fn add(s: &str) -> Diff { Diff::Add(s.to_string()) }
+    fn rem(s: &str) -> Diff { Diff::Remove(s.to_string()) }
+    fn rep(b: &str, a: &str) -> Diff { Diff::Replace(b.to_string(), a.to_string()) }
+    fn line_diff(left: &str, right: &str) -> Vec<Diff> {
+        calculate_line_diff(left, right, &DiffOptions::new())
+    }
+
+    #[test]
+    fn char_diff_keeps_graphemes_whole() {
+        // "cafe\u{301}" is café with a decomposed accent.
+        assert_eq!(vec![same("caf"), rep("e\u{301}", "e")],
+                   calculate_char_diff("cafe\u{301}", "cafe"));
+        assert_eq!(vec![same("re\u{301}sume\u{301}"), add("s")],
+                   calculate_char_diff("re\u{301}sume\u{301}", "re\u{301}sume\u{301}s"));
+    }
+
+    #[test]
+    fn line_diff_both_empty() {
+        assert_eq!(Vec::<Diff>::new(), line_diff("", ""));
+    }
+
+    #[test]
+    fn line_diff_left_empty() {
+        assert_eq!(vec![add("a\nb")], line_diff("", "a\nb\n"));
+    }
+
+    #[test]
+    fn line_diff_right_empty() {
+        assert_eq!(vec![rem("a")], line_diff("a\n", ""));
+    }
+
+    #[test]
+    fn line_diff_single_newline() {
+        assert_eq!(vec![rem("")], line_diff("\n", ""));
+        assert_eq!(vec![same("")], line_diff("\n", "\n"));
+        assert_eq!(vec![rep("", "a")], line_diff("\n", "a\n"));
+    }
+
+    #[test]
+    fn line_diff_trailing_newline_is_not_a_line() {
+        assert_eq!(vec![rep("a", "b")], line_diff("a\n", "b\n"));
+        assert_eq!(vec![same("x"), rep("y", "z")], line_diff("x\ny\n", "x\nz\n"));
+    }
+
+    #[test]
+    fn line_diff_missing_final_newline() {
+        let diffs = line_diff("a\nb\n", "a\nb");
+        assert_eq!(vec![same("a"), rep("b", "b")], diffs);
+        assert!(has_changes(&diffs));
+        assert_eq!(vec![same("a"), rep("b", "c")], line_diff("a\nb", "a\nc\n"));
+        assert_eq!(vec![same("a"), rep("b", "b"), add("c")], line_diff("a\nb", "a\nb\nc\n"));
+        assert_eq!(vec![same("a\nb")], line_diff("a\nb", "a\nb"));
+        assert_eq!(vec![add("a")], line_diff("", "a"));
+        assert_eq!(Some(Side::Right), missing_newline("a\n", "a"));
+        assert_eq!(None, missing_newline("a", "b"));
+    }
+
+    #[test]
+    fn missing_final_newline_is_not_a_line() {
+        let diffs = line_diff("a\nb\nc\n", "a\nb\nc");
+        assert_eq!((1, 1), numstat(&diffs));
+        assert_eq!(serde_json::json!([{"op": "replace", "line": 3, "new_line": 3,
+                                       "old": "c", "value": "c"}]),
+                   json_patch(&diffs, &RenderOptions::new()));
+    }
+
+    #[test]
+    fn calculate_diff_pure_add() {
+        assert_eq!(vec![same("a"), add("b\nc")], line_diff("a", "a\nb\nc"));
+    }
+
+    #[test]
+    fn calculate_diff_pure_remove() {
+        assert_eq!(vec![same("a"), rem("b"), same("c")],
+                   line_diff("a\nb\nc", "a\nc"));
+    }
+
+    #[test]
+    fn calculate_diff_remove_then_add_is_replace() {
+        assert_eq!(vec![same("a"), rep("b", "x"), same("c")],
+                   line_diff("a\nb\nc", "a\nx\nc"));
+    }
+
+    #[test]
+    fn calculate_diff_add_then_remove_is_replace() {
+        let differences = vec![Difference::Add("x".to_string()),
+                               Difference::Rem("b".to_string())];
+        assert_eq!(vec![rep("b", "x")], coalesce_differences(differences, "\n"));
+    }
+
+    #[test]
+    fn calculate_diff_flushes_trailing_change() {
+        assert_eq!(vec![same("a"), rem("b")], line_diff("a\nb", "a"));
+        assert_eq!(vec![same("a"), rep("b", "c")], line_diff("a\nb", "a\nc"));
+    }
+
+    #[test]
+    fn calculate_diff_all_same() {
+        let diffs = line_diff("a\nb\nc", "a\nb\nc");
+        assert_eq!(vec![same("a\nb\nc")], diffs);
+        assert!(!has_changes(&diffs));
+    }
+
+    #[test]
+    fn calculate_diff_myers() {
+        let opts = DiffOptions::new().algorithm(Algorithm::Myers);
+        assert_eq!(vec![same("a"), rep("b", "x"), same("c"), add("d")],
+                   calculate_line_diff("a\nb\nc\n", "a\nx\nc\nd\n", &opts));
+        assert_eq!(vec![add("a")], calculate_line_diff("", "a\n", &opts));
+        assert_eq!(Vec::<Diff>::new(), calculate_line_diff("", "", &opts));
+    }
+
+    #[test]
+    fn calculate_diff_patience() {
+        // Lcs matches the moved function's body and brace in their old place,
+        // splitting the function that stayed; patience anchors on the unique
+        // signature lines and keeps each function whole.
+        let left = "void f() {\n    work();\n}\n\nvoid g() {\n    rest();\n}\n";
+        let right = "void g() {\n    rest();\n}\n\nvoid f() {\n    work();\n    more();\n}\n";
+        assert_eq!(vec![add("void g() {\n    rest();\n}\n"), same("void f() {\n    work();"),
+                        add("    more();"), same("}"), rem("\nvoid g() {\n    rest();\n}")],
+                   line_diff(left, right));
+        let opts = DiffOptions::new().algorithm(Algorithm::Patience);
+        assert_eq!(vec![rem("void f() {\n    work();\n}\n"), same("void g() {\n    rest();\n}"),
+                        add("\nvoid f() {\n    work();\n    more();\n}")],
+                   calculate_line_diff(left, right, &opts));
+        assert_eq!(vec![add("a")], calculate_line_diff("", "a\n", &opts));
+    }
+
+    #[test]
+    fn split_differences_keeps_blank_lines() {
+        let split_diff = |lines_l: &[&str], lines_r: &[&str]| {
+            coalesce_differences(split_differences(lines_l, lines_r, "\n"), "\n")
+        };
+        assert_eq!(vec![rem("")], split_diff(&[""], &[]));
+        assert_eq!(vec![rep("x", "")], split_diff(&["x"], &[""]));
+        assert_eq!(vec![rem(""), same("a")], split_diff(&["", "a"], &["a"]));
+        assert_eq!(vec![same("a"), rep("b", "c")], split_diff(&["a", "b"], &["a", "c"]));
+        assert_eq!(Vec::<Diff>::new(), split_diff(&[], &[]));
+        assert_eq!(vec![same("a"), rep("b", "c")],
+                   coalesce_differences(split_differences(&["a", "b"], &["a", "c"], ""), ""));
+    }
+
+    #[test]
+    fn coalesce_pairs_replacements() {
+        let differences = vec![Difference::Same("a".to_string()),
+                               Difference::Rem("b".to_string()),
+                               Difference::Add("c".to_string()),
+                               Difference::Add("d".to_string()),
+                               Difference::Same("e".to_string())];
+        assert_eq!(vec![same("a"), rep("b", "c\nd"), same("e")],
+                   coalesce_differences(differences, "\n"));
+    }
+
+    #[test]
+    fn coalesce_merges_consecutive_same_kind() {
+        let differences = vec![Difference::Same("a".to_string()),
+                               Difference::Same("b".to_string()),
+                               Difference::Add("c".to_string()),
+                               Difference::Add("d".to_string()),
+                               Difference::Same("e".to_string()),
+                               Difference::Rem("f".to_string()),
+                               Difference::Rem("g".to_string())];
+        assert_eq!(vec![same("a\nb"), add("c\nd"), same("e"), rem("f\ng")],
+                   coalesce_differences(differences, "\n"));
+    }
+
+    #[test]
+    fn ignore_matching_replace() {
+        let regex = Regex::new("^date:").unwrap();
+        let left = "title\ndate: monday\nbody";
+        let right = "title\ndate: tuesday\nbody";
+        let diffs = calculate_line_diff(left, right, &DiffOptions::new().ignore_matching_lines(Some(regex)));
+        assert!(!has_changes(&diffs));
+        assert_eq!(vec![same("title"),
+                        Diff::Ignored(Box::new(rep("date: monday", "date: tuesday"))),
+                        same("body")], diffs);
+    }
+
+    #[test]
+    fn ignore_timestamp_columns() {
+        let left = "2024-01-01 10:00:00 started\n2024-01-01 10:00:01 loaded 3 files\n\
+                    2024-01-01 10:00:02 done\n";
+        let right = "2024-06-30 23:59:58 started\n2024-06-30 23:59:59 loaded 4 files\n\
+                     2024-06-30 23:59:59 done\n";
+        let diffs = calculate_line_diff(left, right, &DiffOptions::new().ignore_columns(vec![(1, 19)]));
+        assert_eq!(vec![Diff::Ignored(Box::new(rep("2024-01-01 10:00:00 started",
+                                                   "2024-06-30 23:59:58 started"))),
+                        rep("2024-01-01 10:00:01 loaded 3 files", "2024-06-30 23:59:59 loaded 4 files"),
+                        Diff::Ignored(Box::new(rep("2024-01-01 10:00:02 done",
+                                                   "2024-06-30 23:59:59 done")))],
+                   diffs);
+        let diffs = calculate_line_diff("a 1\nb 2\n", "a 1\nb 3\n",
+                                        &DiffOptions::new().ignore_columns(vec![(3, 3)]));
+        assert_eq!(vec![same("a 1"), Diff::Ignored(Box::new(rep("b 2", "b 3")))], diffs);
+        assert!(!has_changes(&diffs));
+    }
+
+    #[test]
+    fn only_matching_lines_kept() {
+        let regex = Regex::new("ERROR").unwrap();
+        let left = "INFO start\nERROR disk full\nINFO retry\nERROR timeout\nINFO end";
+        let right = "INFO begin\nERROR disk full\nERROR timed out\nINFO done";
+        let diffs = calculate_line_diff(left, right,
+                                        &DiffOptions::new().only_matching_lines(Some(regex)));
+        assert_eq!(vec![Diff::Unmatched(Box::new(rep("INFO start", "INFO begin"))),
+                        same("ERROR disk full"),
+                        Diff::Unmatched(Box::new(rem("INFO retry"))),
+                        rep("ERROR timeout", "ERROR timed out"),
+                        Diff::Unmatched(Box::new(rep("INFO end", "INFO done")))],
+                   diffs);
+        let opts = RenderOptions::new().color(false);
+        assert_eq!("  INFO begin\n  ERROR disk full\n- ERROR timeout\n+ ERROR timed out\n  INFO done\n",
+                   render(&diffs, &opts));
+        let opts = opts.hide_unmatched(true);
+        assert_eq!("  ERROR disk full\n- ERROR timeout\n+ ERROR timed out\n", render(&diffs, &opts));
+        let opts = opts.width(Some(41)).separator("|");
+        assert_eq!("2: ERROR disk full  |2: ERROR disk full  \n\
+                    4: ERROR timeout    |3: ERROR timed out  \n",
+                   render_side_by_side(&diffs, &opts));
+    }
+
+    #[test]
+    fn hide_unmatched_keeps_ignored_changes() {
+        let only = Regex::new("^[a-z]").unwrap();
+        let ignore = Regex::new("^date:").unwrap();
+        let left = "title\nINFO a\ndate: monday\nbody";
+        let right = "title\nINFO b\ndate: tuesday\nbody";
+        let opts = DiffOptions::new().only_matching_lines(Some(only))
+                                     .ignore_matching_lines(Some(ignore));
+        let diffs = calculate_line_diff(left, right, &opts);
+        assert_eq!(vec![same("title"),
+                        Diff::Unmatched(Box::new(rem("INFO a"))),
+                        Diff::Ignored(Box::new(rem("date: monday"))),
+                        Diff::Unmatched(Box::new(add("INFO b"))),
+                        Diff::Ignored(Box::new(add("date: tuesday"))),
+                        same("body")],
+                   diffs);
+        let opts = RenderOptions::new().color(false).hide_unmatched(true);
+        assert_eq!("  title\n  date: tuesday\n  body\n", render(&diffs, &opts));
+    }
+
+    #[test]
+    fn ignore_matching_add() {
+        let regex = Regex::new("^date:").unwrap();
+        let diffs = calculate_line_diff("a\nb", "a\ndate: today\nb",
+                                        &DiffOptions::new().ignore_matching_lines(Some(regex)));
+        assert!(!has_changes(&diffs));
+    }
+
+    #[test]
+    fn ignore_matching_needs_every_line_to_match() {
+        let regex = Regex::new("^date:").unwrap();
+        let left = "title\ndate: monday\nbody";
+        let right = "title2\ndate: tuesday\nbody";
+        let diffs = calculate_line_diff(left, right, &DiffOptions::new().ignore_matching_lines(Some(regex)));
+        assert!(has_changes(&diffs));
+    }
+
+    #[test]
+    fn ignore_blank_lines_between_paragraphs() {
+        let left = "one\ntwo\nthree";
+        let right = "one\n\ntwo\n\n\nthree";
+        let diffs = calculate_line_diff(left, right, &DiffOptions::new().ignore_blank_lines(true));
+        assert!(!has_changes(&diffs));
+        assert!(has_changes(&calculate_line_diff(left, right, &DiffOptions::new())));
+    }
+
+    #[test]
+    fn ignore_blank_lines_keeps_genuine_changes() {
+        let diffs = calculate_line_diff("a\nb", "a\n\nc",
+                                        &DiffOptions::new().ignore_blank_lines(true));
+        assert_eq!(vec![same("a"), rep("b", "\nc")], diffs);
+    }
+
+    #[test]
+    fn strip_cr_crlf_against_lf() {
+        let crlf = "one\r\ntwo\r\nthree\r\n";
+        let lf = "one\ntwo\nthree\n";
+        let diffs = calculate_line_diff(crlf, lf, &DiffOptions::new().ignore_cr(true));
+        assert!(!has_changes(&diffs));
+        assert_eq!(vec![same("one\ntwo\nthree")], diffs);
+    }
+
+    #[test]
+    fn strip_cr_only_at_eol() {
+        assert_eq!("a\rb\nc", strip_cr_at_eol("a\rb\r\nc\r"));
+    }
+
+    #[test]
+    fn slice_lines_within_range() {
+        assert_eq!(("b\nc".to_string(), (2, 3)), slice_lines("a\nb\nc\nd", 2, 3));
+    }
+
+    #[test]
+    fn slice_lines_clamped() {
+        assert_eq!(("c\nd".to_string(), (3, 4)), slice_lines("a\nb\nc\nd", 3, 10));
+        assert_eq!(("a".to_string(), (1, 1)), slice_lines("a\nb", 0, 1));
+        assert_eq!((String::new(), (3, 2)), slice_lines("a\nb", 5, 10));
+    }
+
+    #[test]
+    fn lineno_width_empty_file() {
+        assert_eq!(1, calc_lineno_width("".matches('\n').count()));
+    }
+
+    #[test]
+    fn lineno_width_single_line() {
+        assert_eq!(1, calc_lineno_width("hello".matches('\n').count()));
+        assert_eq!(1, calc_lineno_width("hello\n".matches('\n').count()));
+    }
+
+    #[test]
+    fn lineno_width_multi_digit() {
+        assert_eq!(1, calc_lineno_width(9));
+        assert_eq!(2, calc_lineno_width(10));
+        assert_eq!(2, calc_lineno_width(99));
+        assert_eq!(3, calc_lineno_width(100));
+        assert_eq!(3, calc_lineno_width(999));
+        assert_eq!(4, calc_lineno_width(1000));
+        assert_eq!(5, calc_lineno_width(10000));
+    }
+
+    #[test]
+    fn lineno_width_large_counts() {
+        assert_eq!(6, calc_lineno_width(999_999));
+        assert_eq!(7, calc_lineno_width(1_000_000));
+        assert_eq!(8, calc_lineno_width(16_777_217));
+        assert_eq!(usize::MAX.to_string().len(), calc_lineno_width(usize::MAX));
+    }
+
+    #[test]
+    fn max_line_width_per_side() {
+        let long = "a very long line which is the same on both sides";
+        let diffs = vec![same(long), add("an added line"), rem("a removed line")];
+        let opts = RenderOptions::new();
+        assert_eq!((long.len(), long.len()), calc_max_line_width(&diffs, &opts));
+        let diffs = vec![same("short"), add(long), rem("a removed line")];
+        assert_eq!(("a removed line".len(), long.len()), calc_max_line_width(&diffs, &opts));
+    }
+
+    #[test]
+    fn max_line_width_tabs_and_wide_chars() {
+        let diffs = vec![rep("\tx", "\u{4f60}\u{597d}\u{4e16}\u{754c}")];
+        assert_eq!((9, 8), calc_max_line_width(&diffs, &RenderOptions::new()));
+        assert_eq!((5, 8), calc_max_line_width(&diffs, &RenderOptions::new().tab_width(4)));
+        let opts = RenderOptions::new().show_whitespace(true);
+        assert_eq!((2, 8), calc_max_line_width(&diffs, &opts));
+    }
+
+    #[test]
+    fn side_by_side_pads_tabs_and_wide_chars() {
+        let opts = RenderOptions::new().color(false).width(Some(25)).separator("|").tab_width(4);
+        assert_eq!("1: \tx\u{4f60}  |1: \tx\u{597d}  \n".replace('\t', "    "),
+                   render_side_by_side(&line_diff("\tx\u{4f60}\n", "\tx\u{597d}\n"), &opts));
+    }
+
+    #[test]
+    fn style_diff_line_emphasis() {
+        let styling = DiffStyling {
+            same:             Style::default(),
+            add:              Green.normal(),
+            add_highlight:    Green.reverse(),
+            remove:           Red.normal(),
+            remove_highlight: Red.reverse(),
+        };
+        let styles = |emphasis| {
+            let mut fmt_b = Vec::new();
+            let mut fmt_a = Vec::new();
+            _style_diff_line("abc", "axc", &styling, &RenderOptions::new().emphasis(emphasis),
+                             &mut fmt_b, &mut fmt_a);
+            (fmt_b.iter().map(|s| *s.style_ref()).collect::<Vec<Style>>(),
+             fmt_a.iter().map(|s| *s.style_ref()).collect::<Vec<Style>>())
+        };
+        assert_eq!((vec![Style::default(), Red.reverse(), Style::default()],
+                    vec![Style::default(), Green.reverse(), Style::default()]),
+                   styles(Emphasis::Changes));
+        assert_eq!((vec![Red.normal(), Red.reverse(), Red.normal()],
+                    vec![Green.normal(), Green.reverse(), Green.normal()]),
+                   styles(Emphasis::Line));
+    }
+
+    #[test]
+    fn style_diff_line_too_long() {
+        let styling = DiffStyling { remove: Red.normal(), add: Green.normal(), ..DiffStyling::plain() };
+        let mut fmt_b = Vec::new();
+        let mut fmt_a = Vec::new();
+        _style_diff_line("abcdef", "abxdef", &styling, &RenderOptions::new().max_line_length(Some(5)),
+                         &mut fmt_b, &mut fmt_a);
+        assert_eq!(vec![Red.paint("abcdef")], fmt_b);
+        assert_eq!(vec![Green.paint("abxdef")], fmt_a);
+    }
+
+    #[test]
+    fn style_diff_line_whitespace_only() {
+        let styling = DiffStyling { remove: Red.normal(), add: Green.normal(),
+                                    remove_highlight: Red.reverse(), add_highlight: Green.reverse(),
+                                    ..DiffStyling::plain() };
+        let styles = |before, after| {
+            let mut fmt_b = Vec::new();
+            let mut fmt_a = Vec::new();
+            let opts = RenderOptions::new().highlight_whitespace_changes(false);
+            _style_diff_line(before, after, &styling, &opts, &mut fmt_b, &mut fmt_a);
+            (fmt_b, fmt_a)
+        };
+        assert_eq!((vec![Red.paint("  if x {")], vec![Green.paint("    if x  {")]),
+                   styles("  if x {", "    if x  {"));
+        assert!(styles("  if x {", "    if y {").0.len() > 1);
+    }
+
+    #[test]
+    fn style_diff_line_inline_context() {
+        let styles = |before, after, opts: &RenderOptions| {
+            let mut fmt_b = Vec::new();
+            let mut fmt_a = Vec::new();
+            _style_diff_line(before, after, &DiffStyling::plain(), opts, &mut fmt_b, &mut fmt_a);
+            (ANSIStrings(&fmt_b).to_string(), ANSIStrings(&fmt_a).to_string())
+        };
+        let opts = RenderOptions::new().inline_context(Some(2));
+        assert_eq!(("\u{2026}jkXmn\u{2026}vwYyz".to_string(), "\u{2026}jkxmn\u{2026}vwyyz".to_string()),
+                   styles("abcdefghijkXmnopqrstuvwYyz", "abcdefghijkxmnopqrstuvwyyz", &opts));
+        assert_eq!(("abXcd".to_string(), "abxcd".to_string()), styles("abXcd", "abxcd", &opts));
+        assert_eq!(("abcdefXghijk".to_string(), "abcdefxghijk".to_string()),
+                   styles("abcdefXghijk", "abcdefxghijk", &RenderOptions::new()));
+    }
+
+    #[test]
+    fn margins_padded_to_widest_marker() {
+        let opts = RenderOptions::new().markers(">>", "<", "");
+        assert_eq!((">> ".to_string(), "<  ".to_string(), "   ".to_string(), "~  ".to_string()),
+                   _margins(&opts));
+        assert_eq!(("+ ".to_string(), "- ".to_string(), "  ".to_string(), "~ ".to_string()),
+                   _margins(&RenderOptions::new()));
+    }
+
+    #[test]
+    fn margins_annotated() {
+        let opts = RenderOptions::new().annotate(true);
+        assert_eq!(("R: + ".to_string(), "L: - ".to_string(), "=    ".to_string(),
+                    "=  ~ ".to_string()),
+                   _margins(&opts));
+        let opts = opts.tags("<", ">", "");
+        assert_eq!(("> + ".to_string(), "< - ".to_string(), "    ".to_string(),
+                    "  ~ ".to_string()),
+                   _margins(&opts));
+    }
+
+    #[test]
+    fn number_changes_only_keeps_counting() {
+        let hunks = hunks(&[same("a\nb"), rep("c", "x"), same("d"), add("e")], None, 0, (0, 0),
+                          (1, 1));
+        let opts = RenderOptions::new().color(false).width(Some(23)).separator("|")
+                                    .number_changes_only(true);
+        let rows = _format_hunks_side_by_side(&hunks, false, &opts);
+        assert_eq!(vec!["   a       |   a       ",
+                        "   b       |   b       ",
+                        "3: c       |3: x       ",
+                        "   d       |   d       ",
+                        "           |5: e       "], rows);
+    }
+
+    #[test]
+    fn left_only_keeps_line_numbers() {
+        let hunks = hunks(&[same("a"), add("b"), rep("c", "x"), same("d"), rem("e")], None, 0,
+                          (0, 0), (1, 1));
+        let opts = RenderOptions::new().color(false).width(Some(23)).separator("|")
+                                       .side(Some(Side::Left));
+        let rows = _format_hunks_side_by_side(&hunks, false, &opts);
+        assert_eq!(vec!["2: c       |           ",
+                        "4: e       |           "], rows);
+        let opts = opts.side(Some(Side::Right));
+        let rows = _format_hunks_side_by_side(&hunks, false, &opts);
+        assert_eq!(vec!["           |2: b       ",
+                        "           |3: x       "], rows);
+    }
+
+    #[test]
+    fn write_diffs_into_buffer() {
+        let mut out = Vec::new();
+        write_diffs(&mut out, &[same("a"), rep("b", "c")], &RenderOptions::new().color(false))
+            .unwrap();
+        assert_eq!("  a\n- b\n+ c\n", String::from_utf8(out).unwrap());
+    }
+
+    fn render(diffs: &[Diff], opts: &RenderOptions) -> String {
+        let mut out = Vec::new();
+        write_diffs(&mut out, diffs, opts).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn render_side_by_side(diffs: &[Diff], opts: &RenderOptions) -> String {
+        let mut out = Vec::new();
+        write_diffs_side_by_side(&mut out, diffs, opts).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn snapshot_pure_add_plain() {
+        let opts = RenderOptions::new().color(false);
+        assert_eq!("+ a\n+ b\n", render(&line_diff("", "a\nb\n"), &opts));
+    }
+
+    #[test]
+    fn snapshot_pure_add_color() {
+        assert_eq!("+ \u{1b}[32ma\u{1b}[0m\n", render(&line_diff("", "a\n"), &RenderOptions::new()));
+    }
+
+    #[test]
+    fn snapshot_replace_intraline_color() {
+        assert_eq!(
+            "  a\n- foo ba\u{1b}[41;30mr\u{1b}[0m\n+ foo ba\u{1b}[42;30mz\u{1b}[0m\n",
+            render(&line_diff("a\nfoo bar\n", "a\nfoo baz\n"), &RenderOptions::new()));
+    }
+
+    #[test]
+    fn snapshot_replace_intraline_plain() {
+        let opts = RenderOptions::new().color(false);
+        assert_eq!("  a\n- foo bar\n+ foo baz\n",
+                   render(&line_diff("a\nfoo bar\n", "a\nfoo baz\n"), &opts));
+    }
+
+    #[test]
+    fn syntax_highlights_unchanged_lines_only() {
+        let diffs = line_diff("fn a() {}\nx\n", "fn a() {}\ny\n");
+        let output = render(&diffs, &RenderOptions::new().syntax(Some("rs")));
+        let lines: Vec<&str> = output.lines().collect();
+        assert!(lines[0].starts_with("  \u{1b}[38;2;"));
+        assert_eq!(vec!["- \u{1b}[41;30mx\u{1b}[0m", "+ \u{1b}[42;30my\u{1b}[0m"], lines[1..]);
+        let plain = RenderOptions::new().color(false).syntax(Some("rs"));
+        assert_eq!("  fn a() {}\n- x\n+ y\n", render(&diffs, &plain));
+    }
+
+    #[test]
+    fn header_heads_changed_files() {
+        let opts = RenderOptions::new().color(false).header("a.txt", "b.txt");
+        assert_eq!("--- a.txt\n+++ b.txt\n  a\n- b\n+ c\n",
+                   render(&line_diff("a\nb\n", "a\nc\n"), &opts));
+        assert_eq!("  a\n", render(&line_diff("a\n", "a\n"), &opts));
+    }
+
+    #[test]
+    fn patch_header_styles() {
+        let lines: Vec<String> = ["commit 0123abc", "Author: A <a@example.com>", "",
+                                  "    rename the thing", "diff --git a/x b/y",
+                                  "similarity index 90%", "rename from x", "rename to y"]
+            .iter().map(|line| line.to_string()).collect();
+        let write = |opts: &RenderOptions| {
+            let mut written = Vec::new();
+            write_patch_header(&mut written, &lines, opts).unwrap();
+            String::from_utf8(written).unwrap()
+        };
+        assert_eq!(format!("{}\n", lines.join("\n")), write(&RenderOptions::new().color(false)));
+        let bold = Style::new().bold();
+        assert_eq!(format!("{}\nAuthor: A <a@example.com>\n\n    rename the thing\n{}\n{}\n{}\n{}\n",
+                           Yellow.paint("commit 0123abc"), bold.paint("diff --git a/x b/y"),
+                           bold.paint("similarity index 90%"), bold.paint("rename from x"),
+                           bold.paint("rename to y")),
+                   write(&RenderOptions::new()));
+    }
+
+    #[test]
+    fn highlight_styles_shared_by_both_modes() {
+        let diffs = line_diff("abc\n", "axc\n");
+        let opts = RenderOptions::new().width(Some(30));
+        let unified = render(&diffs, &opts);
+        assert!(unified.contains(&Black.on(Green).paint("x").to_string()), "{}", unified);
+        let side_by_side = render_side_by_side(&diffs, &opts);
+        assert!(side_by_side.contains(&Fixed(157).reverse().paint("x").to_string()), "{}", side_by_side);
+        let expected = [(HighlightStyle::Fg, Green.bold(), Fixed(157).bold()),
+                        (HighlightStyle::Bg, Black.on(Green), Black.on(Fixed(157))),
+                        (HighlightStyle::Reverse, Green.reverse(), Fixed(157).reverse()),
+                        (HighlightStyle::Underline, Green.underline(), Fixed(157).underline())];
+        for (highlight_style, unified_style, side_by_side_style) in expected {
+            let opts = opts.clone().highlight_style(Some(highlight_style));
+            let unified = render(&diffs, &opts);
+            assert!(unified.contains(&unified_style.paint("x").to_string()), "{}", unified);
+            let side_by_side = render_side_by_side(&diffs, &opts);
+            assert!(side_by_side.contains(&side_by_side_style.paint("x").to_string()),
+                    "{}", side_by_side);
+        }
+        let mono = opts.theme(Theme::Mono).highlight_style(Some(HighlightStyle::Bg));
+        assert!(render(&diffs, &mono).contains(&Style::new().bold().reverse().paint("x").to_string()));
+        let mono = mono.highlight_style(Some(HighlightStyle::Fg));
+        let bold_underline = Style::new().bold().underline().paint("x").to_string();
+        assert!(render(&diffs, &mono).contains(&bold_underline));
+    }
+
+    #[test]
+    fn context_marker_replaces_ranges() {
+        let diffs = line_diff("a\nb\nc\nd\ne\n", "x\nb\nc\nd\ny\n");
+        let opts = RenderOptions::new().color(false).context(Some(0));
+        assert_eq!("@@ -1,1 +1,1 @@\n- a\n+ x\n@@ -5,1 +5,1 @@\n- e\n+ y\n", render(&diffs, &opts));
+        let marked = opts.clone().context_marker(Some("\u{22ee}"));
+        assert_eq!("\u{22ee}\n- a\n+ x\n\u{22ee}\n- e\n+ y\n", render(&diffs, &marked));
+        let blank = opts.width(Some(17)).separator("|").context_marker(Some(""));
+        assert_eq!("\n1: a    |1: x    \n\n5: e    |5: y    \n",
+                   render_side_by_side(&diffs, &blank));
+    }
+
+    #[test]
+    fn moves_written_alone() {
+        let diffs = line_diff("let first = 1;\nlet second = 2;\nx\ny\nz\n",
+                              "x\ny\nz\nlet first = 1;\nlet second = 2;\n");
+        let moves = detect_moves(&diffs, (1, 1));
+        let opts = RenderOptions::new().color(false).header("a.rs", "b.rs");
+        let mut written = Vec::new();
+        write_moves(&mut written, &moves, &opts).unwrap();
+        assert_eq!("--- a.rs\n+++ b.rs\n@@ -1,2 +4,2 @@ moved\n  let first = 1;\n  let second = 2;\n",
+                   String::from_utf8(written).unwrap());
+        let mut written = Vec::new();
+        write_moves(&mut written, &[], &opts).unwrap();
+        assert!(written.is_empty());
+    }
+
+    #[test]
+    fn color_moved_styles() {
+        let moved = |line: usize| MovedBlock {
+            left_start: line,
+            right_start: line + 10,
+            lines: vec!["let value = compute(input);".to_string()],
+        };
+        let moves = [moved(1), moved(3), moved(5)];
+        let styles = |color_moved: ColorMoved| {
+            let mut written = Vec::new();
+            write_moves(&mut written, &moves, &RenderOptions::new().color_moved(color_moved))
+                .unwrap();
+            let written = String::from_utf8(written).unwrap();
+            written.lines().skip(1).step_by(2).map(|line| line[2..7].to_string())
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(vec!["\u{1b}[35m"; 3], styles(ColorMoved::Blocks));
+        assert_eq!(vec!["\u{1b}[35m", "\u{1b}[34m", "\u{1b}[35m"], styles(ColorMoved::Zebra));
+        assert_eq!(vec!["\u{1b}[32m"; 3], styles(ColorMoved::Plain));
+    }
+
+    #[test]
+    fn narrow_widths_dont_fit_side_by_side() {
+        let diffs = line_diff("a\nb\n", "a\nc\n");
+        // Each column loses three characters to its line number, colon and space.
+        let opts = |width| RenderOptions::new().width(Some(width)).separator("|");
+        let fits = |width| fits_side_by_side(&diffs, &opts(width));
+        assert!(fits(47));
+        assert!(!fits(46));
+        let many_lines = line_diff(&"a\n".repeat(1000), &"b\n".repeat(1000));
+        assert!(!fits_side_by_side(&many_lines, &opts(47)));
+    }
+
+    #[test]
+    fn line_endings_shown() {
+        let opts = RenderOptions::new().color(false).show_line_endings(true);
+        assert_eq!("  a\u{240a}\n- b\u{240a}\n+ b\u{240d}\u{240a}\n",
+                   render(&line_diff("a\nb\n", "a\nb\r\n"), &opts));
+    }
+
+    #[test]
+    fn themes_swap_styles() {
+        let diffs = line_diff("abc\n", "axc\n");
+        let opts = RenderOptions::new().width(Some(30));
+        let dark = render_side_by_side(&diffs, &opts.clone().theme(Theme::Dark));
+        assert!(dark.contains("38;5;217m"), "{}", dark);
+        let light = render_side_by_side(&diffs, &opts.clone().theme(Theme::Light));
+        assert!(light.contains("38;5;88m") && !light.contains("217"), "{}", light);
+        // Unified output darkens the light theme's colors too.
+        let (dark, light) = (render(&diffs, &opts.clone().theme(Theme::Dark)),
+                             render(&diffs, &opts.clone().theme(Theme::Light)));
+        assert_ne!(dark, light);
+        assert!(light.contains("38;5;88m") && !dark.contains("38;5;88m"), "{}", light);
+        // Mono only ever resets, bolds or underlines.
+        let escape = Regex::new("\u{1b}\\[([0-9;]*)m").unwrap();
+        let mono = opts.theme(Theme::Mono);
+        for output in &[render_side_by_side(&diffs, &mono), render(&diffs, &mono)] {
+            assert!(output.contains("\u{1b}[4m"), "{}", output);
+            for codes in escape.captures_iter(output) {
+                assert!(codes[1].split(';').all(|code| ["", "0", "1", "4", "9"].contains(&code)),
+                        "{}", output);
+            }
+        }
+    }
+
+    // Every row, whether numbered, wrapped or blank on one side, should put the
+    // separator in the same column.
+    fn assert_rows_aligned(line_count: usize) {
+        let left: String = (1..=line_count).map(|i| format!("line {}\n", i)).collect();
+        let right = left.replacen("line 1\n", "", 1).replace(&format!("line {}\n", line_count),
+                                                               "a long added line\nlast\n");
+        let opts = RenderOptions::new().color(false).width(Some(31)).separator("|").context(Some(1));
+        let output = render_side_by_side(&line_diff(&left, &right), &opts);
+        let columns: Vec<usize> = output.lines()
+                                        .filter(|row| !row.starts_with("@@"))
+                                        .map(|row| row.find('|').unwrap())
+                                        .collect();
+        assert!(columns.len() > 4);
+        assert!(columns.iter().all(|&column| column == columns[0]), "{}", output);
+        let lineno_width = line_count.to_string().len();
+        assert_eq!(15, columns[0]);
+        assert_eq!(lineno_width + 1, _lineno_margin(Some(line_count), lineno_width).len());
+        assert_eq!(lineno_width + 1, _lineno_margin(None, lineno_width).len());
+    }
+
+    #[test]
+    fn side_by_side_rows_aligned_with_control_chars() {
+        let left = "x\u{1b}[31mred\u{1b}[0m\na\rb\nsame\n";
+        let right = "x\u{1b}[31mred\u{1b}[0m\na\rb\nother\n";
+        let opts = RenderOptions::new().color(false).width(Some(31)).separator("|");
+        let output = render_side_by_side(&line_diff(left, right), &opts);
+        assert!(!output.contains('\u{1b}') && !output.contains('\r'), "{:?}", output);
+        assert!(output.starts_with("1: x\u{241b}[31mred\u{241b}[0|"), "{}", output);
+        for row in output.lines() {
+            assert_eq!(15, display_width(&row[..row.find('|').unwrap()], 8), "{}", output);
         }
     }
+
+    #[test]
+    fn side_by_side_crlf_endings_hidden() {
+        let diffs = line_diff("a\r\nb\r\n", "a\r\nc\r\n");
+        let opts = RenderOptions::new().color(false).width(Some(31)).separator("|");
+        let output = render_side_by_side(&diffs, &opts);
+        assert!(!output.contains('\u{240d}') && !output.contains('\r'), "{:?}", output);
+        let output = render_side_by_side(&diffs, &opts.show_line_endings(true));
+        assert_eq!(4, output.matches("\u{240d}\u{240a}").count(), "{}", output);
+    }
+
+    #[test]
+    fn side_by_side_rows_aligned_two_digits() {
+        assert_rows_aligned(42);
+    }
+
+    #[test]
+    fn side_by_side_rows_aligned_three_digits() {
+        assert_rows_aligned(420);
+    }
+
+    #[test]
+    fn tabs_expanded_or_preserved() {
+        let diffs = line_diff("\tx\n", "\ty\n");
+        let unified = RenderOptions::new().color(false).tab_width(4);
+        assert_eq!("- \tx\n+ \ty\n", render(&diffs, &unified));
+        assert_eq!("-     x\n+     y\n", render(&diffs, &unified.clone().tabs(Some(Tabs::Expand))));
+        let side_by_side = unified.width(Some(21)).separator("|");
+        assert_eq!("1:     x  |1:     y  \n", render_side_by_side(&diffs, &side_by_side));
+        let preserve = side_by_side.tabs(Some(Tabs::Preserve));
+        assert_eq!("1: \tx  |1: \ty  \n", render_side_by_side(&diffs, &preserve));
+        // Chars standing in for preserved tabs are kept, and the tabs beside
+        // them expanded.
+        let diffs = line_diff("\tx\u{f8ff}\n", "\ty\u{f8fe}\n");
+        assert_eq!("1:     x\u{f8ff} |1:     y\u{f8fe} \n", render_side_by_side(&diffs, &preserve));
+    }
+
+    #[test]
+    fn word_diff_whole_words() {
+        assert_eq!(vec![same("the "), rep("cart", "cat"), same(" sat")],
+                   calculate_word_diff("the cart sat", "the cat sat"));
+        assert_eq!(vec![same("the ca"), rem("r"), same("t sat")],
+                   calculate_char_diff("the cart sat", "the cat sat"));
+    }
+
+    #[test]
+    fn side_by_side_word_highlights() {
+        let diffs = line_diff("the cart sat\n", "the cat sat\n");
+        let opts = RenderOptions::new().width(Some(37)).separator("|");
+        let remove = Fixed(217).reverse();
+        let add = Fixed(157).reverse();
+        let output = render_side_by_side(&diffs, &opts.clone().granularity(Granularity::Word));
+        assert!(output.contains(&format!("the {} sat", remove.paint("cart"))), "{}", output);
+        assert!(output.contains(&format!("the {} sat", add.paint("cat"))), "{}", output);
+        let output = render_side_by_side(&diffs, &opts);
+        assert!(output.contains(&format!("the ca{}t sat", remove.paint("r"))), "{}", output);
+    }
+
+    #[test]
+    fn max_changes_omits_later_changes() {
+        let diffs = line_diff("a\nb\nc\nd\ne\n", "a\nB\nc\nD\ne\n");
+        let opts = RenderOptions::new().color(false).max_changes(Some(1));
+        assert_eq!("  a\n- b\n+ B\n  c\n(\u{2026} more changes omitted)\n", render(&diffs, &opts));
+        let opts = opts.max_changes(Some(2));
+        assert_eq!(render(&diffs, &opts.clone().max_changes(None)), render(&diffs, &opts));
+        assert_eq!(2, count_changes(&diffs));
+    }
+
+    #[test]
+    fn snapshot_side_by_side_wrapped_plain() {
+        let opts = RenderOptions::new().color(false).width(Some(27)).separator("|");
+        assert_eq!("1: short     |             \n\
+                    \x20            |1: a much lon\n\
+                    \x20            |   ger line  \n",
+                   render_side_by_side(&line_diff("short\n", "a much longer line\n"), &opts));
+    }
+
+    #[test]
+    fn snapshot_side_by_side_color() {
+        let opts = RenderOptions::new().width(Some(27)).separator("|");
+        assert_eq!(
+            "\u{1b}[1;30m1:\u{1b}[0m x         |\u{1b}[1;30m1:\u{1b}[0m x         \n\
+             \u{1b}[1;31m2:\u{1b}[0m a\u{1b}[7;38;5;217mb\u{1b}[0m        |\
+             \u{1b}[1;32m2:\u{1b}[0m a\u{1b}[7;38;5;157mc\u{1b}[0m        \n",
+            render_side_by_side(&line_diff("x\nab\n", "x\nac\n"), &opts));
+    }
+
+    #[test]
+    fn style_inline_line_plain() {
+        let fmt = _style_inline_line("the cat sat", "the dog sat down", &DiffStyling::plain(),
+                                     false, Granularity::Char);
+        assert_eq!("the [-cat-]{+dog+} sat{+ down+}", ANSIStrings(&fmt).to_string());
+    }
+
+    #[test]
+    fn style_char_diffs_across_lines() {
+        let diffs = calculate_char_diff("key=1\nurl=a.net\n", "key=2\nurl=a.org\n");
+        let fmt = _style_char_diffs(&diffs, &DiffStyling::plain(), false);
+        assert_eq!("key=[-1-]{+2+}\nurl=a.[-net-]{+org+}\n", ANSIStrings(&fmt).to_string());
+    }
+
+    #[test]
+    fn side_by_side_long_same_line() {
+        let long = "0123456789abcdefghijklmnopqrstuvwxyz";
+        let opts = RenderOptions::new().color(false).separator("|");
+        let line = vec![Style::default().paint(long)];
+        let rows = _format_side_by_side_line(Style::default().paint("1:"),
+                                             Style::default().paint("1:"),
+                                             Style::default().paint("  "),
+                                             Style::default().paint("  "),
+                                             &line, &line, (10, 10), &opts);
+        assert_eq!(vec!["1: 0123456789|1: 0123456789",
+                        "   abcdefghij|   abcdefghij",
+                        "   klmnopqrst|   klmnopqrst",
+                        "   uvwxyz    |   uvwxyz    "], rows);
+    }
+
+    #[test]
+    fn line_width_normal_terminal() {
+        assert_eq!((36, 36), calc_line_width(81, 1, 2));
+    }
+
+    #[test]
+    fn line_width_odd_terminal() {
+        assert_eq!((36, 35), calc_line_width(80, 1, 2));
+        assert_eq!((36, 35), calc_line_width(82, 3, 2));
+    }
+
+    #[test]
+    fn parse_columns_env() {
+        assert_eq!(Some(120), parse_columns("120"));
+        assert_eq!(None, parse_columns("0"));
+        assert_eq!(None, parse_columns("wide"));
+    }
+
+    #[test]
+    fn align_lines_over_limit_unaligned() {
+        let (lines_b, lines_a) = (["a1", "b1"], ["a2", "b2"]);
+        assert_eq!(vec![(Some("a1"), Some("a2")), (Some("b1"), Some("b2"))],
+                   align_lines(&lines_b, &lines_a, &RenderOptions::new().max_align_lines(Some(4))));
+        assert_eq!(vec![(Some("a1"), None), (Some("b1"), None), (None, Some("a2")), (None, Some("b2"))],
+                   align_lines(&lines_b, &lines_a, &RenderOptions::new().max_align_lines(Some(3))));
+    }
+
+    #[test]
+    fn utf8_locales() {
+        assert!(is_utf8_locale("en_GB.UTF-8"));
+        assert!(is_utf8_locale("C.utf8"));
+        assert!(!is_utf8_locale("C"));
+        assert!(!is_utf8_locale("en_US.ISO-8859-1"));
+    }
+
+    #[test]
+    fn line_width_tiny_terminal() {
+        assert_eq!((1, 1), calc_line_width(10, 1, 3));
+        assert_eq!((1, 1), calc_line_width(0, 1, 1));
+    }
+
+    #[test]
+    fn minimize_trims_common_prefix_and_suffix() {
+        let diffs = vec![same("a"), rep("b\nc\nd", "b\nx\nd"), same("e")];
+        let minimized = minimize_diffs(diffs, "\n");
+        assert_eq!(vec![same("a\nb"), rep("c", "x"), same("d\ne")], minimized);
+    }
+
+    #[test]
+    fn minimize_merges_adjacent_changes() {
+        let diffs = vec![same("a"), rem("b"), add("c"), rep("d", "e"), same("f")];
+        let minimized = minimize_diffs(diffs, "\n");
+        assert_eq!(vec![same("a"), rep("b\nd", "c\ne"), same("f")], minimized);
+    }
+
+    #[test]
+    fn minimize_replace_to_add() {
+        let diffs = vec![rep("a\nc", "a\nb\nc")];
+        let minimized = minimize_diffs(diffs, "\n");
+        assert_eq!(vec![same("a"), add("b"), same("c")], minimized);
+    }
+
+    #[test]
+    fn minimize_identical_replace_to_same() {
+        let diffs = vec![same("a"), rep("b", "b"), same("c")];
+        let minimized = minimize_diffs(diffs, "\n");
+        assert_eq!(vec![same("a\nb\nc")], minimized);
+    }
+
+    #[test]
+    fn minimize_chars() {
+        let diffs = vec![rep("abc", "axc")];
+        let minimized = minimize_diffs(diffs, "");
+        assert_eq!(vec![same("a"), rep("b", "x"), same("c")], minimized);
+    }
 }
@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+use super::Diff;
+
+// The fewest non-whitespace characters a block must have to count as moved, as
+// for git's --color-moved, so that lone braces and blank lines which happen to
+// be removed in one place and added in another aren't taken for moves.
+pub const MIN_MOVED_CHARS: usize = 20;
+
+// A block of lines removed from one place in the left file and added unchanged
+// at another in the right file.
+#[derive(Debug, PartialEq)]
+pub struct MovedBlock {
+    pub left_start: usize,
+    pub right_start: usize,
+    pub lines: Vec<String>,
+}
+
+// A line removed from the left file or added in the right file, with its line
+// number and the run of consecutive changed lines it's in.
+struct ChangedLine<'a> {
+    lineno: usize,
+    run: usize,
+    line: &'a str,
+}
+
+fn push_lines<'a>(lines: &mut Vec<ChangedLine<'a>>, text: &'a str, run: usize,
+                  lineno: &mut usize) {
+    for line in text.split('\n') {
+        lines.push(ChangedLine { lineno: *lineno, run, line });
+        *lineno += 1;
+    }
+}
+
+// The removed and added lines of the diffs, numbering the lines from `start`
+// on each side.
+fn changed_lines(diffs: &[Diff], start: (usize, usize))
+        -> (Vec<ChangedLine<'_>>, Vec<ChangedLine<'_>>) {
+    let (mut removed, mut added) = (Vec::new(), Vec::new());
+    let (mut lineno_l, mut lineno_r) = start;
+    for (run, change) in diffs.iter().enumerate() {
+        match change {
+            Diff::Remove(rem) => push_lines(&mut removed, rem, run, &mut lineno_l),
+            Diff::Add(add) => push_lines(&mut added, add, run, &mut lineno_r),
+            Diff::Replace(rem, add) => {
+                push_lines(&mut removed, rem, run, &mut lineno_l);
+                push_lines(&mut added, add, run, &mut lineno_r);
+            },
+            Diff::Same(_) | Diff::Ignored(_) | Diff::Unmatched(_) => {
+                let (count_l, count_r) = change.line_counts();
+                lineno_l += count_l;
+                lineno_r += count_r;
+            },
+        }
+    }
+    (removed, added)
+}
+
+// Finds the blocks of removed lines which were added back unchanged somewhere
+// else, in the order they were removed. Each removed line is matched to the
+// start of the longest run of identical added lines not already matched, so
+// that a block moved as a whole is found as one.
+pub fn detect_moves(diffs: &[Diff], start: (usize, usize)) -> Vec<MovedBlock> {
+    let (removed, added) = changed_lines(diffs, start);
+    let mut positions: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (j, added_line) in added.iter().enumerate() {
+        positions.entry(added_line.line).or_default().push(j);
+    }
+    let mut matched = vec![false; added.len()];
+    // The length of the block of identical lines starting at removed[i] and
+    // added[j], each staying within its run and the added lines unmatched.
+    let block_len = |matched: &[bool], i: usize, j: usize| {
+        let mut len = 0;
+        while i + len < removed.len() && j + len < added.len() && !matched[j + len]
+                && removed[i + len].run == removed[i].run && added[j + len].run == added[j].run
+                && removed[i + len].line == added[j + len].line {
+            len += 1;
+        }
+        len
+    };
+    let mut moves = Vec::new();
+    let mut i = 0;
+    while i < removed.len() {
+        let best = positions.get(removed[i].line).into_iter().flatten()
+            .map(|&j| (block_len(&matched, i, j), j))
+            .fold((0, 0), |best, block| if block.0 > best.0 { block } else { best });
+        let (len, j) = best;
+        let lines = &removed[i..i + len];
+        let chars: usize = lines.iter()
+            .map(|removed_line| removed_line.line.chars().filter(|c| !c.is_whitespace()).count())
+            .sum();
+        if len == 0 || chars < MIN_MOVED_CHARS {
+            i += 1;
+            continue;
+        }
+        matched[j..j + len].iter_mut().for_each(|matched| *matched = true);
+        moves.push(MovedBlock {
+            left_start: removed[i].lineno,
+            right_start: added[j].lineno,
+            lines: lines.iter().map(|removed_line| removed_line.line.to_string()).collect(),
+        });
+        i += len;
+    }
+    moves
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff::{calculate_line_diff, DiffOptions};
+
+    #[test]
+    fn detect_moved_block() {
+        let left = "fn a() {\n    call_the_first();\n}\nfn b() {}\nfn c() {}\nfn d() {}\nfn e() {}\n";
+        let right = "fn b() {}\nfn c() {}\nfn d() {}\nfn e() {}\nfn a() {\n    call_the_first();\n}\n";
+        let diffs = calculate_line_diff(left, right, &DiffOptions::new());
+        let lines = ["fn a() {", "    call_the_first();", "}"];
+        assert_eq!(vec![MovedBlock {
+            left_start: 1,
+            right_start: 5,
+            lines: lines.iter().map(|line| line.to_string()).collect(),
+        }], detect_moves(&diffs, (1, 1)));
+        assert_eq!(10, detect_moves(&diffs, (10, 1))[0].left_start);
+    }
+
+    #[test]
+    fn detect_moves_ignores_short_and_changed_lines() {
+        // The brace on its own is too short to be a move, and the other line
+        // changed as it moved.
+        let diffs = vec![Diff::Remove("}\nlet value = compute(input);".to_string()),
+                         Diff::Same("unchanged".to_string()),
+                         Diff::Add("}\nlet value = compute(other);".to_string())];
+        assert_eq!(Vec::<MovedBlock>::new(), detect_moves(&diffs, (1, 1)));
+    }
+}
@@ -0,0 +1,507 @@
+use std::cmp::max;
+use regex::Regex;
+
+// The algorithm used to find the changes between two files. Lcs and Myers each
+// find a smallest set of changes, but they break ties differently and so can
+// place the hunk boundaries differently. Myers is usually faster on large
+// inputs. Patience first anchors the diff on the lines which occur exactly once
+// in each file, which can take more changes but keeps reordered or heavily
+// edited code from being matched up by its braces and blank lines.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Algorithm {
+    #[default]
+    Lcs,
+    Myers,
+    Patience,
+}
+
+// Options controlling how the diff is calculated.
+#[derive(Clone, Debug, Default)]
+pub struct DiffOptions {
+    pub algorithm: Algorithm,
+    pub minimal: bool,
+    pub ignore_cr: bool,
+    pub ignore_matching_lines: Option<Regex>,
+    pub only_matching_lines: Option<Regex>,
+    pub ignore_blank_lines: bool,
+    pub ignore_columns: Vec<(usize, usize)>,
+}
+
+impl DiffOptions {
+    pub fn new() -> DiffOptions {
+        DiffOptions::default()
+    }
+
+    // Find the changes by longest common subsequence, Myers' algorithm or
+    // patience diff.
+    pub fn algorithm(mut self, algorithm: Algorithm) -> DiffOptions {
+        self.algorithm = algorithm;
+        self
+    }
+
+    // Trim changes down to the smallest possible regions.
+    pub fn minimal(mut self, minimal: bool) -> DiffOptions {
+        self.minimal = minimal;
+        self
+    }
+
+    // Ignore carriage returns at the end of lines.
+    pub fn ignore_cr(mut self, ignore_cr: bool) -> DiffOptions {
+        self.ignore_cr = ignore_cr;
+        self
+    }
+
+    // Ignore changes in which every changed line is blank.
+    pub fn ignore_blank_lines(mut self, ignore_blank_lines: bool) -> DiffOptions {
+        self.ignore_blank_lines = ignore_blank_lines;
+        self
+    }
+
+    // Compare lines without the characters in each inclusive range of columns,
+    // counted from 1, though the whole lines are still shown.
+    pub fn ignore_columns(mut self, columns: Vec<(usize, usize)>) -> DiffOptions {
+        self.ignore_columns = columns;
+        self
+    }
+
+    // Ignore changes in which every changed line matches the regex.
+    pub fn ignore_matching_lines(mut self, regex: Option<Regex>) -> DiffOptions {
+        self.ignore_matching_lines = regex;
+        self
+    }
+
+    // Only diff the lines matching the regex. The rest are ignored, as if they
+    // were the same in both files.
+    pub fn only_matching_lines(mut self, regex: Option<Regex>) -> DiffOptions {
+        self.only_matching_lines = regex;
+        self
+    }
+}
+
+// What to tint within a replaced line: just the spans which changed, or the
+// whole line with the changed spans highlighted on top.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Emphasis {
+    Changes,
+    Line,
+}
+
+// The set of styles to color the diff with, suited to the terminal's background.
+// Mono doesn't use color at all, just bold and underlining.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Theme {
+    #[default]
+    Dark,
+    Light,
+    Mono,
+}
+
+// How the spans which changed within a replaced line are picked out from the
+// rest of it: in bold text of the line's color, in black on a background of
+// it, in reverse video, or underlined. By default unified output puts them on
+// a background and side-by-side output reverses them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HighlightStyle {
+    Fg,
+    Bg,
+    Reverse,
+    Underline,
+}
+
+// How moved blocks are colored, after git's diff.colorMoved: alternating
+// between two shades from one block to the next, all in one color, or in the
+// color of ordinary added lines.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ColorMoved {
+    Zebra,
+    #[default]
+    Blocks,
+    Plain,
+}
+
+// The units in which changes within replaced lines are found and highlighted.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Granularity {
+    Char,
+    Word,
+}
+
+// One side of the diff, for showing only the lines removed from the left file
+// or only those added in the right.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+// Whether tabs are printed as the spaces they expand to, or as literal tabs
+// (which copy and paste as tabs) while still being measured as expanded.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Tabs {
+    Expand,
+    Preserve,
+}
+
+// The columns between tab stops by default.
+pub const DEFAULT_TAB_WIDTH: usize = 8;
+
+// The most replaced lines to align with one another by default.
+pub const DEFAULT_MAX_ALIGN_LINES: usize = 500;
+
+// The longest line to diff within or align by default.
+pub const DEFAULT_MAX_LINE_LENGTH: usize = 4000;
+
+// Options controlling how the diff is printed.
+#[derive(Clone, Debug)]
+pub struct RenderOptions {
+    pub color: bool,
+    pub context: Option<usize>,
+    pub collapse_over: usize,
+    pub show_whitespace: bool,
+    pub show_line_endings: bool,
+    pub intraline: bool,
+    pub emphasis: Emphasis,
+    pub granularity: Granularity,
+    pub theme: Theme,
+    pub color_moved: ColorMoved,
+    pub highlight_style: Option<HighlightStyle>,
+    pub inline: bool,
+    pub side: Option<Side>,
+    pub missing_newline: Option<Side>,
+    pub align_threshold: f64,
+    pub max_align_lines: Option<usize>,
+    pub max_line_length: Option<usize>,
+    pub inline_context: Option<usize>,
+    pub highlight_whitespace_changes: bool,
+    pub hide_unmatched: bool,
+    pub function_context: Option<Regex>,
+    pub edges: (usize, usize),
+    pub width: Option<usize>,
+    pub wrap_indent: usize,
+    pub offsets: bool,
+    pub separator: String,
+    pub context_marker: Option<String>,
+    pub tab_width: usize,
+    pub tabs: Option<Tabs>,
+    pub syntax: Option<String>,
+    pub max_changes: Option<usize>,
+    pub header: Option<(String, String)>,
+    pub number_changes_only: bool,
+    pub marker_add: String,
+    pub marker_remove: String,
+    pub marker_same: String,
+    pub annotate: bool,
+    pub tag_left: String,
+    pub tag_right: String,
+    pub tag_same: String,
+    pub start_line: (usize, usize),
+}
+
+impl Default for RenderOptions {
+    fn default() -> RenderOptions {
+        RenderOptions {
+            color: true,
+            context: None,
+            collapse_over: 0,
+            show_whitespace: false,
+            show_line_endings: false,
+            intraline: true,
+            emphasis: Emphasis::Changes,
+            granularity: Granularity::Char,
+            theme: Theme::default(),
+            color_moved: ColorMoved::default(),
+            highlight_style: None,
+            inline: false,
+            side: None,
+            missing_newline: None,
+            align_threshold: 1.0,
+            max_align_lines: Some(DEFAULT_MAX_ALIGN_LINES),
+            max_line_length: Some(DEFAULT_MAX_LINE_LENGTH),
+            inline_context: None,
+            highlight_whitespace_changes: true,
+            hide_unmatched: false,
+            function_context: None,
+            edges: (0, 0),
+            width: None,
+            wrap_indent: 0,
+            offsets: false,
+            separator: "\u{2502}".to_string(),
+            context_marker: None,
+            tab_width: DEFAULT_TAB_WIDTH,
+            tabs: None,
+            syntax: None,
+            max_changes: None,
+            header: None,
+            number_changes_only: false,
+            marker_add: "+".to_string(),
+            marker_remove: "-".to_string(),
+            marker_same: " ".to_string(),
+            annotate: false,
+            tag_left: "L:".to_string(),
+            tag_right: "R:".to_string(),
+            tag_same: "=".to_string(),
+            start_line: (1, 1),
+        }
+    }
+}
+
+impl RenderOptions {
+    pub fn new() -> RenderOptions {
+        RenderOptions::default()
+    }
+
+    pub fn color(mut self, color: bool) -> RenderOptions {
+        self.color = color;
+        self
+    }
+
+    // Only show this many unchanged lines around each change, or everything if
+    // None.
+    pub fn context(mut self, context: Option<usize>) -> RenderOptions {
+        self.context = context;
+        self
+    }
+
+    // Show runs of no more than this many unchanged lines between changes in
+    // full, only cutting longer ones down to the context.
+    pub fn collapse_over(mut self, collapse_over: usize) -> RenderOptions {
+        self.collapse_over = collapse_over;
+        self
+    }
+
+    pub fn show_whitespace(mut self, show_whitespace: bool) -> RenderOptions {
+        self.show_whitespace = show_whitespace;
+        self
+    }
+
+    // End every line with a marker for its line ending, CRLF or LF, so that
+    // lines differing only in their endings can be told apart. The CRs have to
+    // still be in the diffed lines, rather than ignored.
+    pub fn show_line_endings(mut self, show_line_endings: bool) -> RenderOptions {
+        self.show_line_endings = show_line_endings;
+        self
+    }
+
+    // Highlight the individual characters which changed within changed lines.
+    pub fn intraline(mut self, intraline: bool) -> RenderOptions {
+        self.intraline = intraline;
+        self
+    }
+
+    pub fn emphasis(mut self, emphasis: Emphasis) -> RenderOptions {
+        self.emphasis = emphasis;
+        self
+    }
+
+    // Color the diff to suit a dark or a light background, or with no color.
+    pub fn theme(mut self, theme: Theme) -> RenderOptions {
+        self.theme = theme;
+        self
+    }
+
+    // Highlight the changes within lines in the same style in both unified and
+    // side-by-side output, or None for each's own default.
+    pub fn highlight_style(mut self, highlight_style: Option<HighlightStyle>) -> RenderOptions {
+        self.highlight_style = highlight_style;
+        self
+    }
+
+    // Color moved blocks in alternating shades, all in one color or just as
+    // added lines, as with git's diff.colorMoved.
+    pub fn color_moved(mut self, color_moved: ColorMoved) -> RenderOptions {
+        self.color_moved = color_moved;
+        self
+    }
+
+    // Highlight the changes within replaced lines by char or by whole word.
+    pub fn granularity(mut self, granularity: Granularity) -> RenderOptions {
+        self.granularity = granularity;
+        self
+    }
+
+    // Show single line replacements as one line, with the removed and added
+    // spans interleaved.
+    pub fn inline(mut self, inline: bool) -> RenderOptions {
+        self.inline = inline;
+        self
+    }
+
+    // Only show the changed lines on one side, or both sides if None.
+    pub fn side(mut self, side: Option<Side>) -> RenderOptions {
+        self.side = side;
+        self
+    }
+
+    // The side whose file lacks a newline at its end, which diff notes after the
+    // last line of the changes.
+    pub fn missing_newline(mut self, side: Option<Side>) -> RenderOptions {
+        self.missing_newline = side;
+        self
+    }
+
+    // The largest proportion of two lines which may differ for them to still be
+    // paired up within a replacement, from 0.0 to 1.0.
+    pub fn align_threshold(mut self, align_threshold: f64) -> RenderOptions {
+        self.align_threshold = align_threshold;
+        self
+    }
+
+    // Leave replacements of more than this many lines (on both sides together)
+    // unaligned, or align all of them if None.
+    pub fn max_align_lines(mut self, max_align_lines: Option<usize>) -> RenderOptions {
+        self.max_align_lines = max_align_lines;
+        self
+    }
+
+    // Neither pair up nor highlight the changes within lines of more than this
+    // many chars, which are shown as wholly removed and added instead, or allow
+    // lines of any length if None.
+    pub fn max_line_length(mut self, max_line_length: Option<usize>) -> RenderOptions {
+        self.max_line_length = max_line_length;
+        self
+    }
+
+    // Within replaced lines, elide the unchanged text more than this many chars
+    // from a change, or show the whole line if None.
+    pub fn inline_context(mut self, inline_context: Option<usize>) -> RenderOptions {
+        self.inline_context = inline_context;
+        self
+    }
+
+    // Highlight the changes within replaced lines even when they're all
+    // whitespace, rather than showing such lines as plainly changed.
+    pub fn highlight_whitespace_changes(mut self, highlight_whitespace_changes: bool)
+            -> RenderOptions {
+        self.highlight_whitespace_changes = highlight_whitespace_changes;
+        self
+    }
+
+    // Leave out the lines which didn't match when only diffing matching lines,
+    // rather than showing them as unchanged lines. They still count towards
+    // the line numbers.
+    pub fn hide_unmatched(mut self, hide_unmatched: bool) -> RenderOptions {
+        self.hide_unmatched = hide_unmatched;
+        self
+    }
+
+    // Always show this many lines at the start and end of the file, even when
+    // only showing context around changes.
+    pub fn edges(mut self, head: usize, tail: usize) -> RenderOptions {
+        self.edges = (head, tail);
+        self
+    }
+
+    // The total width to fit side-by-side output into, or None to use the
+    // terminal's width.
+    pub fn width(mut self, width: Option<usize>) -> RenderOptions {
+        self.width = width;
+        self
+    }
+
+    // Include the character offsets of the changes within replaced lines in
+    // JSON output.
+    pub fn offsets(mut self, offsets: bool) -> RenderOptions {
+        self.offsets = offsets;
+        self
+    }
+
+    // Label each hunk header with the nearest preceding unchanged line matching
+    // the regex.
+    pub fn function_context(mut self, regex: Option<Regex>) -> RenderOptions {
+        self.function_context = regex;
+        self
+    }
+
+    // The string drawn between the two columns of side-by-side output.
+    pub fn separator(mut self, separator: &str) -> RenderOptions {
+        self.separator = separator.to_string();
+        self
+    }
+
+    // Head each hunk with the marker rather than its @@ range, where an empty
+    // marker leaves a blank line between hunks.
+    pub fn context_marker(mut self, marker: Option<&str>) -> RenderOptions {
+        self.context_marker = marker.map(str::to_string);
+        self
+    }
+
+    // Indent the rows a long line wraps onto in side-by-side output by this many
+    // columns, so that they stand apart from the start of the next line. The
+    // indent always leaves at least one column for the line itself.
+    pub fn wrap_indent(mut self, wrap_indent: usize) -> RenderOptions {
+        self.wrap_indent = wrap_indent;
+        self
+    }
+
+    // The columns between tab stops, which tabs are expanded to when fitting
+    // lines to side-by-side columns.
+    pub fn tab_width(mut self, tab_width: usize) -> RenderOptions {
+        self.tab_width = max(1, tab_width);
+        self
+    }
+
+    // How to print tabs. By default they're expanded in side-by-side output,
+    // where the columns need them to be, and preserved in unified output.
+    pub fn tabs(mut self, tabs: Option<Tabs>) -> RenderOptions {
+        self.tabs = tabs;
+        self
+    }
+
+    // Syntax highlight unchanged lines as the language, given as a file
+    // extension or a file name such as Makefile. The highlighting is only shown
+    // in color, and changed lines keep their own styling.
+    pub fn syntax(mut self, language: Option<&str>) -> RenderOptions {
+        self.syntax = language.map(str::to_string);
+        self
+    }
+
+    // Stop after this many changes, noting that the rest were omitted.
+    pub fn max_changes(mut self, max_changes: Option<usize>) -> RenderOptions {
+        self.max_changes = max_changes;
+        self
+    }
+
+    // Head unified output with the names of the left and right files, as
+    // --- and +++ lines. Nothing is printed for files without changes.
+    pub fn header(mut self, left: &str, right: &str) -> RenderOptions {
+        self.header = Some((left.to_string(), right.to_string()));
+        self
+    }
+
+    // Only number changed lines in side-by-side output, leaving unchanged lines'
+    // margins blank.
+    pub fn number_changes_only(mut self, number_changes_only: bool) -> RenderOptions {
+        self.number_changes_only = number_changes_only;
+        self
+    }
+
+    // The markers drawn in the margin of added, removed and unchanged lines.
+    pub fn markers(mut self, add: &str, remove: &str, same: &str) -> RenderOptions {
+        self.marker_add = add.to_string();
+        self.marker_remove = remove.to_string();
+        self.marker_same = same.to_string();
+        self
+    }
+
+    // Tag each line of unified output with the file it came from, ahead of its
+    // margin, for consumers which parse the output line by line.
+    pub fn annotate(mut self, annotate: bool) -> RenderOptions {
+        self.annotate = annotate;
+        self
+    }
+
+    // The tags of lines from the left file, the right file and both.
+    pub fn tags(mut self, left: &str, right: &str, same: &str) -> RenderOptions {
+        self.tag_left = left.to_string();
+        self.tag_right = right.to_string();
+        self.tag_same = same.to_string();
+        self
+    }
+
+    // The line numbers the diff starts at on the left and right, for when it
+    // covers only part of the files.
+    pub fn start_line(mut self, left: usize, right: usize) -> RenderOptions {
+        self.start_line = (left, right);
+        self
+    }
+}
@@ -0,0 +1,145 @@
+use std::fmt;
+use std::ops::AddAssign;
+use super::{align_lines, Diff, RenderOptions};
+
+// Line counts summarising a diff. Replaced lines which align with one another
+// are counted as changed, any left over are counted as added or removed.
+#[derive(Debug, Default, PartialEq)]
+pub struct DiffStats {
+    pub added: usize,
+    pub removed: usize,
+    pub changed: usize,
+    pub unchanged: usize,
+}
+
+impl DiffStats {
+    // The number of lines which were added, removed or changed.
+    pub fn changed_lines(&self) -> usize {
+        self.added + self.removed + self.changed
+    }
+
+    fn count(&mut self, change: &Diff, opts: &RenderOptions) {
+        match change {
+            Diff::Same(same) => self.unchanged += same.split('\n').count(),
+            Diff::Add(add) => self.added += add.split('\n').count(),
+            Diff::Remove(rem) => self.removed += rem.split('\n').count(),
+            Diff::Replace(before, after) => {
+                let lines_b: Vec<&str> = before.split('\n').collect();
+                let lines_a: Vec<&str> = after.split('\n').collect();
+                for aligned in align_lines(&lines_b, &lines_a, opts) {
+                    match aligned {
+                        (Some(_), Some(_)) => self.changed += 1,
+                        (Some(_), None) => self.removed += 1,
+                        (None, Some(_)) => self.added += 1,
+                        (None, None) => {},
+                    }
+                }
+            },
+            Diff::Ignored(ignored) | Diff::Unmatched(ignored) => {
+                self.unchanged += ignored.line_counts().1
+            },
+        }
+    }
+}
+
+impl AddAssign for DiffStats {
+    fn add_assign(&mut self, other: DiffStats) {
+        self.added += other.added;
+        self.removed += other.removed;
+        self.changed += other.changed;
+        self.unchanged += other.unchanged;
+    }
+}
+
+impl fmt::Display for DiffStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} added, {} removed, {} changed", self.added, self.removed, self.changed)
+    }
+}
+
+pub fn diff_stats(diffs: &[Diff]) -> DiffStats {
+    diff_stats_with_options(diffs, &RenderOptions::new())
+}
+
+// Counts the lines as diff_stats does, but pairing up replaced lines only when
+// they differ by at most the threshold, to match output printed with the same
+// RenderOptions::align_threshold.
+pub fn diff_stats_with_threshold(diffs: &[Diff], align_threshold: f64) -> DiffStats {
+    diff_stats_with_options(diffs, &RenderOptions::new().align_threshold(align_threshold))
+}
+
+// Counts the lines as diff_stats does, but pairing up replaced lines the way the
+// options say to, to match output printed with them.
+pub fn diff_stats_with_options(diffs: &[Diff], opts: &RenderOptions) -> DiffStats {
+    diffs.iter().fold(DiffStats::default(), |mut stats, change| {
+        stats.count(change, opts);
+        stats
+    })
+}
+
+// The numbers of lines added and removed, counted as git diff --numstat counts
+// them: each line of a replacement counts as removed and each line it was
+// replaced with as added, however they align.
+pub fn numstat(diffs: &[Diff]) -> (usize, usize) {
+    diffs.iter().fold((0, 0), |(added, removed), change| match change {
+        Diff::Same(_) | Diff::Ignored(_) | Diff::Unmatched(_) => (added, removed),
+        _ => {
+            let (count_l, count_r) = change.line_counts();
+            (added + count_r, removed + count_l)
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stats_mixed() {
+        let diffs = vec![Diff::Same("a\nb".to_string()),
+                         Diff::Add("c".to_string()),
+                         Diff::Same("d".to_string()),
+                         Diff::Remove("e\nf".to_string()),
+                         Diff::Replace("hello world".to_string(),
+                                       "hello there world\nzzzzzzzzzzzzzzzzzzzz".to_string())];
+        let stats = diff_stats(&diffs);
+        assert_eq!(DiffStats { added: 2, removed: 2, changed: 1, unchanged: 3 }, stats);
+        assert_eq!("2 added, 2 removed, 1 changed", stats.to_string());
+        assert_eq!(5, stats.changed_lines());
+    }
+
+    #[test]
+    fn numstat_mixed() {
+        let diffs = vec![Diff::Same("a\nb".to_string()),
+                         Diff::Add("c\nd".to_string()),
+                         Diff::Remove("e\nf".to_string()),
+                         Diff::Replace("hello world".to_string(), "hello there world\nz".to_string()),
+                         Diff::Ignored(Box::new(Diff::Add("\n".to_string())))];
+        assert_eq!((4, 3), numstat(&diffs));
+        assert_eq!((0, 0), numstat(&[]));
+    }
+
+    #[test]
+    fn stats_empty() {
+        assert_eq!(DiffStats::default(), diff_stats(&[]));
+    }
+
+    #[test]
+    fn stats_ignored_are_unchanged() {
+        let diffs = vec![Diff::Same("a".to_string()),
+                         Diff::Ignored(Box::new(Diff::Add("\n".to_string())))];
+        assert_eq!(DiffStats { added: 0, removed: 0, changed: 0, unchanged: 3 },
+                   diff_stats(&diffs));
+    }
+
+    #[test]
+    fn stats_follow_align_threshold() {
+        let diffs = vec![Diff::Replace("abcdef".to_string(), "uvwxyz".to_string())];
+        assert_eq!(DiffStats { added: 0, removed: 0, changed: 1, unchanged: 0 },
+                   diff_stats(&diffs));
+        assert_eq!(DiffStats { added: 1, removed: 1, changed: 0, unchanged: 0 },
+                   diff_stats_with_threshold(&diffs, 0.5));
+        assert_eq!(DiffStats { added: 1, removed: 1, changed: 0, unchanged: 0 },
+                   diff_stats_with_options(&diffs, &RenderOptions::new().align_threshold(0.5)));
+    }
+}
@@ -0,0 +1,138 @@
+use std::collections::VecDeque;
+use std::io::{self, BufRead};
+use super::{calculate_lines_diff, Diff, DiffOptions};
+
+// The default number of lines from each input held in memory at once.
+pub const DEFAULT_WINDOW: usize = 10000;
+
+// Diffs two line streams within a sliding window of lines, so memory use is
+// bounded however long the inputs are. Each iteration yields the diffs for as
+// much of the window as could be resolved; the window is then advanced up to
+// the last unchanged block, which anchors the next window. Changes that are
+// larger than a window may be reported less minimally than by a whole-file
+// diff.
+pub struct StreamingDiff<L, R> {
+    left: io::Lines<L>,
+    right: io::Lines<R>,
+    window: usize,
+    opts: DiffOptions,
+    buf_l: VecDeque<String>,
+    buf_r: VecDeque<String>,
+}
+
+impl<L: BufRead, R: BufRead> StreamingDiff<L, R> {
+    pub fn new(left: L, right: R, window: usize, opts: DiffOptions) -> StreamingDiff<L, R> {
+        StreamingDiff {
+            left: left.lines(),
+            right: right.lines(),
+            window: window.max(1),
+            opts,
+            buf_l: VecDeque::new(),
+            buf_r: VecDeque::new(),
+        }
+    }
+}
+
+// Tops the buffer back up to the window size, returning whether the input has
+// been exhausted.
+fn fill<B: BufRead>(lines: &mut io::Lines<B>, buf: &mut VecDeque<String>,
+                    window: usize) -> io::Result<bool> {
+    while buf.len() < window {
+        match lines.next() {
+            Some(line) => buf.push_back(line?),
+            None => return Ok(true),
+        }
+    }
+    Ok(false)
+}
+
+impl<L: BufRead, R: BufRead> Iterator for StreamingDiff<L, R> {
+    type Item = io::Result<Vec<Diff>>;
+
+    fn next(&mut self) -> Option<io::Result<Vec<Diff>>> {
+        let eof_l = match fill(&mut self.left, &mut self.buf_l, self.window) {
+            Ok(eof) => eof,
+            Err(error) => return Some(Err(error)),
+        };
+        let eof_r = match fill(&mut self.right, &mut self.buf_r, self.window) {
+            Ok(eof) => eof,
+            Err(error) => return Some(Err(error)),
+        };
+        if self.buf_l.is_empty() && self.buf_r.is_empty() {
+            return None;
+        }
+        let lines_l: Vec<&str> = self.buf_l.iter().map(String::as_str).collect();
+        let lines_r: Vec<&str> = self.buf_r.iter().map(String::as_str).collect();
+        let mut diffs = calculate_lines_diff(&lines_l, &lines_r, &self.opts);
+        // Unless this is the last of both inputs, hold back everything from
+        // partway through the last unchanged block onwards so that the next
+        // window can resolve it with more context. The first half of the block
+        // is kept so that whatever precedes it still has its trailing context.
+        if !(eof_l && eof_r) {
+            let last_same = diffs.iter().enumerate().rev().find_map(|(i, change)| match change {
+                Diff::Same(same) => Some((i, same.clone())),
+                _ => None,
+            });
+            if let Some((last_same, same)) = last_same {
+                let lines: Vec<&str> = same.split('\n').collect();
+                let head_len = lines.len() / 2;
+                diffs.truncate(last_same);
+                if head_len > 0 {
+                    diffs.push(Diff::Same(lines[..head_len].join("\n")));
+                } else if diffs.is_empty() {
+                    // Always make at least some progress.
+                    diffs.push(Diff::Same(self.buf_l[0].clone()));
+                }
+            }
+        }
+        let (used_l, used_r) = diffs.iter().map(Diff::line_counts)
+                                    .fold((0, 0), |total, count| (total.0 + count.0,
+                                                                  total.1 + count.1));
+        self.buf_l.drain(..used_l);
+        self.buf_r.drain(..used_r);
+        Some(Ok(diffs))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stream(left: &str, right: &str, window: usize) -> Vec<Vec<Diff>> {
+        StreamingDiff::new(left.as_bytes(), right.as_bytes(), window, DiffOptions::new())
+            .map(|diffs| diffs.unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn stream_matches_whole_diff_within_window() {
+        let chunks = stream("a\nb\nc\nd", "a\nx\nc\nd\ne", 100);
+        assert_eq!(vec![vec![Diff::Same("a".to_string()),
+                             Diff::Replace("b".to_string(), "x".to_string()),
+                             Diff::Same("c\nd".to_string()),
+                             Diff::Add("e".to_string())]], chunks);
+    }
+
+    #[test]
+    fn stream_small_window() {
+        let left = "1\n2\n3\n4\n5\n6\n7\n8\n9";
+        let right = "1\n2\nthree\n4\n5\n6\n7\neight\n9";
+        let chunks = stream(left, right, 3);
+        let flat: Vec<Diff> = chunks.into_iter().flatten().collect();
+        let changes: Vec<&Diff> = flat.iter().filter(|d| !matches!(d, Diff::Same(_))).collect();
+        assert_eq!(vec![&Diff::Replace("3".to_string(), "three".to_string()),
+                        &Diff::Replace("8".to_string(), "eight".to_string())], changes);
+        let (lines_l, lines_r) = flat.iter().map(Diff::line_counts)
+                                     .fold((0, 0), |t, c| (t.0 + c.0, t.1 + c.1));
+        assert_eq!((9, 9), (lines_l, lines_r));
+    }
+
+    #[test]
+    fn stream_uneven_lengths() {
+        let chunks = stream("1\n2", "1\n2\n3\n4\n5\n6", 2);
+        let flat: Vec<Diff> = chunks.into_iter().flatten().collect();
+        let (lines_l, lines_r) = flat.iter().map(Diff::line_counts)
+                                     .fold((0, 0), |t, c| (t.0 + c.0, t.1 + c.1));
+        assert_eq!((2, 6), (lines_l, lines_r));
+    }
+}
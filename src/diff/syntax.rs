@@ -0,0 +1,82 @@
+use std::sync::OnceLock;
+use ansi_term::{ANSIString, Style};
+use ansi_term::Color::RGB;
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{FontStyle, Theme, ThemeSet};
+use syntect::parsing::SyntaxSet;
+
+// The syntaxes and theme are slow enough to load that it's only done once,
+// the first time anything is highlighted.
+fn syntax_set() -> &'static SyntaxSet {
+    static SYNTAX_SET: OnceLock<SyntaxSet> = OnceLock::new();
+    SYNTAX_SET.get_or_init(SyntaxSet::load_defaults_newlines)
+}
+
+fn theme() -> &'static Theme {
+    static THEME: OnceLock<Theme> = OnceLock::new();
+    THEME.get_or_init(|| {
+        let mut themes = ThemeSet::load_defaults().themes;
+        themes.remove("base16-ocean.dark").expect("the default themes include base16-ocean.dark")
+    })
+}
+
+// Highlights a run of consecutive lines in the language, given as a file
+// extension or a file name such as Makefile, into one vector of spans per line.
+// Returns None if the language isn't known. The lines are parsed
+// from a fresh state, so a construct which started before them (such as a
+// block comment) isn't recognised.
+pub fn highlight(lines: &[&str], language: &str) -> Option<Vec<Vec<ANSIString<'static>>>> {
+    let syntax_set = syntax_set();
+    let syntax = syntax_set.find_syntax_by_extension(language)?;
+    let mut highlighter = HighlightLines::new(syntax, theme());
+    let mut highlighted = Vec::with_capacity(lines.len());
+    for line in lines {
+        // The syntaxes expect each line to end in a newline.
+        let line = format!("{}\n", line);
+        let ranges = match highlighter.highlight_line(&line, syntax_set) {
+            Ok(ranges) => ranges,
+            Err(_) => return None,
+        };
+        highlighted.push(ranges.into_iter().filter_map(|(style, text)| {
+            let text = text.strip_suffix('\n').unwrap_or(text);
+            if text.is_empty() {
+                return None;
+            }
+            let color = style.foreground;
+            let mut ansi_style = Style::new().fg(RGB(color.r, color.g, color.b));
+            if style.font_style.contains(FontStyle::BOLD) {
+                ansi_style = ansi_style.bold();
+            }
+            if style.font_style.contains(FontStyle::ITALIC) {
+                ansi_style = ansi_style.italic();
+            }
+            Some(ansi_style.paint(text.to_string()))
+        }).collect());
+    }
+    Some(highlighted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ansi_term::ANSIStrings;
+
+    #[test]
+    fn highlight_keeps_text() {
+        let lines = ["fn main() {", "    let x = 1;", "}"];
+        let highlighted = highlight(&lines, "rs").unwrap();
+        assert_eq!(3, highlighted.len());
+        for (line, spans) in lines.iter().zip(&highlighted) {
+            assert_eq!(*line, ansi_term::unstyle(&ANSIStrings(spans)));
+        }
+        // The keyword is colored differently from the name after it.
+        assert!(highlighted[0].len() > 1);
+        assert_ne!(highlighted[0][0].style_ref(), highlighted[0].last().unwrap().style_ref());
+    }
+
+    #[test]
+    fn highlight_unknown_language() {
+        assert!(highlight(&["x = 1"], "py").is_some());
+        assert_eq!(None, highlight(&["x"], "not-a-language"));
+    }
+}
@@ -0,0 +1,425 @@
+use std::collections::VecDeque;
+use std::io::{self, BufRead};
+use std::mem;
+use difference::Difference;
+use super::hunk::Hunk;
+use super::coalesce_differences;
+
+// One file's worth of a unified diff: the header lines which introduce it
+// (diff, index, ---, +++ and so on) followed by its hunks.
+#[derive(Debug, Default, PartialEq)]
+pub struct PatchFile {
+    pub header: Vec<String>,
+    pub hunks: Vec<Hunk>,
+}
+
+// Parses the start and length of one side of a hunk header, e.g. "-12,3". A
+// missing length means one line, and an empty side starts after the line given.
+fn parse_range(range: &str, sign: char) -> Option<(usize, usize)> {
+    let range = range.strip_prefix(sign)?;
+    let mut parts = range.splitn(2, ',');
+    let start = parts.next()?.parse::<usize>().ok()?;
+    let len = match parts.next() {
+        Some(len) => len.parse::<usize>().ok()?,
+        None => 1,
+    };
+    Some((if len == 0 { start + 1 } else { start }, len))
+}
+
+// A hunk part way through being parsed, with the number of lines still
+// expected on each side.
+struct PendingHunk {
+    start: (usize, usize),
+    remaining: (usize, usize),
+    function: Option<String>,
+    lines: Vec<Difference>,
+}
+
+impl PendingHunk {
+    fn finish(self) -> Hunk {
+        let mut hunk = Hunk::new(self.start.0, self.start.1);
+        hunk.function = self.function;
+        for change in coalesce_differences(self.lines, "\n") {
+            hunk.push(change);
+        }
+        hunk
+    }
+}
+
+// Parses the ranges from a hunk header, along with any function name following
+// them.
+fn parse_hunk_header(line: &str) -> Option<PendingHunk> {
+    let mut fields = line.splitn(4, ' ');
+    if fields.next() != Some("@@") {
+        return None;
+    }
+    let (left_start, left_len) = parse_range(fields.next()?, '-')?;
+    let (right_start, right_len) = parse_range(fields.next()?, '+')?;
+    let rest = fields.next()?.strip_prefix("@@")?.trim();
+    let function = if rest.is_empty() { None } else { Some(rest.to_string()) };
+    Some(PendingHunk {
+        start:     (left_start, right_start),
+        remaining: (left_len, right_len),
+        function,
+        lines:     Vec::new(),
+    })
+}
+
+// Whether the text looks like a unified diff rather than a file to be diffed:
+// it either has git's "diff --git" line introducing a file, or a "---" and a
+// "+++" line followed by a hunk header. Other lines, such as the commit details
+// git show prints, may come before them.
+pub fn is_unified_diff(text: &str) -> bool {
+    let lines: Vec<&str> = text.lines().collect();
+    lines.iter().any(|line| line.starts_with("diff --git "))
+        || lines.windows(3).any(|three| {
+            three[0].starts_with("--- ") && three[1].starts_with("+++ ") && three[2].starts_with("@@ ")
+        })
+}
+
+// Removes the escape sequences which color a diff, as git prints them when it
+// pipes its output to a pager, so that the lines can be parsed.
+pub fn strip_colors(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\u{1b}' || chars.peek() != Some(&'[') {
+            stripped.push(c);
+            continue;
+        }
+        // A control sequence runs on up to and including its final byte,
+        // which is the first in the range @ to ~.
+        chars.next();
+        for c in chars.by_ref() {
+            if ('@'..='~').contains(&c) {
+                break;
+            }
+        }
+    }
+    stripped
+}
+
+// Parses the text of a unified diff into the files and hunks it describes, so
+// that an existing patch can be shown with the same printers as a fresh diff.
+// The combined diffs git shows for merges (with @@@ headers) have a column for
+// each parent and aren't parsed, but are kept as they are in the header of the
+// next file.
+pub fn parse_unified(text: &str) -> Result<Vec<PatchFile>, String> {
+    let mut files = Vec::new();
+    let mut file = PatchFile::default();
+    let mut pending: Option<PendingHunk> = None;
+    let mut combined = false;
+    for (lineno, line) in text.lines().enumerate() {
+        if combined && line.starts_with([' ', '+', '-', '\\']) {
+            file.header.push(line.to_string());
+        } else if let Some(hunk) = pending.as_mut() {
+            // Some tools strip the trailing space from unchanged empty lines.
+            let (change, used) = match line.chars().next() {
+                Some(' ') => (Difference::Same(line[1..].to_string()), (1, 1)),
+                None => (Difference::Same(String::new()), (1, 1)),
+                Some('+') => (Difference::Add(line[1..].to_string()), (0, 1)),
+                Some('-') => (Difference::Rem(line[1..].to_string()), (1, 0)),
+                Some('\\') => continue,
+                _ => return Err(format!("Unexpected line {} in hunk: {}", lineno + 1, line)),
+            };
+            if used.0 > hunk.remaining.0 || used.1 > hunk.remaining.1 {
+                return Err(format!("Hunk longer than its header on line {}", lineno + 1));
+            }
+            hunk.remaining = (hunk.remaining.0 - used.0, hunk.remaining.1 - used.1);
+            hunk.lines.push(change);
+            if hunk.remaining == (0, 0) {
+                file.hunks.push(pending.take().expect("hunk is pending").finish());
+            }
+        } else if line.starts_with("@@@") {
+            if !file.hunks.is_empty() {
+                files.push(file);
+                file = PatchFile::default();
+            }
+            file.header.push(line.to_string());
+            combined = true;
+        } else if line.starts_with("@@") {
+            combined = false;
+            match parse_hunk_header(line) {
+                Some(hunk) => {
+                    if hunk.remaining == (0, 0) {
+                        file.hunks.push(hunk.finish());
+                    } else {
+                        pending = Some(hunk);
+                    }
+                },
+                None => return Err(format!("Invalid hunk header on line {}: {}",
+                                           lineno + 1, line)),
+            }
+        } else if line.starts_with('\\') {
+            // "\ No newline at end of file" after the last line of a hunk.
+            continue;
+        } else {
+            // Anything else belongs to the header of the next file once the
+            // current one has hunks.
+            combined = false;
+            if !file.hunks.is_empty() {
+                files.push(file);
+                file = PatchFile::default();
+            }
+            file.header.push(line.to_string());
+        }
+    }
+    if pending.is_some() {
+        return Err("Unexpected end of input part way through a hunk".to_string());
+    }
+    if !file.hunks.is_empty() || !file.header.is_empty() {
+        files.push(file);
+    }
+    Ok(files)
+}
+
+// A piece of text which may hold unified diffs, as it was read: either a line
+// which isn't part of a diff, or the whole of one file's diff.
+#[derive(Debug, PartialEq)]
+pub enum PatchChunk {
+    Text(String),
+    File(String),
+}
+
+// Where the last line read left the current file's diff.
+#[derive(Clone, Copy, PartialEq)]
+enum ChunkState {
+    Outside,
+    Header,
+    // Part way through a hunk, with the number of lines still expected on
+    // each side.
+    Hunk(usize, usize),
+    Combined,
+    AfterHunk,
+}
+
+// The lines which git prints in the header of a file's diff.
+const HEADER_PREFIXES: [&str; 15] = [
+    "index ", "--- ", "+++ ", "old mode ", "new mode ", "deleted file mode ", "new file mode ",
+    "copy from ", "copy to ", "rename from ", "rename to ", "similarity index ",
+    "dissimilarity index ", "Binary files ", "mode ",
+];
+
+// Splits a stream into the lines around diffs and the diffs of each file, as
+// they're read, so that a diff can be printed as soon as its file is complete
+// and anything else passed on straight away. The lines may be colored.
+pub struct PatchChunks<R> {
+    input: R,
+    state: ChunkState,
+    file: String,
+    // A "---" line, which only starts a file if a "+++" line follows it.
+    held: Option<String>,
+    ready: VecDeque<PatchChunk>,
+    done: bool,
+}
+
+impl<R: BufRead> PatchChunks<R> {
+    pub fn new(input: R) -> PatchChunks<R> {
+        PatchChunks {
+            input,
+            state: ChunkState::Outside,
+            file: String::new(),
+            held: None,
+            ready: VecDeque::new(),
+            done: false,
+        }
+    }
+
+    fn end_file(&mut self) {
+        if !self.file.is_empty() {
+            self.ready.push_back(PatchChunk::File(mem::take(&mut self.file)));
+        }
+        self.state = ChunkState::Outside;
+    }
+
+    // Whether the line carries on the current file's diff, moving the state on
+    // past it if so.
+    fn continues_file(&mut self, line: &str) -> bool {
+        match self.state {
+            ChunkState::Outside => false,
+            ChunkState::Hunk(left, right) => {
+                let used = match line.chars().next() {
+                    Some(' ') | None => (1, 1),
+                    Some('+') => (0, 1),
+                    Some('-') => (1, 0),
+                    Some('\\') => (0, 0),
+                    _ => return false,
+                };
+                if used.0 > left || used.1 > right {
+                    return false;
+                }
+                self.state = match (left - used.0, right - used.1) {
+                    (0, 0) => ChunkState::AfterHunk,
+                    (left, right) => ChunkState::Hunk(left, right),
+                };
+                true
+            },
+            ChunkState::Combined if line.starts_with([' ', '+', '-', '\\']) => true,
+            _ if line.starts_with("@@@") => {
+                self.state = ChunkState::Combined;
+                true
+            },
+            _ if line.starts_with("@@") => {
+                // An invalid header is kept with the file, which then fails to
+                // parse and is passed on as it is.
+                self.state = match parse_hunk_header(line).map(|hunk| hunk.remaining) {
+                    Some((left, right)) if (left, right) != (0, 0) => ChunkState::Hunk(left, right),
+                    _ => ChunkState::AfterHunk,
+                };
+                true
+            },
+            _ if line.starts_with('\\') => true,
+            ChunkState::Header => HEADER_PREFIXES.iter().any(|prefix| line.starts_with(prefix)),
+            _ => false,
+        }
+    }
+
+    fn feed(&mut self, raw: String) {
+        let line = strip_colors(&raw);
+        let line = line.trim_end_matches('\n');
+        if let Some(held) = self.held.take() {
+            self.end_file();
+            if line.starts_with("+++ ") {
+                self.file = held + &raw;
+                self.state = ChunkState::Header;
+                return;
+            }
+            self.ready.push_back(PatchChunk::Text(held));
+        }
+        if self.continues_file(line) {
+            self.file.push_str(&raw);
+            return;
+        }
+        self.end_file();
+        if line.starts_with("diff ") {
+            self.file = raw;
+            self.state = ChunkState::Header;
+        } else if line.starts_with("--- ") {
+            self.held = Some(raw);
+        } else {
+            self.ready.push_back(PatchChunk::Text(raw));
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for PatchChunks<R> {
+    type Item = io::Result<PatchChunk>;
+
+    fn next(&mut self) -> Option<io::Result<PatchChunk>> {
+        loop {
+            if let Some(chunk) = self.ready.pop_front() {
+                return Some(Ok(chunk));
+            }
+            if self.done {
+                return None;
+            }
+            let mut line = Vec::new();
+            match self.input.read_until(b'\n', &mut line) {
+                Ok(0) => {
+                    self.done = true;
+                    self.end_file();
+                    if let Some(held) = self.held.take() {
+                        self.ready.push_back(PatchChunk::Text(held));
+                    }
+                },
+                Ok(_) => self.feed(String::from_utf8_lossy(&line).into_owned()),
+                Err(error) => {
+                    self.done = true;
+                    return Some(Err(error));
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::Diff;
+
+    #[test]
+    fn detect_unified_diffs() {
+        assert!(is_unified_diff("--- a/file\n+++ b/file\n@@ -1 +1 @@\n-a\n+b\n"));
+        assert!(is_unified_diff("commit 0123abc\n\n    Message\n\ndiff --git a/f b/f\n"));
+        assert!(!is_unified_diff("--- a list\n+++ of headings\nmore text\n"));
+        assert!(!is_unified_diff("fn main() {}\n"));
+    }
+
+    #[test]
+    fn parse_single_file() {
+        let patch = "--- a/file\n+++ b/file\n@@ -1,3 +1,3 @@\n a\n-b\n+c\n d\n";
+        let files = parse_unified(patch).unwrap();
+        assert_eq!(1, files.len());
+        assert_eq!(vec!["--- a/file", "+++ b/file"], files[0].header);
+        assert_eq!(1, files[0].hunks.len());
+        assert_eq!(vec![Diff::Same("a".to_string()),
+                        Diff::Replace("b".to_string(), "c".to_string()),
+                        Diff::Same("d".to_string())], files[0].hunks[0].diffs);
+        assert_eq!("@@ -1,3 +1,3 @@", files[0].hunks[0].header());
+    }
+
+    #[test]
+    fn parse_multiple_files_and_hunks() {
+        let patch = "diff --git a/x b/x\n--- a/x\n+++ b/x\n@@ -0,0 +1,2 @@\n+1\n+2\n\
+                     diff --git a/y b/y\n--- a/y\n+++ b/y\n@@ -4 +4 @@\n-old\n+new\n\
+                     @@ -10,2 +10 @@ fn context\n keep\n-gone\n\\ No newline at end of file\n";
+        let files = parse_unified(patch).unwrap();
+        assert_eq!(2, files.len());
+        assert_eq!("@@ -0,0 +1,2 @@", files[0].hunks[0].header());
+        assert_eq!(vec![Diff::Add("1\n2".to_string())], files[0].hunks[0].diffs);
+        assert_eq!(3, files[1].header.len());
+        assert_eq!(2, files[1].hunks.len());
+        assert_eq!("@@ -10,2 +10,1 @@ fn context", files[1].hunks[1].header());
+    }
+
+    #[test]
+    fn parse_removed_line_resembling_header() {
+        let patch = "@@ -1,2 +1 @@\n--- not a header\n keep\n";
+        let files = parse_unified(patch).unwrap();
+        assert_eq!(vec![Diff::Remove("-- not a header".to_string()),
+                        Diff::Same("keep".to_string())], files[0].hunks[0].diffs);
+    }
+
+    #[test]
+    fn parse_combined_diff_as_header() {
+        let patch = "diff --cc m\nindex 1,2..3\n@@@ -1,1 -1,1 +1,1 @@@\n- a\n -b\n++c\n\
+                     diff --git a/x b/x\n--- a/x\n+++ b/x\n@@ -1 +1 @@\n-old\n+new\n";
+        let files = parse_unified(patch).unwrap();
+        assert_eq!(1, files.len());
+        assert_eq!(vec!["diff --cc m", "index 1,2..3", "@@@ -1,1 -1,1 +1,1 @@@", "- a", " -b", "++c",
+                        "diff --git a/x b/x", "--- a/x", "+++ b/x"], files[0].header);
+        assert_eq!(vec![Diff::Replace("old".to_string(), "new".to_string())],
+                   files[0].hunks[0].diffs);
+    }
+
+    #[test]
+    fn strip_git_colors() {
+        assert_eq!("@@ -1 +1 @@\n-a\n+b\n",
+                   strip_colors("\u{1b}[36m@@ -1 +1 @@\u{1b}[m\n\u{1b}[31m-a\u{1b}[m\n\u{1b}[1;32m+b\u{1b}[K\u{1b}[m\n"));
+        assert_eq!("plain \u{1b}x", strip_colors("plain \u{1b}x"));
+    }
+
+    #[test]
+    fn split_log_into_chunks() {
+        let log = "commit 0123abc\n\n    --- a note\n\ndiff --git a/x b/x\nindex 1..2\n\
+                   --- a/x\n+++ b/x\n@@ -1,2 +1 @@\n-a\n b\n\u{1b}[33mcommit 4567def\u{1b}[m\n\
+                   --- plain\n+++ plain\n@@ -1 +1 @@\n-c\n+d\n--- trailing\n";
+        let chunks: Vec<PatchChunk> = PatchChunks::new(log.as_bytes()).map(Result::unwrap)
+                                                                       .collect();
+        let text = |text: &str| PatchChunk::Text(text.to_string());
+        let file = |text: &str| PatchChunk::File(text.to_string());
+        assert_eq!(vec![text("commit 0123abc\n"), text("\n"), text("    --- a note\n"), text("\n"),
+                        file("diff --git a/x b/x\nindex 1..2\n--- a/x\n+++ b/x\n\
+                              @@ -1,2 +1 @@\n-a\n b\n"),
+                        text("\u{1b}[33mcommit 4567def\u{1b}[m\n"),
+                        file("--- plain\n+++ plain\n@@ -1 +1 @@\n-c\n+d\n"),
+                        text("--- trailing\n")],
+                   chunks);
+    }
+
+    #[test]
+    fn parse_invalid_hunks() {
+        assert!(parse_unified("@@ -x +1 @@\n").is_err());
+        assert!(parse_unified("@@ -1,2 +1,2 @@\n a\n").is_err());
+    }
+}
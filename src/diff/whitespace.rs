@@ -0,0 +1,212 @@
+use ansi_term::ANSIString;
+use super::wrap::char_width;
+
+// The markers drawn in place of whitespace when it is being shown explicitly.
+pub const TAB_MARKER: char = '\u{2192}';
+pub const SPACE_MARKER: char = '\u{b7}';
+
+// The markers drawn at the end of each line when its line ending is shown.
+pub const CR_MARKER: char = '\u{240d}';
+pub const LF_MARKER: char = '\u{240a}';
+
+// Private use chars which stand in for the first and the remaining columns of a
+// tab being preserved while its line is laid out.
+pub const TAB_START: char = '\u{f8ff}';
+pub const TAB_FILL: char = '\u{f8fe}';
+
+// Replaces every tab, and every space trailing at the end of the line, with a
+// dimmed marker in the style of the span it replaces.
+pub fn show_whitespace(line: &[ANSIString]) -> Vec<ANSIString<'static>> {
+    let text: String = line.iter().map(|fragment| &**fragment).collect();
+    let trailing_start = text.trim_end_matches(' ').chars().count();
+
+    let mut shown = Vec::with_capacity(line.len());
+    let mut pos = 0;
+    for fragment in line {
+        let style = *fragment.style_ref();
+        let mut run = String::new();
+        for c in fragment.chars() {
+            let marker = match c {
+                '\t' => Some(TAB_MARKER),
+                ' ' if pos >= trailing_start => Some(SPACE_MARKER),
+                _ => None,
+            };
+            match marker {
+                Some(marker) => {
+                    if !run.is_empty() {
+                        shown.push(style.paint(std::mem::take(&mut run)));
+                    }
+                    shown.push(style.dimmed().paint(marker.to_string()));
+                },
+                None => run.push(c),
+            }
+            pos += 1;
+        }
+        if !run.is_empty() {
+            shown.push(style.paint(run));
+        }
+    }
+    shown
+}
+
+// Replaces every control char other than a tab with a symbol for it: one of
+// the Unicode control pictures for the ASCII control chars, and a replacement
+// character for the rest. Each takes up the single column it's measured to.
+pub fn show_controls(line: &[ANSIString]) -> Vec<ANSIString<'static>> {
+    line.iter().map(|fragment| {
+        let shown: String = fragment.chars().map(|c| match c {
+            '\t' => c,
+            '\u{0}'..='\u{1f}' => char::from_u32(0x2400 + c as u32).unwrap_or(c),
+            '\u{7f}' => '\u{2421}',
+            c if c.is_control() => '\u{fffd}',
+            c => c,
+        }).collect();
+        fragment.style_ref().paint(shown)
+    }).collect()
+}
+
+// Drops the CR of a CRLF line ending from the end of the line, which is part of
+// how the line ends rather than what it holds. It's only shown, as a marker,
+// when the line endings are.
+pub fn strip_line_ending<'a>(line: &[ANSIString<'a>]) -> Vec<ANSIString<'a>> {
+    let mut stripped = line.to_vec();
+    if let Some(last) = stripped.iter_mut().rev().find(|fragment| !fragment.is_empty()) {
+        if let Some(text) = last.strip_suffix('\r') {
+            *last = last.style_ref().paint(text.to_string());
+        }
+    }
+    stripped
+}
+
+// Ends each line of the text with markers for its line ending: a line which
+// still has the CR of a CRLF ending is ended with both a CR and an LF marker,
+// and any other with just an LF marker. The last line of a file gets one too,
+// whether or not it ended in a newline.
+pub fn mark_line_endings(text: &str) -> String {
+    text.split('\n').map(|line| match line.strip_suffix('\r') {
+        Some(line) => format!("{}{}{}", line, CR_MARKER, LF_MARKER),
+        None => format!("{}{}", line, LF_MARKER),
+    }).collect::<Vec<String>>().join("\n")
+}
+
+// Replaces every tab with the spaces up to the next multiple of `tab_width`
+// columns, so that the line takes up as many columns as it's measured to. When
+// preserving tabs, the columns are filled with TAB_START and TAB_FILL instead.
+pub fn expand_tabs(line: &[ANSIString], tab_width: usize, preserve: bool)
+        -> Vec<ANSIString<'static>> {
+    let mut expanded = Vec::with_capacity(line.len());
+    let mut column = 0;
+    for fragment in line {
+        let mut run = String::new();
+        for c in fragment.chars() {
+            if c == '\t' {
+                let spaces = tab_width - column % tab_width;
+                if preserve {
+                    run.push(TAB_START);
+                    run.extend(std::iter::repeat_n(TAB_FILL, spaces - 1));
+                } else {
+                    run.extend(std::iter::repeat_n(' ', spaces));
+                }
+                column += spaces;
+            } else {
+                run.push(c);
+                column += char_width(c);
+            }
+        }
+        expanded.push(fragment.style_ref().paint(run));
+    }
+    expanded
+}
+
+// Whether the line holds either of the chars which stand in for preserved tabs,
+// which would be turned into tabs themselves if its tabs were preserved.
+pub fn has_tab_placeholders(line: &[ANSIString]) -> bool {
+    line.iter().any(|fragment| fragment.contains([TAB_START, TAB_FILL]))
+}
+
+// Turns each expanded tab in a laid out row back into a tab. A tab which was
+// wrapped onto the row, so that the row starts part way through it, is kept as
+// a tab too.
+pub fn restore_tabs(row: &str) -> String {
+    let mut restored = String::with_capacity(row.len());
+    let mut in_tab = false;
+    for c in row.chars() {
+        match c {
+            TAB_START => restored.push('\t'),
+            TAB_FILL if !in_tab => restored.push('\t'),
+            TAB_FILL => {},
+            c => restored.push(c),
+        }
+        in_tab = c == TAB_START || c == TAB_FILL;
+    }
+    restored
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ansi_term::{ANSIStrings, Style};
+    use ansi_term::Color::Green;
+
+    #[test]
+    fn show_control_chars() {
+        let line = vec![Green.paint("a\tb\u{1b}[0m"), Style::default().paint("\r\u{7f}\u{85}")];
+        let shown = show_controls(&line);
+        assert_eq!("a\tb\u{241b}[0m\u{240d}\u{2421}\u{fffd}",
+                   ansi_term::unstyle(&ANSIStrings(&shown)));
+        assert_eq!(Green.normal(), *shown[0].style_ref());
+    }
+
+    #[test]
+    fn strip_crlf_ending() {
+        let line = vec![Green.paint("a\rb\r"), Style::default().paint("")];
+        let stripped = strip_line_ending(&line);
+        assert_eq!("a\rb", ansi_term::unstyle(&ANSIStrings(&stripped)));
+        assert_eq!(Green.normal(), *stripped[0].style_ref());
+        let line = vec![Style::default().paint("a\n")];
+        assert_eq!(line, strip_line_ending(&line));
+    }
+
+    #[test]
+    fn show_tabs_and_trailing_spaces() {
+        let line = vec![Style::default().paint("\tfoo bar  ")];
+        let shown = show_whitespace(&line);
+        assert_eq!("\u{2192}foo bar\u{b7}\u{b7}", ansi_term::unstyle(&ANSIStrings(&shown)));
+        assert_eq!(Style::default().dimmed(), *shown[0].style_ref());
+    }
+
+    #[test]
+    fn show_trailing_spaces_across_fragments() {
+        let line = vec![Green.paint("a "), Green.paint(" "), Green.paint("b  "), Green.paint(" ")];
+        let shown = show_whitespace(&line);
+        assert_eq!("a  b\u{b7}\u{b7}\u{b7}", ansi_term::unstyle(&ANSIStrings(&shown)));
+        assert_eq!(Green.dimmed(), *shown.last().unwrap().style_ref());
+    }
+
+    #[test]
+    fn mark_crlf_and_lf_endings() {
+        assert_eq!("a\u{240d}\u{240a}\nb\u{240a}\n\u{240a}", mark_line_endings("a\r\nb\n"));
+        assert_eq!("\u{240a}", mark_line_endings(""));
+    }
+
+    #[test]
+    fn expand_tabs_to_tab_stops() {
+        let line = vec![Style::default().paint("a\tb"), Green.paint("\u{4f60}\tc\t")];
+        let expanded = expand_tabs(&line, 4, false);
+        assert_eq!("a   b\u{4f60} c   ", ansi_term::unstyle(&ANSIStrings(&expanded)));
+        assert_eq!(Green.normal(), *expanded[1].style_ref());
+    }
+
+    #[test]
+    fn restore_preserved_tabs() {
+        let line = vec![Style::default().paint("a\tb\t\tc")];
+        let expanded = ansi_term::unstyle(&ANSIStrings(&expand_tabs(&line, 4, true)));
+        assert_eq!(13, expanded.chars().count());
+        assert_eq!("a\tb\t\tc", restore_tabs(&expanded));
+        // A tab wrapped over two rows leaves each row with a tab.
+        let rows: Vec<String> = vec![expanded.chars().take(6).collect(),
+                                     expanded.chars().skip(6).collect()];
+        assert_eq!(vec!["a\tb\t", "\t\tc"],
+                   rows.iter().map(|row| restore_tabs(row)).collect::<Vec<String>>());
+    }
+}
@@ -1,7 +1,9 @@
 use std::cmp::min;
 use std::iter::Iterator;
 use ansi_term::{ANSIString, ANSIStrings};
+use unicode_width::UnicodeWidthChar;
 
+#[allow(dead_code)]
 pub struct WrappedStrIter<'a> {
     s: &'a str,
     len: usize,
@@ -20,13 +22,14 @@ impl<'a> Iterator for WrappedStrIter<'a> {
         self.output_once = true;
         let start_pos = self.cur_pos;
         self.cur_pos = min(self.cur_pos + self.wrap_at, self.len);
-        return Some(&self.s[start_pos..self.cur_pos]);
+        Some(&self.s[start_pos..self.cur_pos])
     }
 }
 
+#[allow(dead_code)]
 pub fn wrap_str<'a>(s: &'a str, width: usize) -> WrappedStrIter<'a> {
     WrappedStrIter {
-        s: s,
+        s,
         len: s.len(),
         wrap_at: width,
         cur_pos: 0,
@@ -34,46 +37,124 @@ pub fn wrap_str<'a>(s: &'a str, width: usize) -> WrappedStrIter<'a> {
     }
 }
 
+// The number of columns the char takes up on a terminal: two for wide chars
+// such as CJK, none for combining marks and control chars.
+pub fn char_width(c: char) -> usize {
+    c.width().unwrap_or(0)
+}
+
+// The number of columns the line takes up when printed, with tabs expanded to
+// the next multiple of `tab_width` columns.
+pub fn display_width(line: &str, tab_width: usize) -> usize {
+    line.chars().fold(0, |width, c| match c {
+        '\t' => width + tab_width - width % tab_width,
+        c => width + char_width(c),
+    })
+}
+
+// Takes as many chars as fit in `width` columns, starting `start` chars into
+// the strings, and returns them along with how many chars and columns they
+// take. A wide char is never split over two rows so the substring may fall
+// short of the width, but at least one char is always taken so that wrapping
+// to a column narrower than a wide char still makes progress. Every fragment
+// of the substring keeps the style of the span it was taken from, so a span
+// which straddles a wrap point is styled the same on both rows.
+fn sub_string(start: usize, width: usize, strs: &ANSIStrings)
+        -> (Vec<ANSIString<'static>>, usize, usize) {
+    let mut vec = Vec::new();
+    let mut pos = start;
+    let mut chars = 0;
+    let mut columns = 0;
+    'fragments: for fragment in strs.0.iter() {
+        let mut taken = String::new();
+        for c in fragment.chars() {
+            if pos > 0 {
+                pos -= 1;
+                continue;
+            }
+            if chars > 0 && columns + char_width(c) > width {
+                if !taken.is_empty() {
+                    vec.push(fragment.style_ref().paint(taken));
+                }
+                break 'fragments;
+            }
+            taken.push(c);
+            chars += 1;
+            columns += char_width(c);
+        }
+        if !taken.is_empty() {
+            vec.push(fragment.style_ref().paint(taken));
+        }
+    }
+    (vec, chars, columns)
+}
+
+fn unstyled_len(strs: &ANSIStrings) -> usize {
+    strs.0.iter().map(|fragment| fragment.chars().count()).sum()
+}
+
+fn unstyled_width(strs: &ANSIStrings) -> usize {
+    strs.0.iter().map(|fragment| fragment.chars().map(char_width).sum::<usize>()).sum()
+}
+
 pub struct WrappedANSIStringsIter<'u> {
     s_ansi: ANSIStrings<'u>,
     unstyled_len: usize,
+    unstyled_width: usize,
     wrap_at: usize,
+    indent: usize,
     cur_pos: usize,
     output_once: bool,
 }
 
-impl<'s, 'u> Iterator for WrappedANSIStringsIter<'u> where 'u: 's  {
+impl<'u> WrappedANSIStringsIter<'u> {
+    // Indents every row after the first by the columns, narrowing them to
+    // match, though never so far as to leave no room for the strings.
+    pub fn indent(mut self, indent: usize) -> WrappedANSIStringsIter<'u> {
+        self.indent = min(indent, self.wrap_at.saturating_sub(1));
+        self
+    }
+}
+
+impl<'u> Iterator for WrappedANSIStringsIter<'u> {
     type Item = String;
 
     fn next(&mut self) -> Option<String> {
         if self.output_once && self.cur_pos >= self.unstyled_len {
             return None;
         }
+        let indent = if self.output_once { self.indent } else { 0 };
         self.output_once = true;
         let start_pos = self.cur_pos;
-        if self.unstyled_len <= self.wrap_at {
+        if self.unstyled_width <= self.wrap_at {
             self.cur_pos = self.unstyled_len;
-            let padding_required = self.wrap_at - self.unstyled_len;
-            let fmt = format!("{}{:w$}", self.s_ansi, "", w=padding_required);
-            return Some(fmt);
+            let padding_required = self.wrap_at - self.unstyled_width;
+            // The padding is repeated rather than formatted to a width, which
+            // is limited to u16::MAX and so too narrow beside a huge line.
+            let fmt = format!("{}{}", self.s_ansi, " ".repeat(padding_required));
+            Some(fmt)
         } else {
-            let split = ansi_term::sub_string(start_pos, self.wrap_at, &self.s_ansi);
-            let split_fmt = ANSIStrings(split.as_slice());
-            let split_len = ansi_term::unstyled_len(&split_fmt);
+            let width = self.wrap_at - indent;
+            let (split, split_len, split_width) = sub_string(start_pos, width, &self.s_ansi);
             self.cur_pos += split_len;
-            let padding_required = self.wrap_at - split_len;
-            let fmt = format!("{}{:w$}", split_fmt, "", w=padding_required);
-            return Some(fmt);
+            let padding_required = width.saturating_sub(split_width);
+            let fmt = format!("{}{}{}", " ".repeat(indent), ANSIStrings(split.as_slice()),
+                              " ".repeat(padding_required));
+            Some(fmt)
         }
     }
 }
 
+// Wraps the strings into rows `width` columns wide, each padded out to the
+// full width.
 pub fn wrap_ansistrings<'s, 'u>(s: &'s Vec<ANSIString<'u>>, width: usize)
         -> WrappedANSIStringsIter<'s> where 'u: 's {
     WrappedANSIStringsIter {
         s_ansi: ANSIStrings(s.as_slice()),
-        unstyled_len: ansi_term::unstyled_len(&ANSIStrings(s.as_slice())),
+        unstyled_len: unstyled_len(&ANSIStrings(s.as_slice())),
+        unstyled_width: unstyled_width(&ANSIStrings(s.as_slice())),
         wrap_at: width,
+        indent: 0,
         cur_pos: 0,
         output_once: false,
     }
@@ -83,12 +164,12 @@ pub fn wrap_ansistrings<'s, 'u>(s: &'s Vec<ANSIString<'u>>, width: usize)
 #[cfg(test)]
 mod tests {
     use super::*;
-    use ansi_term::Color::{Red, Green};
+    use ansi_term::Color::{Red, Green, Fixed};
 
     #[test]
     fn wrap_str_empty() {
         let s = "";
-        let wrapped: Vec<&str> = wrap_str(&s, 0).collect();
+        let wrapped: Vec<&str> = wrap_str(s, 0).collect();
         assert_eq!(1, wrapped.len());
         assert_eq!("", wrapped[0]);
     }
@@ -96,7 +177,7 @@ mod tests {
     #[test]
     fn wrap_str_single_line_under() {
         let s = "hello";
-        let wrapped: Vec<&str> = wrap_str(&s, 10).collect();
+        let wrapped: Vec<&str> = wrap_str(s, 10).collect();
         assert_eq!(1, wrapped.len());
         assert_eq!("hello", wrapped[0]);
     }
@@ -104,7 +185,7 @@ mod tests {
     #[test]
     fn wrap_str_single_line_exact() {
         let s = "hello";
-        let wrapped: Vec<&str> = wrap_str(&s, 5).collect();
+        let wrapped: Vec<&str> = wrap_str(s, 5).collect();
         assert_eq!(1, wrapped.len());
         assert_eq!("hello", wrapped[0]);
     }
@@ -112,7 +193,7 @@ mod tests {
     #[test]
     fn wrap_str_multi_line_under() {
         let s = "hello world";
-        let wrapped: Vec<&str> = wrap_str(&s, 6).collect();
+        let wrapped: Vec<&str> = wrap_str(s, 6).collect();
         assert_eq!(2, wrapped.len());
         assert_eq!("hello ", wrapped[0]);
         assert_eq!("world", wrapped[1]);
@@ -121,7 +202,7 @@ mod tests {
     #[test]
     fn wrap_str_multi_line_exact() {
         let s = "hello";
-        let wrapped: Vec<&str> = wrap_str(&s, 1).collect();
+        let wrapped: Vec<&str> = wrap_str(s, 1).collect();
         assert_eq!(5, wrapped.len());
         assert_eq!("h", wrapped[0]);
         assert_eq!("e", wrapped[1]);
@@ -178,4 +259,68 @@ mod tests {
         assert_eq!(5, wrapped.len());
         assert_eq!(s_fmt, wrapped);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn wrap_ansi_highlight_across_rows() {
+        let add_highlight = Fixed(157).reverse();
+        let s = vec![Green.paint("ab"), add_highlight.paint("cdefgh")];
+        let s_fmt = vec![format!("{}", ANSIStrings(&[Green.paint("ab"), add_highlight.paint("c")])),
+                         format!("{}", add_highlight.paint("def")),
+                         format!("{} ", add_highlight.paint("gh"))];
+        let wrapped: Vec<String> = wrap_ansistrings(&s, 3).collect();
+        assert_eq!(s_fmt, wrapped);
+    }
+
+    #[test]
+    fn wrap_ansi_multi_byte() {
+        let s = vec![Red.paint("h\u{e9}llo w\u{f6}rld")];
+        let s_fmt = vec![format!("{}", Red.paint("h\u{e9}ll")),
+                         format!("{}", Red.paint("o w\u{f6}")),
+                         format!("{} ", Red.paint("rld"))];
+        let wrapped: Vec<String> = wrap_ansistrings(&s, 4).collect();
+        assert_eq!(s_fmt, wrapped);
+    }
+
+    #[test]
+    fn wrap_ansi_wide_chars() {
+        let s = vec![Red.paint("\u{4f60}\u{597d}a\u{4e16}\u{754c}")];
+        let s_fmt = vec![format!("{}", Red.paint("\u{4f60}\u{597d}")),
+                         format!("{} ", Red.paint("a\u{4e16}")),
+                         format!("{}  ", Red.paint("\u{754c}"))];
+        let wrapped: Vec<String> = wrap_ansistrings(&s, 4).collect();
+        assert_eq!(s_fmt, wrapped);
+    }
+
+    #[test]
+    fn wrap_ansi_wide_char_wider_than_row() {
+        let s = vec![Red.paint("\u{4f60}\u{597d}")];
+        let s_fmt = vec![format!("{}", Red.paint("\u{4f60}")), format!("{}", Red.paint("\u{597d}"))];
+        let wrapped: Vec<String> = wrap_ansistrings(&s, 1).collect();
+        assert_eq!(s_fmt, wrapped);
+    }
+
+    #[test]
+    fn wrap_ansi_indented() {
+        let s = vec![Red.paint("abcdefg")];
+        let s_fmt = vec![format!("{}", Red.paint("abcd")),
+                         format!("  {}", Red.paint("ef")),
+                         format!("  {} ", Red.paint("g"))];
+        let wrapped: Vec<String> = wrap_ansistrings(&s, 4).indent(2).collect();
+        assert_eq!(s_fmt, wrapped);
+        // The indent leaves at least a column to wrap into.
+        let wrapped: Vec<String> = wrap_ansistrings(&s, 4).indent(10).collect();
+        assert_eq!(format!("   {}", Red.paint("e")), wrapped[1]);
+        let s = vec![Red.paint("abc")];
+        assert_eq!(vec![format!("{} ", Red.paint("abc"))],
+                   wrap_ansistrings(&s, 4).indent(2).collect::<Vec<String>>());
+    }
+
+    #[test]
+    fn display_width_tabs_and_wide_chars() {
+        assert_eq!(0, display_width("", 8));
+        assert_eq!(8, display_width("\t", 8));
+        assert_eq!(9, display_width("ab\tcd\tx", 4));
+        assert_eq!(6, display_width("\u{4f60}\u{597d}ab", 8));
+        assert_eq!(4, display_width("cafe\u{301}", 8));
+    }
+}
@@ -0,0 +1,226 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use glob::Pattern;
+
+// A file found when comparing two directories, by its path relative to them.
+#[derive(Debug, PartialEq)]
+pub enum DirEntry {
+    Both(PathBuf),
+    LeftOnly(PathBuf),
+    RightOnly(PathBuf),
+    // A file only in the left tree which is similar enough to one only in the
+    // right tree to have been moved there.
+    Renamed(PathBuf, PathBuf),
+}
+
+// Whether any of the patterns match the path relative to the tree's root, or
+// just its name (so that `target` excludes a directory of that name anywhere).
+fn is_excluded(relative: &Path, exclude: &[Pattern]) -> bool {
+    exclude.iter().any(|pattern| {
+        pattern.matches_path(relative)
+            || relative.file_name().is_some_and(|name| pattern.matches_path(Path::new(name)))
+    })
+}
+
+// Collects the paths of every file under the directory, relative to `root`,
+// leaving out the files and directories which are excluded.
+fn list_files(root: &Path, dir: &Path, exclude: &[Pattern], files: &mut BTreeSet<PathBuf>)
+        -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let relative = match path.strip_prefix(root) {
+            Ok(relative) => relative,
+            Err(_) => continue,
+        };
+        if is_excluded(relative, exclude) {
+            continue;
+        }
+        if path.is_dir() {
+            list_files(root, &path, exclude, files)?;
+        } else {
+            files.insert(relative.to_path_buf());
+        }
+    }
+    Ok(())
+}
+
+// Pairs up the files in two directory trees by their relative paths, in sorted
+// order.
+pub fn pair_files(left: &Path, right: &Path) -> io::Result<Vec<DirEntry>> {
+    pair_files_excluding(left, right, &[])
+}
+
+// Pairs up the files in two directory trees as pair_files does, but skipping
+// over any file or directory matching one of the patterns.
+pub fn pair_files_excluding(left: &Path, right: &Path, exclude: &[Pattern])
+        -> io::Result<Vec<DirEntry>> {
+    let mut files_l = BTreeSet::new();
+    let mut files_r = BTreeSet::new();
+    list_files(left, left, exclude, &mut files_l)?;
+    list_files(right, right, exclude, &mut files_r)?;
+    Ok(files_l.union(&files_r).map(|path| {
+        match (files_l.contains(path), files_r.contains(path)) {
+            (true, true) => DirEntry::Both(path.clone()),
+            (true, false) => DirEntry::LeftOnly(path.clone()),
+            _ => DirEntry::RightOnly(path.clone()),
+        }
+    }).collect())
+}
+
+// The lines of a file, where as for str::lines a trailing newline ends the last
+// line rather than starting an empty one.
+fn lines(content: &[u8]) -> Vec<&[u8]> {
+    if content.is_empty() {
+        return Vec::new();
+    }
+    content.strip_suffix(b"\n").unwrap_or(content).split(|&byte| byte == b'\n').collect()
+}
+
+// How alike two files are, from 0.0 to 1.0, by the proportion of their lines
+// which they have in common.
+fn similarity(left: &[u8], right: &[u8]) -> f64 {
+    if left.is_empty() && right.is_empty() {
+        return 1.0;
+    }
+    let mut counts: HashMap<&[u8], isize> = HashMap::new();
+    let (lines_l, lines_r) = (lines(left), lines(right));
+    for line in &lines_l {
+        *counts.entry(line).or_insert(0) += 1;
+    }
+    let mut common = 0;
+    for line in &lines_r {
+        if let Some(count) = counts.get_mut(line) {
+            if *count > 0 {
+                *count -= 1;
+                common += 1;
+            }
+        }
+    }
+    (2 * common) as f64 / (lines_l.len() + lines_r.len()) as f64
+}
+
+// Pairs up files found in only one tree or the other whose contents are at
+// least `threshold` similar, as renames. The most similar pairs are taken
+// first, and each file is part of at most one rename.
+pub fn detect_renames(left: &Path, right: &Path, entries: Vec<DirEntry>, threshold: f64)
+        -> io::Result<Vec<DirEntry>> {
+    let mut removed = Vec::new();
+    let mut added = Vec::new();
+    for entry in &entries {
+        match entry {
+            DirEntry::LeftOnly(path) => removed.push((path.clone(), fs::read(left.join(path))?)),
+            DirEntry::RightOnly(path) => added.push((path.clone(), fs::read(right.join(path))?)),
+            _ => {},
+        }
+    }
+    let mut candidates = Vec::new();
+    for (i, (_, content_l)) in removed.iter().enumerate() {
+        for (j, (_, content_r)) in added.iter().enumerate() {
+            let score = similarity(content_l, content_r);
+            if score >= threshold {
+                candidates.push((score, i, j));
+            }
+        }
+    }
+    candidates.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap().then((a.1, a.2).cmp(&(b.1, b.2))));
+    let mut renamed_to = HashMap::new();
+    let mut renamed_from = BTreeSet::new();
+    for (_, i, j) in candidates {
+        if !renamed_to.contains_key(&removed[i].0) && !renamed_from.contains(&added[j].0) {
+            renamed_to.insert(removed[i].0.clone(), added[j].0.clone());
+            renamed_from.insert(added[j].0.clone());
+        }
+    }
+    // Report each rename where the file was removed from.
+    Ok(entries.into_iter().filter_map(|entry| match entry {
+        DirEntry::LeftOnly(path) => match renamed_to.remove(&path) {
+            Some(to) => Some(DirEntry::Renamed(path, to)),
+            None => Some(DirEntry::LeftOnly(path)),
+        },
+        DirEntry::RightOnly(ref path) if renamed_from.contains(path) => None,
+        entry => Some(entry),
+    }).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pair_files_in_trees() {
+        let root = std::env::temp_dir().join(format!("jiff-dir-test-{}", std::process::id()));
+        let (left, right) = (root.join("left"), root.join("right"));
+        fs::create_dir_all(left.join("sub")).unwrap();
+        fs::create_dir_all(right.join("sub")).unwrap();
+        for path in &[left.join("a"), left.join("sub/b"), right.join("sub/b"), right.join("c")] {
+            fs::write(path, "x").unwrap();
+        }
+        let pairs = pair_files(&left, &right);
+        fs::remove_dir_all(&root).unwrap();
+        assert_eq!(vec![DirEntry::LeftOnly(PathBuf::from("a")),
+                        DirEntry::RightOnly(PathBuf::from("c")),
+                        DirEntry::Both(PathBuf::from("sub/b"))], pairs.unwrap());
+    }
+
+    #[test]
+    fn pair_files_excluding_patterns() {
+        let root = std::env::temp_dir().join(format!("jiff-exclude-test-{}", std::process::id()));
+        let (left, right) = (root.join("left"), root.join("right"));
+        fs::create_dir_all(left.join("sub/target")).unwrap();
+        fs::create_dir_all(right.join("target")).unwrap();
+        for path in &[left.join("a"), left.join("Cargo.lock"), left.join("sub/b.lock"),
+                      left.join("sub/target/c"), right.join("a"), right.join("target/c")] {
+            fs::write(path, "x").unwrap();
+        }
+        let exclude = [Pattern::new("*.lock").unwrap(), Pattern::new("target").unwrap()];
+        let pairs = pair_files_excluding(&left, &right, &exclude);
+        fs::remove_dir_all(&root).unwrap();
+        assert_eq!(vec![DirEntry::Both(PathBuf::from("a"))], pairs.unwrap());
+    }
+
+    #[test]
+    fn pair_files_excluding_recursive_pattern() {
+        let root = std::env::temp_dir().join(format!("jiff-deep-exclude-{}", std::process::id()));
+        let (left, right) = (root.join("left"), root.join("right"));
+        fs::create_dir_all(left.join("sub/deep/target")).unwrap();
+        fs::create_dir_all(right.join("target")).unwrap();
+        for path in &[left.join("sub/a"), left.join("sub/deep/target/c"), right.join("sub-target"),
+                      right.join("target/c")] {
+            fs::write(path, "x").unwrap();
+        }
+        let pairs = pair_files_excluding(&left, &right, &[Pattern::new("**/target").unwrap()]);
+        fs::remove_dir_all(&root).unwrap();
+        assert_eq!(vec![DirEntry::LeftOnly(PathBuf::from("sub/a")),
+                        DirEntry::RightOnly(PathBuf::from("sub-target"))],
+                   pairs.unwrap());
+    }
+
+    #[test]
+    fn similarity_by_common_lines() {
+        assert_eq!(1.0, similarity(b"a\nb\n", b"a\nb\n"));
+        assert_eq!(0.75, similarity(b"a\nb\nc\nd", b"a\nb\nc\nx"));
+        assert_eq!(0.0, similarity(b"a", b"b"));
+        assert_eq!(0.0, similarity(b"alpha\n", b"beta\n"));
+        assert_eq!(0.0, similarity(b"", b"a\n"));
+    }
+
+    #[test]
+    fn detect_renames_pairs_most_similar() {
+        let root = std::env::temp_dir().join(format!("jiff-rename-test-{}", std::process::id()));
+        let (left, right) = (root.join("left"), root.join("right"));
+        fs::create_dir_all(&left).unwrap();
+        fs::create_dir_all(&right).unwrap();
+        fs::write(left.join("x.rs"), "a\nb\nc\nd\n").unwrap();
+        fs::write(left.join("gone"), "1\n2\n").unwrap();
+        fs::write(right.join("y.rs"), "a\nb\nc\nz\n").unwrap();
+        fs::write(right.join("z.rs"), "a\nq\nr\ns\n").unwrap();
+        let entries = pair_files(&left, &right)
+            .and_then(|entries| detect_renames(&left, &right, entries, 0.5));
+        fs::remove_dir_all(&root).unwrap();
+        assert_eq!(vec![DirEntry::LeftOnly(PathBuf::from("gone")),
+                        DirEntry::Renamed(PathBuf::from("x.rs"), PathBuf::from("y.rs")),
+                        DirEntry::RightOnly(PathBuf::from("z.rs"))], entries.unwrap());
+    }
+}
@@ -0,0 +1,110 @@
+// The errors which can stop jiff from diffing, each worded so as to say what
+// went wrong and with which file.
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum JiffError {
+    // A file (or stdin, as "-") couldn't be opened or read.
+    Read { path: String, source: io::Error },
+    // A file's contents weren't valid in the encoding they were read as.
+    Decode { path: String, encoding: &'static str },
+    // A file which looked to be gzipped couldn't be decompressed.
+    Decompress { path: String, message: String },
+    // A git revision didn't name a commit.
+    UnknownRevision { rev: String },
+    // git couldn't be run, or failed to show a file at a revision.
+    Git { rev: String, path: String, message: String },
+    // A file couldn't be read as a zip archive.
+    Zip { path: String, message: String },
+    // A zip archive has no file of the name.
+    MissingZipEntry { path: String, entry: String },
+    // A line range given to an option wasn't of the form FIRST:LAST.
+    InvalidRange { option: String, range: String },
+    // A line of a list of file pairs wasn't of the form left<TAB>right.
+    InvalidPairList { path: String, lineno: usize },
+    // A unified diff couldn't be parsed.
+    Parse { path: String, message: String },
+}
+
+impl JiffError {
+    pub(crate) fn read(path: &str, source: io::Error) -> JiffError {
+        JiffError::Read { path: path.to_string(), source }
+    }
+
+    // The status jiff exits with, which as for diff is 2 for any trouble
+    // rather than 1, which means that the files differ.
+    pub fn exit_code(&self) -> i32 {
+        2
+    }
+}
+
+impl fmt::Display for JiffError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            JiffError::Read { path, source } if path == "-" => {
+                write!(f, "Could not read stdin: {}", source)
+            },
+            JiffError::Read { path, source } => write!(f, "Could not read {}: {}", path, source),
+            JiffError::Decode { path, encoding } => {
+                write!(f, "Could not read {}: not valid {} (try a different --encoding)",
+                       path, encoding)
+            },
+            JiffError::Decompress { path, message } => {
+                write!(f, "Could not decompress {}: {} (try --no-decompress)", path, message)
+            },
+            JiffError::UnknownRevision { rev } => {
+                write!(f, "Unknown git revision {} (expected a commit, branch or tag)", rev)
+            },
+            JiffError::Git { rev, path, message } => {
+                write!(f, "Could not read {} at {}: {}", path, rev, message)
+            },
+            JiffError::Zip { path, message } => {
+                write!(f, "Could not read {} as a zip archive: {}", path, message)
+            },
+            JiffError::MissingZipEntry { path, entry } => write!(f, "No {} in {}", entry, path),
+            JiffError::InvalidRange { option, range } => {
+                write!(f, "Invalid {} value: {} (expected FIRST:LAST, counting from 1)",
+                       option, range)
+            },
+            JiffError::InvalidPairList { path, lineno } => {
+                write!(f, "Invalid entry on line {} of {}: expected \"left<TAB>right\"",
+                       lineno, path)
+            },
+            JiffError::Parse { path, message } => write!(f, "Could not parse {}: {}", path, message),
+        }
+    }
+}
+
+impl Error for JiffError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            JiffError::Read { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_errors() {
+        let error = JiffError::read("a.txt", io::Error::new(io::ErrorKind::NotFound, "no such file"));
+        assert_eq!("Could not read a.txt: no such file", error.to_string());
+        assert!(error.source().is_some());
+        let error = JiffError::read("-", io::Error::other("closed"));
+        assert_eq!("Could not read stdin: closed", error.to_string());
+        let error = JiffError::Decode { path: "a.txt".to_string(), encoding: "UTF-8" };
+        assert_eq!("Could not read a.txt: not valid UTF-8 (try a different --encoding)",
+                   error.to_string());
+        assert!(error.source().is_none());
+        let error = JiffError::InvalidRange { option: "--left-range".to_string(),
+                                              range: "3".to_string() };
+        assert_eq!("Invalid --left-range value: 3 (expected FIRST:LAST, counting from 1)",
+                   error.to_string());
+        assert_eq!(2, error.exit_code());
+    }
+}
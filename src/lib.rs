@@ -0,0 +1,333 @@
+pub mod diff;
+pub mod dir;
+pub mod error;
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::process::{self, Command};
+use encoding_rs::Encoding;
+use flate2::read::MultiGzDecoder;
+use zip::ZipArchive;
+use diff::{Diff, DiffOptions, RenderOptions};
+pub use error::JiffError;
+
+// Reads the whole of a file to be diffed, or of stdin if the path is "-". The
+// file is read until it ends rather than for as long as it claims to be, so
+// that named pipes and process substitutions (such as jiff <(cmd1) <(cmd2)),
+// which report a size of zero, work too. Gzipped files are decompressed.
+pub fn read_source(path: &str) -> Result<String, JiffError> {
+    read_source_with(path, encoding_rs::UTF_8, true)
+}
+
+// Reads a file to be diffed just as read_source does, but decoding it from the
+// given encoding rather than UTF-8. Contents which aren't valid in the encoding
+// are an error, rather than being silently replaced.
+pub fn read_source_with_encoding(path: &str, encoding: &'static Encoding)
+                                 -> Result<String, JiffError> {
+    read_source_with(path, encoding, true)
+}
+
+// Reads a file to be diffed in the given encoding, decompressing it first if it
+// is gzipped (as its name ending in .gz or its contents starting with gzip's
+// magic bytes say) unless asked not to.
+pub fn read_source_with(path: &str, encoding: &'static Encoding, decompress: bool)
+                        -> Result<String, JiffError> {
+    let content = read_source_bytes(path, decompress)?;
+    decode(&content, encoding)
+        .ok_or_else(|| JiffError::Decode { path: path.to_string(), encoding: encoding.name() })
+}
+
+// Reads a file to be diffed as read_source_with does, but without decoding it,
+// for telling whether it's binary first.
+pub fn read_source_bytes(path: &str, decompress: bool) -> Result<Vec<u8>, JiffError> {
+    let mut content = Vec::new();
+    open_source(path)
+        .and_then(|mut source| source.read_to_end(&mut content))
+        .map_err(|error| JiffError::read(path, error))?;
+    if decompress && (path.ends_with(".gz") || content.starts_with(&GZIP_MAGIC)) {
+        content = gunzip(&content).map_err(|error| JiffError::Decompress {
+            path: path.to_string(),
+            message: error.to_string(),
+        })?;
+    }
+    Ok(content)
+}
+
+// The bytes every gzip file starts with.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+// Decompresses every member of gzipped contents, as zcat does, so that files
+// which were appended to with gzip still read in full.
+fn gunzip(content: &[u8]) -> io::Result<Vec<u8>> {
+    let mut decompressed = Vec::new();
+    MultiGzDecoder::new(content).read_to_end(&mut decompressed)?;
+    Ok(decompressed)
+}
+
+fn decode(content: &[u8], encoding: &'static Encoding) -> Option<String> {
+    encoding.decode_without_bom_handling_and_without_replacement(content)
+        .map(|decoded| decoded.into_owned())
+}
+
+fn open_source(path: &str) -> io::Result<Box<dyn Read>> {
+    if path == "-" {
+        Ok(Box::new(io::stdin().lock()))
+    } else {
+        Ok(Box::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+fn git(args: &[&str]) -> io::Result<process::Output> {
+    Command::new("git").args(args).output()
+}
+
+// Reads a file as it was at the given git revision, or None if it did not exist
+// then. The path is relative to the current directory.
+pub fn read_git_revision(rev: &str, path: &str) -> Result<Option<String>, JiffError> {
+    let git_error = |message: String| JiffError::Git {
+        rev: rev.to_string(),
+        path: path.to_string(),
+        message,
+    };
+    let git = |args: &[&str]| git(args).map_err(|error| git_error(error.to_string()));
+    let commit = format!("{}^{{commit}}", rev);
+    if !git(&["rev-parse", "--verify", "--quiet", &commit])?.status.success() {
+        return Err(JiffError::UnknownRevision { rev: rev.to_string() });
+    }
+    let object = format!("{}:./{}", rev, path);
+    if !git(&["cat-file", "-e", &object])?.status.success() {
+        return Ok(None);
+    }
+    let output = git(&["cat-file", "blob", &object])?;
+    if !output.status.success() {
+        return Err(git_error(String::from_utf8_lossy(&output.stderr).trim().to_string()));
+    }
+    String::from_utf8(output.stdout)
+        .map(Some)
+        .map_err(|_| git_error("not valid UTF-8".to_string()))
+}
+
+// Diffs two texts given as raw bytes (such as the output of processes) by line,
+// without them having to be valid UTF-8. Each invalid sequence is decoded as a
+// U+FFFD replacement character first, so lines which differ only in their
+// invalid bytes compare as the same, and every one is shown as U+FFFD.
+pub fn calculate_line_diff_bytes(left: &[u8], right: &[u8], opts: &DiffOptions) -> Vec<Diff> {
+    diff::calculate_line_diff(&String::from_utf8_lossy(left), &String::from_utf8_lossy(right), opts)
+}
+
+// Diffs two texts by line and renders the diff just as jiff prints it, in
+// color unless the render options turn it off.
+pub fn render_diff_to_string(left: &str, right: &str, diff_opts: &DiffOptions,
+                             render_opts: &RenderOptions) -> String {
+    let diffs = diff::calculate_line_diff(left, right, diff_opts);
+    let mut rendered = Vec::new();
+    diff::write_diffs(&mut rendered, &diffs, render_opts).expect("writing to a Vec can't fail");
+    String::from_utf8(rendered).expect("the diff of two strs is UTF-8")
+}
+
+// The same as render_diff_to_string, but rendered side by side.
+pub fn render_diff_side_by_side_to_string(left: &str, right: &str, diff_opts: &DiffOptions,
+                                          render_opts: &RenderOptions) -> String {
+    let diffs = diff::calculate_line_diff(left, right, diff_opts);
+    let mut rendered = Vec::new();
+    diff::write_diffs_side_by_side(&mut rendered, &diffs, render_opts)
+        .expect("writing to a Vec can't fail");
+    String::from_utf8(rendered).expect("the diff of two strs is UTF-8")
+}
+
+// How a line changed, as jiff sees it when pairing up the lines of a
+// replacement.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineChange {
+    Identical,
+    // The lines differ only in whitespace (including in how much of it there
+    // is), which jiff can be asked not to highlight.
+    Whitespace,
+    Substantive,
+}
+
+// Classifies the change from one line to another by diffing them by grapheme
+// cluster, just as jiff does to highlight the changes within a replaced line.
+pub fn classify_line_change(before: &str, after: &str) -> LineChange {
+    if before == after {
+        return LineChange::Identical;
+    }
+    if diff::only_whitespace_changed(&diff::calculate_char_diff(before, after)) {
+        LineChange::Whitespace
+    } else {
+        LineChange::Substantive
+    }
+}
+
+// Whether the contents are binary rather than text, as when they contain a NUL
+// byte (which text practically never does) or aren't valid UTF-8.
+pub fn is_binary(content: &[u8]) -> bool {
+    content.contains(&0) || std::str::from_utf8(content).is_err()
+}
+
+fn open_zip(path: &str) -> Result<ZipArchive<BufReader<File>>, JiffError> {
+    let file = File::open(path).map_err(|error| JiffError::read(path, error))?;
+    ZipArchive::new(BufReader::new(file)).map_err(|error| zip_error(path, error))
+}
+
+fn zip_error(path: &str, error: zip::result::ZipError) -> JiffError {
+    JiffError::Zip { path: path.to_string(), message: error.to_string() }
+}
+
+// Reads one file from a zip archive, or None if the archive has no such file.
+pub fn read_zip_entry(path: &str, name: &str) -> Result<Option<Vec<u8>>, JiffError> {
+    let mut archive = open_zip(path)?;
+    let mut entry = match archive.by_name(name) {
+        Ok(entry) if entry.is_file() => entry,
+        Ok(_) | Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+        Err(error) => return Err(zip_error(path, error)),
+    };
+    let mut content = Vec::new();
+    entry.read_to_end(&mut content).map_err(|error| JiffError::read(path, error))?;
+    Ok(Some(content))
+}
+
+// Reads every file in a zip archive, keyed by its name. Directories are left
+// out, as they have no contents to diff.
+pub fn read_zip_entries(path: &str) -> Result<BTreeMap<String, Vec<u8>>, JiffError> {
+    let mut archive = open_zip(path)?;
+    let mut entries = BTreeMap::new();
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i).map_err(|error| zip_error(path, error))?;
+        if !entry.is_file() {
+            continue;
+        }
+        let mut content = Vec::new();
+        entry.read_to_end(&mut content).map_err(|error| JiffError::read(path, error))?;
+        entries.insert(entry.name().to_string(), content);
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::Write;
+    use std::thread;
+
+    #[test]
+    fn read_source_from_named_pipe() {
+        let fifo = std::env::temp_dir().join(format!("jiff-fifo-test-{}", std::process::id()));
+        assert!(Command::new("mkfifo").arg(&fifo).status().unwrap().success());
+        let writer_path = fifo.clone();
+        let writer = thread::spawn(move || {
+            let mut pipe = fs::OpenOptions::new().write(true).open(writer_path).unwrap();
+            for i in 0..1000 {
+                writeln!(pipe, "line {}", i).unwrap();
+            }
+        });
+        let content = read_source(fifo.to_str().unwrap());
+        writer.join().unwrap();
+        fs::remove_file(&fifo).unwrap();
+        let content = content.unwrap();
+        assert_eq!(1000, content.lines().count());
+        assert!(content.ends_with("line 999\n"));
+    }
+
+    #[test]
+    fn line_diff_bytes_replaces_invalid_utf8() {
+        let same = |text: &str| Diff::Same(text.to_string());
+        let diffs = calculate_line_diff_bytes(b"a\n\xff\nb\n", b"a\n\xfe\nc\n", &DiffOptions::new());
+        assert_eq!(vec![same("a\n\u{fffd}"), Diff::Replace("b".to_string(), "c".to_string())],
+                   diffs);
+    }
+
+    #[test]
+    fn render_diff() {
+        let render_opts = RenderOptions::new().color(false);
+        assert_eq!("  a\n- b\n+ c\n",
+                   render_diff_to_string("a\nb\n", "a\nc\n", &DiffOptions::new(), &render_opts));
+        let render_opts = render_opts.width(Some(17)).separator("|");
+        assert_eq!("1: a    |1: a    \n2: b    |2: c    \n",
+                   render_diff_side_by_side_to_string("a\nb\n", "a\nc\n", &DiffOptions::new(),
+                                                      &render_opts));
+    }
+
+    #[test]
+    fn decode_encodings() {
+        let latin1 = b"caf\xe9\n";
+        assert_eq!(Some("caf\u{e9}\n".to_string()), decode(latin1, encoding_rs::WINDOWS_1252));
+        assert_eq!(None, decode(latin1, encoding_rs::UTF_8));
+    }
+
+    #[test]
+    fn read_source_errors() {
+        let path = std::env::temp_dir().join(format!("jiff-latin1-test-{}", std::process::id()));
+        fs::write(&path, b"caf\xe9\n").unwrap();
+        let path_str = path.to_str().unwrap();
+        let utf8 = read_source(path_str);
+        let utf16 = read_source_with_encoding(path_str, encoding_rs::UTF_16LE);
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(utf8, Err(JiffError::Decode { encoding: "UTF-8", .. })));
+        assert!(matches!(utf16, Err(JiffError::Decode { encoding: "UTF-16LE", .. })));
+        assert!(matches!(read_source(path_str), Err(JiffError::Read { .. })));
+    }
+
+    #[test]
+    fn read_gzipped_source() {
+        let path = std::env::temp_dir().join(format!("jiff-gzip-test-{}.gz", std::process::id()));
+        let path_str = path.to_str().unwrap();
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(b"a\nb\n").unwrap();
+        fs::write(&path, encoder.finish().unwrap()).unwrap();
+        let decompressed = read_source(path_str);
+        let raw = read_source_with(path_str, encoding_rs::WINDOWS_1252, false);
+        fs::write(&path, b"not gzipped\n").unwrap();
+        let corrupt = read_source(path_str);
+        fs::remove_file(&path).unwrap();
+        assert_eq!("a\nb\n", decompressed.unwrap());
+        assert!(raw.unwrap().starts_with("\u{1f}\u{2039}"));
+        assert!(matches!(corrupt, Err(JiffError::Decompress { .. })));
+    }
+
+    #[test]
+    fn classify_line_changes() {
+        assert_eq!(LineChange::Identical, classify_line_change("let a = 1;", "let a = 1;"));
+        assert_eq!(LineChange::Identical, classify_line_change("", ""));
+        assert_eq!(LineChange::Whitespace, classify_line_change("let a = 1;", "let  a = 1;"));
+        assert_eq!(LineChange::Whitespace, classify_line_change("\tlet a = 1;", "    let a = 1; "));
+        assert_eq!(LineChange::Whitespace, classify_line_change("", " "));
+        assert_eq!(LineChange::Substantive, classify_line_change("let a = 1;", "let a = 2;"));
+        assert_eq!(LineChange::Substantive, classify_line_change("let a = 1;", "leta = 1;x"));
+        assert_eq!(LineChange::Substantive, classify_line_change("", "x"));
+    }
+
+    #[test]
+    fn detect_binary() {
+        assert!(!is_binary(b""));
+        assert!(!is_binary("caf\u{e9}\n".as_bytes()));
+        assert!(is_binary(b"a\0b"));
+        assert!(is_binary(b"\xff\xfe"));
+    }
+
+    #[test]
+    fn read_zip() {
+        let path = std::env::temp_dir().join(format!("jiff-zip-test-{}.zip", std::process::id()));
+        let mut writer = zip::ZipWriter::new(File::create(&path).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+        writer.add_directory("dir/", options).unwrap();
+        writer.start_file("dir/a.txt", options).unwrap();
+        writer.write_all(b"a\n").unwrap();
+        writer.start_file("b.txt", options).unwrap();
+        writer.write_all(b"b\n").unwrap();
+        writer.finish().unwrap();
+
+        let path_str = path.to_str().unwrap();
+        let entries = read_zip_entries(path_str);
+        let entry = read_zip_entry(path_str, "dir/a.txt");
+        let missing = read_zip_entry(path_str, "dir/");
+        fs::remove_file(&path).unwrap();
+        let entries: Vec<(String, Vec<u8>)> = entries.unwrap().into_iter().collect();
+        assert_eq!(vec![("b.txt".to_string(), b"b\n".to_vec()),
+                        ("dir/a.txt".to_string(), b"a\n".to_vec())], entries);
+        assert_eq!(Some(b"a\n".to_vec()), entry.unwrap());
+        assert_eq!(None, missing.unwrap());
+    }
+}
@@ -1,17 +1,787 @@
-mod diff;
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::Once;
+use clap::{Arg, App, ArgMatches};
+use encoding_rs::Encoding;
+use glob::Pattern;
+use jiff::diff;
+use jiff::dir::{self, DirEntry};
+use jiff::JiffError;
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
+use jiff::diff::{Algorithm, ColorMoved, DiffOptions, DiffStats, Emphasis, Granularity,
+                 HighlightStyle, RenderOptions, Side, StreamingDiff, Tabs, Theme};
+use regex::Regex;
 
-use std::cmp::max;
-use std::fs;
-use std::process;
-use clap::{Arg, App};
+mod config;
+mod pager;
+
+// Lines starting with an identifier, as for diff's --show-c-function.
+const DEFAULT_FUNCTION_REGEX: &str = r"^[[:alpha:]$_]";
+
+// Whether an option which defaults to off is on, by the last of --NAME and
+// --no-NAME given, or else as set in the config file.
+fn switch(matches: &ArgMatches, name: &str, config: Option<bool>) -> bool {
+    if matches.is_present(name) {
+        true
+    } else if matches.is_present(format!("no-{}", name)) {
+        false
+    } else {
+        config.unwrap_or(false)
+    }
+}
+
+// Reports an error and exits with the status for it.
+fn die(error: JiffError) -> ! {
+    eprintln!("{}", error);
+    pager::exit(error.exit_code());
+}
 
 fn read_file_or_die(path: &str) -> String {
-    match fs::read_to_string(path) {
-        Ok(content) => content,
-        Err(ref error)  => {
-            eprintln!("Could not read {}: {}", path, error);
-            process::exit(1);
-        },
+    jiff::read_source(path).unwrap_or_else(|error| die(error))
+}
+
+// Reads a file to be diffed, decoding it from the encoding it was said to be in
+// and decompressing it if it's gzipped, unless asked not to.
+fn read_input_or_die(path: &str, settings: &Settings) -> String {
+    let encoding = settings.encoding.unwrap_or(encoding_rs::UTF_8);
+    jiff::read_source_with(path, encoding, settings.decompress)
+        .unwrap_or_else(|error| die(error))
+}
+
+fn open_file_or_die(path: &str) -> BufReader<File> {
+    match File::open(path) {
+        Ok(file) => BufReader::new(file),
+        Err(error) => die(JiffError::Read { path: path.to_string(), source: error }),
+    }
+}
+
+fn read_pair_list_or_die(path: &str) -> Vec<(String, String)> {
+    let list = read_file_or_die(path);
+    let mut pairs = Vec::new();
+    for (lineno, line) in list.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let mut paths = line.splitn(2, '\t');
+        match (paths.next(), paths.next()) {
+            (Some(lpath), Some(rpath)) => {
+                pairs.push((lpath.to_string(), rpath.to_string()));
+            },
+            _ => die(JiffError::InvalidPairList { path: path.to_string(), lineno: lineno + 1 }),
+        }
+    }
+    pairs
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Stat {
+    Off,
+    // Print the summary after the diff.
+    On,
+    // Print the summary instead of the diff.
+    Only,
+}
+
+// The order files in directories are printed in.
+#[derive(Clone, Copy, PartialEq)]
+enum SortBy {
+    Path,
+    // The most changed lines first.
+    Size,
+    // The fewest changed lines first.
+    SizeAscending,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Text,
+    // One JSON operation per changed line.
+    JsonPatch,
+    // One line per changed file of its added and removed line counts, as for
+    // git diff --numstat.
+    Numstat,
+}
+
+// Everything needed to diff and print each pair of files, as given on the
+// command line.
+struct Settings {
+    format: Format,
+    side_by_side: bool,
+    // Print side by side even when the columns would be too narrow to read.
+    force_side_by_side: bool,
+    // Diff whole files character by character rather than line by line.
+    chars: bool,
+    // Say so when files are identical, rather than printing nothing.
+    report_identical: bool,
+    // Print nothing at all, stopping at the first difference found.
+    quiet: bool,
+    // Syntax highlight unchanged lines in the language of each file.
+    syntax: bool,
+    // The encoding of the files, if not UTF-8.
+    encoding: Option<&'static Encoding>,
+    // Decompress gzipped files before diffing them.
+    decompress: bool,
+    // Head each file's diff with the names of the files.
+    header: bool,
+    // The line heading each file's diff in directory mode, with {path}, {left}
+    // and {right} standing for the file's path in the trees and on each side.
+    header_format: String,
+    // Print how the lines of each replacement were aligned to stderr.
+    dump_alignment: bool,
+    // Print only the blocks of lines which moved, not the other changes.
+    moves_only: bool,
+    reverse: bool,
+    streaming: bool,
+    stat: Stat,
+    left_range: Option<(usize, usize)>,
+    right_range: Option<(usize, usize)>,
+    // Files in directories which are only reported as differing, not diffed.
+    skip_globs: Vec<Pattern>,
+    sort_by: SortBy,
+    // Files and directories which are left out of directory diffs altogether.
+    exclude_globs: Vec<Pattern>,
+    // How many files in directories to diff at once, or None for one per CPU.
+    jobs: Option<usize>,
+    // How similar files in directories must be to be paired as renames, or None
+    // to not look for renames.
+    rename_threshold: Option<f64>,
+    diff_opts: DiffOptions,
+    render_opts: RenderOptions,
+}
+
+// The options to print the diff of a file with, which is highlighted in the
+// language its extension (or failing that its name) suggests.
+fn render_opts_for(path: &str, settings: &Settings) -> RenderOptions {
+    if !settings.syntax {
+        return settings.render_opts.clone();
+    }
+    let path = Path::new(path);
+    let language = path.extension().or_else(|| path.file_name()).and_then(|name| name.to_str());
+    settings.render_opts.clone().syntax(language)
+}
+
+// The options to print the diff of two files with, headed with their names
+// unless asked not to.
+fn with_header(render_opts: RenderOptions, lname: &str, rname: &str, settings: &Settings)
+        -> RenderOptions {
+    if settings.header {
+        render_opts.header(lname, rname)
+    } else {
+        render_opts
+    }
+}
+
+fn print_diffs(diffs: &[diff::Diff], render_opts: &RenderOptions, settings: &Settings) {
+    if settings.stat != Stat::Only {
+        if settings.moves_only {
+            diff::print_moves(&diff::detect_moves(diffs, render_opts.start_line), render_opts);
+        } else if settings.format == Format::JsonPatch {
+            diff::print_json_patch(diffs, render_opts);
+        } else if settings.side_by_side
+                && (settings.force_side_by_side || diff::fits_side_by_side(diffs, render_opts)) {
+            diff::print_diffs_side_by_side(diffs, render_opts);
+        } else {
+            if settings.side_by_side {
+                note_too_narrow();
+            }
+            diff::print_diffs(diffs, render_opts);
+        }
+    }
+}
+
+// Says, just the once, that side-by-side output was too narrow to print.
+fn note_too_narrow() {
+    static NOTED: Once = Once::new();
+    NOTED.call_once(|| {
+        eprintln!("Note: the terminal is too narrow for side-by-side output, so printing it \
+                   unified instead (use --force-side-by-side to print it anyway)");
+    });
+}
+
+// Prints an existing unified diff, read from the path, as if jiff had produced
+// it.
+fn print_patch(patch: &str, path: &str, settings: &Settings) {
+    let files = diff::parse_unified(patch).unwrap_or_else(|error| {
+        die(JiffError::Parse { path: path.to_string(), message: error.to_string() })
+    });
+    print_patch_files(&files, settings);
+}
+
+fn print_patch_files(files: &[diff::PatchFile], settings: &Settings) {
+    for file in files {
+        diff::print_patch_header(&file.header, &settings.render_opts);
+        let fits = settings.force_side_by_side
+            || diff::hunks_fit_side_by_side(&file.hunks, &settings.render_opts);
+        if settings.side_by_side && fits {
+            diff::print_hunks_side_by_side(&file.hunks, &settings.render_opts);
+        } else {
+            if settings.side_by_side {
+                note_too_narrow();
+            }
+            diff::print_hunks(&file.hunks, &settings.render_opts);
+        }
+    }
+}
+
+// Prints whatever git pipes to jiff as its pager (with core.pager set to jiff
+// --git-diff). A diff, which git will have colored itself, is printed as if
+// jiff had produced it, and anything else (such as the output of git log or
+// git branch) is passed through untouched, as is a diff which can't be parsed.
+// Lines are passed on as they're read, and each file's diff as soon as it ends.
+fn page_git_output(settings: &Settings) -> ! {
+    let stdin = io::stdin();
+    for chunk in diff::PatchChunks::new(stdin.lock()) {
+        match chunk {
+            Ok(diff::PatchChunk::Text(text)) => print!("{}", text),
+            Ok(diff::PatchChunk::File(text)) => {
+                match diff::parse_unified(&diff::strip_colors(&text)) {
+                    Ok(files) => print_patch_files(&files, settings),
+                    Err(_) => print!("{}", text),
+                }
+            },
+            Err(error) => {
+                eprintln!("Could not read stdin: {}", error);
+                pager::exit(2);
+            },
+        }
+    }
+    pager::exit(0);
+}
+
+// Diffs and prints a single pair of files a window at a time, without ever
+// holding either file in memory, returning whether they differ.
+fn diff_files_streaming(lpath: &str, rpath: &str, settings: &Settings) -> bool {
+    let lfile = open_file_or_die(lpath);
+    let rfile = open_file_or_die(rpath);
+    let mut differ = false;
+    let mut stats = DiffStats::default();
+    let (mut lineno_l, mut lineno_r) = (1, 1);
+    // The changes still to be printed if they're limited, which are counted
+    // down across the windows until some have to be omitted.
+    let mut changes_left = settings.render_opts.max_changes;
+    let mut omitted = false;
+    // Whether the header has been printed, ahead of the first window's changes.
+    let mut headed = false;
+    let windows = StreamingDiff::new(lfile, rfile, diff::DEFAULT_WINDOW,
+                                     settings.diff_opts.clone());
+    for diffs in windows {
+        let diffs = diffs.unwrap_or_else(|error| {
+            eprintln!("Could not read {} or {}: {}", lpath, rpath, error);
+            pager::exit(2);
+        });
+        if settings.quiet {
+            if diff::has_changes(&diffs) {
+                return true;
+            }
+            continue;
+        }
+        if omitted && settings.stat == Stat::Off {
+            // Nothing more will be printed and the files are known to differ.
+            return true;
+        }
+        let changed = diff::has_changes(&diffs);
+        // A window without changes has nothing to show, not even context.
+        if !omitted && changed {
+            let mut render_opts = render_opts_for(rpath, settings).start_line(lineno_l, lineno_r)
+                                                                  .max_changes(changes_left);
+            if !headed {
+                render_opts = with_header(render_opts, lpath, rpath, settings);
+            }
+            print_diffs(&diffs, &render_opts, settings);
+            let changes = diff::count_changes(&diffs);
+            headed |= changes > 0;
+            omitted = changes_left.is_some_and(|left| changes > left);
+            changes_left = changes_left.map(|left| left.saturating_sub(changes));
+        }
+        for change in &diffs {
+            let (count_l, count_r) = change.line_counts();
+            lineno_l += count_l;
+            lineno_r += count_r;
+        }
+        stats += diff::diff_stats_with_options(&diffs, &settings.render_opts);
+        differ |= changed;
+    }
+    if settings.stat != Stat::Off {
+        println!("{}", stats);
+    }
+    differ
+}
+
+// Diffs and prints a single pair of files, returning whether they differ.
+fn diff_files(lpath: &str, rpath: &str, settings: &Settings) -> bool {
+    let (lpath, rpath) = if settings.reverse { (rpath, lpath) } else { (lpath, rpath) };
+    let differ = if settings.streaming {
+        diff_files_streaming(lpath, rpath, settings)
+    } else if settings.format == Format::Numstat && settings.encoding.is_none() {
+        diff_files_numstat(lpath, rpath, settings)
+    } else {
+        diff_contents(&read_input_or_die(lpath, settings), &read_input_or_die(rpath, settings),
+                      (lpath, rpath), rpath, settings)
+    };
+    if !differ && settings.report_identical {
+        println!("Files {} and {} are identical", lpath, rpath);
+    }
+    differ
+}
+
+// Counts the changes between a single pair of files for --format numstat,
+// returning whether they differ. Binary files aren't diffed, and like git their
+// counts are given as "-".
+fn diff_files_numstat(lpath: &str, rpath: &str, settings: &Settings) -> bool {
+    let read = |path| jiff::read_source_bytes(path, settings.decompress).unwrap_or_else(|error| die(error));
+    let (lcontent, rcontent) = (read(lpath), read(rpath));
+    if lcontent == rcontent {
+        return false;
+    }
+    if jiff::is_binary(&lcontent) || jiff::is_binary(&rcontent) {
+        if !settings.quiet {
+            print_binary_numstat(rpath);
+        }
+        return true;
+    }
+    let (lfile, rfile) = (String::from_utf8_lossy(&lcontent), String::from_utf8_lossy(&rcontent));
+    diff_contents(&lfile, &rfile, (lpath, rpath), rpath, settings)
+}
+
+fn print_numstat(diffs: &[diff::Diff], path: &str) {
+    let (added, removed) = diff::numstat(diffs);
+    println!("{}\t{}\t{}", added, removed, path);
+}
+
+fn print_binary_numstat(path: &str) {
+    println!("-\t-\t{}", path);
+}
+
+// Diffs and prints two texts given on the command line, returning whether they
+// differ. As they aren't files, they're headed as just left and right.
+fn diff_literals(left: &str, right: &str, settings: &Settings) -> bool {
+    let (left, right) = if settings.reverse { (right, left) } else { (left, right) };
+    diff_contents(left, right, ("left", "right"), "", settings)
+}
+
+// The outcome of comparing one path in two directory trees.
+enum DirReport {
+    OnlyIn(PathBuf, String),
+    Renamed(PathBuf, PathBuf),
+    Differ(PathBuf, PathBuf),
+    Diff(String, String, Vec<diff::Diff>, Box<RenderOptions>),
+    Identical(PathBuf, PathBuf),
+}
+
+fn compare_dir_entry(ldir: &Path, rdir: &Path, entry: &DirEntry, settings: &Settings) -> DirReport {
+    let only_in = |dir: &Path, path: &Path| {
+        let parent = match path.parent() {
+            Some(parent) if parent != Path::new("") => dir.join(parent),
+            _ => dir.to_path_buf(),
+        };
+        let name = path.file_name().unwrap_or_default();
+        DirReport::OnlyIn(parent, name.to_string_lossy().into_owned())
+    };
+    // The machine readable formats count a file in only one tree as wholly
+    // removed or added, as git does, rather than reporting it.
+    let counted = matches!(settings.format, Format::Numstat | Format::JsonPatch);
+    let (lrel, rrel) = match entry {
+        DirEntry::LeftOnly(path) if !counted => return only_in(ldir, path),
+        DirEntry::RightOnly(path) if !counted => return only_in(rdir, path),
+        DirEntry::Both(path) | DirEntry::LeftOnly(path) | DirEntry::RightOnly(path) => (path, path),
+        DirEntry::Renamed(from, to) => (from, to),
+    };
+    let (in_left, in_right) = match entry {
+        DirEntry::LeftOnly(_) => (true, false),
+        DirEntry::RightOnly(_) => (false, true),
+        _ => (true, true),
+    };
+    let (lpath, rpath) = (ldir.join(lrel), rdir.join(rrel));
+    let read_bytes = |path: &Path, present| if present { fs::read(path) } else { Ok(Vec::new()) };
+    if let (Ok(lbytes), Ok(rbytes)) = (read_bytes(&lpath, in_left), read_bytes(&rpath, in_right)) {
+        if lbytes == rbytes && in_left && in_right {
+            return if lrel == rrel {
+                DirReport::Identical(lpath, rpath)
+            } else {
+                DirReport::Renamed(lpath, rpath)
+            };
+        }
+        // Binary files can only be counted as differing.
+        if settings.format == Format::Numstat && settings.encoding.is_none()
+                && (jiff::is_binary(&lbytes) || jiff::is_binary(&rbytes)) {
+            return DirReport::Differ(lpath, rpath);
+        }
+    }
+    let path = rrel;
+    if settings.skip_globs.iter().any(|glob| glob.matches_path(path)) {
+        return DirReport::Differ(lpath, rpath);
+    }
+    let (lpath_str, rpath_str) = (lpath.to_string_lossy(), rpath.to_string_lossy());
+    let read = |path: &str, present| {
+        if present { read_input_or_die(path, settings) } else { String::new() }
+    };
+    let (diffs, render_opts) = calculate_diffs(&read(&lpath_str, in_left),
+                                               &read(&rpath_str, in_right),
+                                               &rpath_str, settings);
+    // An empty file in only one tree still counts, as having no lines.
+    if !diff::has_changes(&diffs) && in_left && in_right {
+        return DirReport::Identical(lpath, rpath);
+    }
+    // Like git, the missing side of a file in only one tree is headed as
+    // /dev/null.
+    let name = |path, present| if present { path } else { "/dev/null" };
+    let render_opts = with_header(render_opts, name(&lpath_str, in_left),
+                                  name(&rpath_str, in_right), settings);
+    let (lpath, rpath) = (lpath_str, rpath_str);
+    DirReport::Diff(lpath.into_owned(), rpath.into_owned(), diffs, Box::new(render_opts))
+}
+
+// The header template with its placeholders filled in. Anything in braces which
+// isn't a placeholder is left as it is.
+fn format_header(template: &str, path: &str, left: &str, right: &str) -> String {
+    let mut header = String::new();
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        header.push_str(&rest[..open]);
+        rest = &rest[open..];
+        let value = rest.find('}').and_then(|close| match &rest[1..close] {
+            "path" => Some((path, close)),
+            "left" => Some((left, close)),
+            "right" => Some((right, close)),
+            _ => None,
+        });
+        match value {
+            Some((value, close)) => {
+                header.push_str(value);
+                rest = &rest[close + 1..];
+            },
+            None => {
+                header.push('{');
+                rest = &rest[1..];
+            },
+        }
+    }
+    header.push_str(rest);
+    header
+}
+
+// The path of a file within the directory tree it was found in.
+fn relative_path(path: &Path, dir: &Path) -> String {
+    path.strip_prefix(dir).unwrap_or(path).to_string_lossy().into_owned()
+}
+
+// Orders the reports by how many lines of each file changed, the most first
+// unless asked for the fewest. Files which weren't diffed count as having no
+// changed lines, and files with as many keep their path order.
+fn sort_reports_by_size(reports: &mut [DirReport], fewest_first: bool) {
+    reports.sort_by_cached_key(|report| {
+        let changed_lines = match report {
+            DirReport::Diff(_, _, diffs, render_opts) => {
+                diff::diff_stats_with_options(diffs, render_opts).changed_lines()
+            },
+            _ => 0,
+        };
+        if fewest_first {
+            changed_lines as isize
+        } else {
+            -(changed_lines as isize)
+        }
+    });
+}
+
+// Diffs every file in two directory trees, returning whether any differ. Only
+// files which differ are printed, along with any found in just one tree. The
+// files are diffed in parallel but printed in order.
+fn diff_dirs(ldir: &str, rdir: &str, settings: &Settings) -> bool {
+    let (ldir, rdir) = if settings.reverse { (rdir, ldir) } else { (ldir, rdir) };
+    let (ldir, rdir) = (Path::new(ldir), Path::new(rdir));
+    let entries = dir::pair_files_excluding(ldir, rdir, &settings.exclude_globs)
+        .unwrap_or_else(|error| {
+            eprintln!("Could not read {} or {}: {}", ldir.display(), rdir.display(), error);
+            pager::exit(2);
+        });
+    let entries = match settings.rename_threshold {
+        Some(threshold) => dir::detect_renames(ldir, rdir, entries, threshold).unwrap_or_else(|error| {
+            eprintln!("Could not read {} or {}: {}", ldir.display(), rdir.display(), error);
+            pager::exit(2);
+        }),
+        None => entries,
+    };
+    let pool = ThreadPoolBuilder::new()
+        .num_threads(settings.jobs.unwrap_or(0))
+        .build()
+        .unwrap_or_else(|error| {
+            eprintln!("Could not start {} jobs: {}", settings.jobs.unwrap_or(0), error);
+            pager::exit(2);
+        });
+    if settings.quiet {
+        // Stop as soon as any file is found to differ.
+        return pool.install(|| {
+            entries.par_iter().any(|entry| {
+                !matches!(compare_dir_entry(ldir, rdir, entry, settings), DirReport::Identical(..))
+            })
+        });
+    }
+    let mut reports: Vec<DirReport> = pool.install(|| {
+        entries.par_iter()
+               .map(|entry| compare_dir_entry(ldir, rdir, entry, settings))
+               .collect()
+    });
+    match settings.sort_by {
+        SortBy::Path => {},
+        SortBy::Size => sort_reports_by_size(&mut reports, false),
+        SortBy::SizeAscending => sort_reports_by_size(&mut reports, true),
+    }
+    let mut any_differ = false;
+    for report in reports {
+        match report {
+            DirReport::OnlyIn(dir, name) => {
+                println!("Only in {}: {}", dir.display(), name);
+                any_differ = true;
+            },
+            DirReport::Renamed(lpath, rpath) if settings.format == Format::Numstat => {
+                let (from, to) = (relative_path(&lpath, ldir), relative_path(&rpath, rdir));
+                println!("0\t0\t{} => {}", from, to);
+                any_differ = true;
+            },
+            DirReport::Renamed(lpath, rpath) => {
+                println!("Renamed {} to {}", lpath.display(), rpath.display());
+                any_differ = true;
+            },
+            DirReport::Differ(_, rpath) if settings.format == Format::Numstat => {
+                print_binary_numstat(&relative_path(&rpath, rdir));
+                any_differ = true;
+            },
+            DirReport::Differ(lpath, rpath) => {
+                println!("Files {} and {} differ", lpath.display(), rpath.display());
+                any_differ = true;
+            },
+            DirReport::Diff(_, rpath, diffs, _) if settings.format == Format::Numstat => {
+                print_numstat(&diffs, &relative_path(Path::new(&rpath), rdir));
+                any_differ = true;
+            },
+            DirReport::Diff(lpath, rpath, diffs, render_opts) => {
+                let path = relative_path(Path::new(&rpath), rdir);
+                println!("{}", format_header(&settings.header_format, &path, &lpath, &rpath));
+                any_differ |= print_changeset(&diffs, &render_opts, settings);
+            },
+            DirReport::Identical(lpath, rpath) => {
+                if settings.report_identical {
+                    println!("Files {} and {} are identical", lpath.display(), rpath.display());
+                }
+            },
+        }
+    }
+    any_differ
+}
+
+// Diffs and prints a file as it was at a git revision against how it is now,
+// returning whether they differ. A file which didn't exist at the revision is
+// shown as entirely added.
+fn diff_git_revision(rev: &str, path: &str, settings: &Settings) -> bool {
+    let old = jiff::read_git_revision(rev, path)
+        .unwrap_or_else(|error| die(error))
+        .unwrap_or_default();
+    let new = read_input_or_die(path, settings);
+    let old_name = format!("{}:{}", rev, path);
+    if settings.reverse {
+        diff_contents(&new, &old, (path, &old_name), path, settings)
+    } else {
+        diff_contents(&old, &new, (&old_name, path), path, settings)
+    }
+}
+
+// Diffs and prints the files in two zip archives, either every file or just the
+// named entry, returning whether any differ. Files in just one archive are
+// reported as in directory mode.
+fn diff_zips(lzip: &str, rzip: &str, entry: Option<&str>, settings: &Settings) -> bool {
+    let (lzip, rzip) = if settings.reverse { (rzip, lzip) } else { (lzip, rzip) };
+    if let Some(entry) = entry {
+        let lcontent = jiff::read_zip_entry(lzip, entry).unwrap_or_else(|error| die(error));
+        let rcontent = jiff::read_zip_entry(rzip, entry).unwrap_or_else(|error| die(error));
+        let (lcontent, rcontent) = match (lcontent, rcontent) {
+            (Some(lcontent), Some(rcontent)) => (lcontent, rcontent),
+            (lcontent, _) => die(JiffError::MissingZipEntry {
+                path: if lcontent.is_none() { lzip } else { rzip }.to_string(),
+                entry: entry.to_string(),
+            }),
+        };
+        return diff_zip_entry(lzip, rzip, entry, &lcontent, &rcontent, settings);
+    }
+    let mut lentries = jiff::read_zip_entries(lzip).unwrap_or_else(|error| die(error));
+    let rentries = jiff::read_zip_entries(rzip).unwrap_or_else(|error| die(error));
+    let mut any_differ = false;
+    let mut printed = false;
+    for (name, rcontent) in &rentries {
+        let lcontent = match lentries.remove(name) {
+            Some(lcontent) => lcontent,
+            None => {
+                any_differ = true;
+                if !settings.quiet {
+                    println!("Only in {}: {}", rzip, name);
+                }
+                continue;
+            },
+        };
+        if lcontent == *rcontent {
+            if settings.report_identical {
+                println!("Files {}:{} and {}:{} are identical", lzip, name, rzip, name);
+            }
+            continue;
+        }
+        any_differ = true;
+        if settings.quiet {
+            return true;
+        }
+        // Like directory mode, only files which are diffed get a header.
+        if !jiff::is_binary(&lcontent) && !jiff::is_binary(rcontent) {
+            if printed {
+                println!();
+            }
+            printed = true;
+            println!("diff {}:{} {}:{}", lzip, name, rzip, name);
+        }
+        diff_zip_entry(lzip, rzip, name, &lcontent, rcontent, settings);
+    }
+    for name in lentries.keys() {
+        any_differ = true;
+        if !settings.quiet {
+            println!("Only in {}: {}", lzip, name);
+        }
+    }
+    any_differ
+}
+
+// Diffs and prints the entry from each of two archives, returning whether they
+// differ. Binary files are only reported as differing.
+fn diff_zip_entry(lzip: &str, rzip: &str, entry: &str, lcontent: &[u8], rcontent: &[u8],
+                  settings: &Settings) -> bool {
+    let (lname, rname) = (format!("{}:{}", lzip, entry), format!("{}:{}", rzip, entry));
+    if jiff::is_binary(lcontent) || jiff::is_binary(rcontent) {
+        let differ = lcontent != rcontent;
+        if differ && !settings.quiet {
+            println!("Binary files {} and {} differ", lname, rname);
+        } else if !differ && settings.report_identical {
+            println!("Files {} and {} are identical", lname, rname);
+        }
+        return differ;
+    }
+    let (lfile, rfile) = (String::from_utf8_lossy(lcontent), String::from_utf8_lossy(rcontent));
+    let differ = diff_contents(&lfile, &rfile, (&lname, &rname), entry, settings);
+    if !differ && settings.report_identical {
+        println!("Files {} and {} are identical", lname, rname);
+    }
+    differ
+}
+
+// Diffs and prints the contents of two files, returning whether they differ. The
+// files are headed with their names, and the path is that of the right file, for
+// choosing how to highlight it.
+fn diff_contents(lfile: &str, rfile: &str, (lname, rname): (&str, &str), path: &str,
+                 settings: &Settings) -> bool {
+    if settings.chars {
+        let diffs = diff::calculate_char_diff(lfile, rfile);
+        if diff::has_changes(&diffs) && !settings.quiet {
+            diff::print_char_diffs(&diffs, &settings.render_opts);
+        }
+        return diff::has_changes(&diffs);
+    }
+    let (diffs, render_opts) = calculate_diffs(lfile, rfile, path, settings);
+    if settings.quiet {
+        return diff::has_changes(&diffs);
+    }
+    if settings.format == Format::Numstat {
+        if diff::has_changes(&diffs) {
+            print_numstat(&diffs, rname);
+        }
+        return diff::has_changes(&diffs);
+    }
+    let render_opts = with_header(render_opts, lname, rname, settings);
+    print_changeset(&diffs, &render_opts, settings)
+}
+
+// Calculates the changeset between the contents of two files, along with the
+// options to print it with.
+fn calculate_diffs(lfile: &str, rfile: &str, path: &str, settings: &Settings)
+        -> (Vec<diff::Diff>, RenderOptions) {
+    // Cut the files down to the requested ranges, numbering the lines as they
+    // were in the whole file.
+    let (lfile, lstart) = slice_range(lfile, settings.left_range, "--left-range");
+    let (rfile, rstart) = slice_range(rfile, settings.right_range, "--right-range");
+    let (lfile, rfile) = (lfile.as_str(), rfile.as_str());
+    let render_opts = render_opts_for(path, settings)
+        .start_line(lstart, rstart)
+        .missing_newline(diff::missing_newline(lfile, rfile));
+
+    // Calculate the changeset. If only one file has Windows line endings every
+    // line would otherwise differ, so ignore the CRs just as if asked to, unless
+    // the line endings are to be shown.
+    let diffs = if diff::uses_crlf(lfile) != diff::uses_crlf(rfile)
+            && !settings.render_opts.show_line_endings {
+        let diff_opts = settings.diff_opts.clone().ignore_cr(true);
+        diff::calculate_line_diff(lfile, rfile, &diff_opts)
+    } else {
+        diff::calculate_line_diff(lfile, rfile, &settings.diff_opts)
+    };
+    (diffs, render_opts)
+}
+
+// Prints the changeset, returning whether it has any changes. Like diff, nothing
+// is printed for files without any.
+fn print_changeset(diffs: &[diff::Diff], render_opts: &RenderOptions, settings: &Settings)
+        -> bool {
+    if settings.dump_alignment {
+        dump_alignments(diffs);
+    }
+    if diff::has_changes(diffs) {
+        print_diffs(diffs, render_opts, settings);
+    }
+    if settings.stat != Stat::Off {
+        println!("{}", diff::diff_stats_with_options(diffs, render_opts));
+    }
+    diff::has_changes(diffs)
+}
+
+// Prints the alignment matrix and chosen path of each replacement to stderr,
+// away from the diff itself.
+fn dump_alignments(diffs: &[diff::Diff]) {
+    for diff in diffs {
+        if let diff::Diff::Replace(before, after) = diff {
+            let lines_b: Vec<&str> = before.split('\n').collect();
+            let lines_a: Vec<&str> = after.split('\n').collect();
+            eprint!("{}", diff::dump_alignment(&lines_b, &lines_a));
+        }
+    }
+}
+
+// Returns the lines of the file within the range and the line it starts on,
+// warning if the range had to be clamped to fit the file.
+fn slice_range(file: &str, range: Option<(usize, usize)>, option: &str) -> (String, usize) {
+    let (first, last) = match range {
+        Some(range) => range,
+        None => return (file.to_string(), 1),
+    };
+    let (lines, (taken_first, taken_last)) = diff::slice_lines(file, first, last);
+    if (taken_first, taken_last) != (first, last) && last != usize::MAX {
+        eprintln!("Warning: {} {}:{} is outside the file, using lines {}:{}",
+                  option, first, last, taken_first, taken_last);
+    }
+    (lines, taken_first)
+}
+
+// Parses a line range of the form FIRST:LAST, either of which may be omitted to
+// mean the start or end of the file.
+fn parse_range_or_die(range: &str, option: &str) -> (usize, usize) {
+    let bound = |bound: &str, default: usize| if bound.is_empty() {
+        Some(default)
+    } else {
+        bound.parse::<usize>().ok().filter(|&bound| bound > 0)
+    };
+    let mut bounds = range.splitn(2, ':');
+    let parsed = match (bounds.next(), bounds.next()) {
+        (Some(first), Some(last)) => bound(first, 1).zip(bound(last, usize::MAX)),
+        _ => None,
+    };
+    match parsed {
+        Some((first, last)) if first <= last => (first, last),
+        _ => die(JiffError::InvalidRange { option: option.to_string(), range: range.to_string() }),
     }
 }
 
@@ -23,37 +793,837 @@ fn main() {
                     .arg(Arg::with_name("git-diff")
                         .short("g")
                         .long("git-diff")
-                        .help("Enable git diff mode"))
+                        .conflicts_with_all(&["file1", "file2", "from-list", "apply-format", "zip",
+                                              "git-rev", "literal", "streaming"])
+                        .help("Read what git pipes to its pager from stdin, printing any diff in it as jiff would, for use as core.pager"))
+                    .arg(Arg::with_name("format")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .takes_value(true)
+                        .possible_values(&["text", "jsonpatch", "numstat"])
+                        .conflicts_with("streaming")
+                        .help("Print the diff as text (the default), as JSON line operations, or as the added and removed line counts of each file"))
+                    .arg(Arg::with_name("offsets")
+                        .long("offsets")
+                        .requires("format")
+                        .help("Include the offsets of changes within lines in JSON output"))
                     .arg(Arg::with_name("side-by-side")
                         .short("s")
                         .long("side-by-side")
+                        .overrides_with("no-side-by-side")
                         .help("Enable side-by-side diffing"))
+                    .arg(Arg::with_name("no-side-by-side")
+                        .long("no-side-by-side")
+                        .overrides_with("side-by-side")
+                        .help("Diff line by line, even if side-by-side is set in the config file"))
+                    .arg(Arg::with_name("force-side-by-side")
+                        .long("force-side-by-side")
+                        .conflicts_with_all(&["chars", "moves-only", "annotate"])
+                        .help("Print side by side even on a terminal too narrow for it, rather than unified"))
+                    .arg(Arg::with_name("chars")
+                        .long("chars")
+                        .conflicts_with_all(&["side-by-side", "format", "streaming", "stat",
+                                              "stat-only", "left-range", "right-range"])
+                        .help("Diff each file as a single line, character by character"))
+                    .arg(Arg::with_name("literal")
+                        .short("l")
+                        .long("literal")
+                        .requires_all(&["file1", "file2"])
+                        .conflicts_with_all(&["zip", "git-rev", "from-list", "streaming",
+                                              "apply-format"])
+                        .help("Diff the two arguments themselves as text, rather than the files they name"))
+                    .arg(Arg::with_name("reverse")
+                        .short("R")
+                        .long("reverse")
+                        .help("Swap the two files, so additions become removals and vice versa"))
+                    .arg(Arg::with_name("width")
+                        .short("W")
+                        .long("width")
+                        .value_name("NUM")
+                        .takes_value(true)
+                        .help("Fit side-by-side output into NUM columns instead of the terminal width"))
+                    .arg(Arg::with_name("wrap-indent")
+                        .long("wrap-indent")
+                        .value_name("NUM")
+                        .takes_value(true)
+                        .requires("side-by-side")
+                        .help("Indent the rows long lines wrap onto in side-by-side output by NUM columns"))
+                    .arg(Arg::with_name("tab-width")
+                        .long("tab-width")
+                        .value_name("NUM")
+                        .takes_value(true)
+                        .help("Expand tabs to every NUM columns in side-by-side output (default: 8)"))
+                    .arg(Arg::with_name("expand-tabs")
+                        .long("expand-tabs")
+                        .conflicts_with("preserve-tabs")
+                        .help("Print tabs as spaces (the default in side-by-side output)"))
+                    .arg(Arg::with_name("preserve-tabs")
+                        .long("preserve-tabs")
+                        .help("Print tabs as tabs, so that they copy as tabs (the default in unified output)"))
+                    .arg(Arg::with_name("separator")
+                        .long("separator")
+                        .value_name("SEP")
+                        .takes_value(true)
+                        .help("Draw SEP between the side-by-side columns"))
+                    .arg(Arg::with_name("context-marker")
+                        .long("context-marker")
+                        .value_name("MARKER")
+                        .takes_value(true)
+                        .help("Head each hunk with MARKER instead of its @@ line range"))
+                    .arg(Arg::with_name("number-changes-only")
+                        .long("number-changes-only")
+                        .help("Only show line numbers on changed lines in side-by-side output"))
+                    .arg(Arg::with_name("only-changes")
+                        .long("only-changes")
+                        .conflicts_with_all(&["context", "head", "tail"])
+                        .help("Print only the changed lines, each hunk headed by its line numbers"))
+                    .arg(Arg::with_name("moves-only")
+                        .long("moves-only")
+                        .conflicts_with_all(&["side-by-side", "format", "chars", "streaming"])
+                        .help("Print only the blocks of lines which moved, headed by where they moved from and to"))
+                    .arg(Arg::with_name("color-moved")
+                        .long("color-moved")
+                        .value_name("STYLE")
+                        .takes_value(true)
+                        .possible_values(&["zebra", "blocks", "plain"])
+                        .requires("moves-only")
+                        .help("Color moved blocks in alternating shades, all in one color (the default) or as added lines"))
+                    .arg(Arg::with_name("left-only")
+                        .long("left-only")
+                        .conflicts_with_all(&["right-only", "format"])
+                        .help("Print only the lines removed from the left file"))
+                    .arg(Arg::with_name("right-only")
+                        .long("right-only")
+                        .conflicts_with("format")
+                        .help("Print only the lines added in the right file"))
+                    .arg(Arg::with_name("marker-add")
+                        .long("marker-add")
+                        .value_name("MARKER")
+                        .takes_value(true)
+                        .help("Mark added lines with MARKER instead of +"))
+                    .arg(Arg::with_name("marker-remove")
+                        .long("marker-remove")
+                        .value_name("MARKER")
+                        .takes_value(true)
+                        .help("Mark removed lines with MARKER instead of -"))
+                    .arg(Arg::with_name("marker-same")
+                        .long("marker-same")
+                        .value_name("MARKER")
+                        .takes_value(true)
+                        .help("Mark unchanged lines with MARKER instead of a space"))
+                    .arg(Arg::with_name("max-changes")
+                        .long("max-changes")
+                        .value_name("NUM")
+                        .takes_value(true)
+                        .conflicts_with_all(&["format", "chars"])
+                        .help("Stop printing after NUM changes"))
+                    .arg(Arg::with_name("annotate")
+                        .long("annotate")
+                        .conflicts_with_all(&["side-by-side", "format", "chars"])
+                        .help("Tag each line with the file it came from"))
+                    .arg(Arg::with_name("tag-left")
+                        .long("tag-left")
+                        .value_name("TAG")
+                        .takes_value(true)
+                        .requires("annotate")
+                        .help("Tag lines from the left file with TAG instead of L:"))
+                    .arg(Arg::with_name("tag-right")
+                        .long("tag-right")
+                        .value_name("TAG")
+                        .takes_value(true)
+                        .requires("annotate")
+                        .help("Tag lines from the right file with TAG instead of R:"))
+                    .arg(Arg::with_name("tag-same")
+                        .long("tag-same")
+                        .value_name("TAG")
+                        .takes_value(true)
+                        .requires("annotate")
+                        .help("Tag lines in both files with TAG instead of ="))
+                    .arg(Arg::with_name("context")
+                        .short("C")
+                        .long("context")
+                        .value_name("NUM")
+                        .takes_value(true)
+                        .help("Only show NUM unchanged lines around each change"))
+                    .arg(Arg::with_name("left-range")
+                        .long("left-range")
+                        .value_name("FIRST:LAST")
+                        .takes_value(true)
+                        .conflicts_with("streaming")
+                        .help("Only diff lines FIRST to LAST of the left file"))
+                    .arg(Arg::with_name("right-range")
+                        .long("right-range")
+                        .value_name("FIRST:LAST")
+                        .takes_value(true)
+                        .conflicts_with("streaming")
+                        .help("Only diff lines FIRST to LAST of the right file"))
+                    .arg(Arg::with_name("collapse-over")
+                        .long("collapse-over")
+                        .value_name("NUM")
+                        .takes_value(true)
+                        .requires("context")
+                        .help("Show unchanged runs of up to NUM lines between changes in full, rather than cutting them to the context"))
+                    .arg(Arg::with_name("head")
+                        .long("head")
+                        .value_name("NUM")
+                        .takes_value(true)
+                        .requires("context")
+                        .help("Always show the first NUM lines of the files"))
+                    .arg(Arg::with_name("tail")
+                        .long("tail")
+                        .value_name("NUM")
+                        .takes_value(true)
+                        .requires("context")
+                        .help("Always show the last NUM lines of the files"))
+                    .arg(Arg::with_name("function-context")
+                        .long("function-context")
+                        .requires("context")
+                        .help("Show the function each hunk is in on its header"))
+                    .arg(Arg::with_name("function-regex")
+                        .long("function-regex")
+                        .alias("context-regex")
+                        .value_name("REGEX")
+                        .takes_value(true)
+                        .requires("context")
+                        .help("Treat lines matching REGEX as the start of a function (implies --function-context)"))
+                    .arg(Arg::with_name("algorithm")
+                        .long("algorithm")
+                        .value_name("ALGORITHM")
+                        .takes_value(true)
+                        .possible_values(&["lcs", "myers", "patience"])
+                        .help("Find the changes with a longest common subsequence (the default), Myers diff or patience diff"))
+                    .arg(Arg::with_name("patience")
+                        .long("patience")
+                        .conflicts_with("algorithm")
+                        .help("Anchor the diff on lines which occur once in each file (same as --algorithm patience)"))
+                    .arg(Arg::with_name("minimal")
+                        .long("minimal")
+                        .help("Trim changes down to the smallest possible regions"))
+                    .arg(Arg::with_name("ignore-cr-at-eol")
+                        .long("ignore-cr-at-eol")
+                        .help("Ignore carriage returns at the end of lines"))
+                    .arg(Arg::with_name("ignore-blank-lines")
+                        .short("B")
+                        .long("ignore-blank-lines")
+                        .help("Ignore changes whose lines are all blank"))
+                    .arg(Arg::with_name("ignore-columns")
+                        .long("ignore-columns")
+                        .value_name("FIRST:LAST")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .help("Compare lines without the characters in columns FIRST to LAST, though still showing them"))
+                    .arg(Arg::with_name("ignore-matching-lines")
+                        .short("I")
+                        .long("ignore-matching-lines")
+                        .value_name("REGEX")
+                        .takes_value(true)
+                        .help("Ignore changes whose lines all match REGEX"))
+                    .arg(Arg::with_name("only-matching-lines")
+                        .long("only-matching-lines")
+                        .value_name("REGEX")
+                        .takes_value(true)
+                        .help("Only diff lines matching REGEX, showing the rest as unchanged"))
+                    .arg(Arg::with_name("hide-unmatched")
+                        .long("hide-unmatched")
+                        .requires("only-matching-lines")
+                        .help("Leave out the lines not matching --only-matching-lines, rather than showing them"))
+                    .arg(Arg::with_name("streaming")
+                        .long("streaming")
+                        .help("Read the files a window of lines at a time, for files too large for memory"))
+                    .arg(Arg::with_name("stat")
+                        .long("stat")
+                        .help("Print a summary of the changed lines after the diff"))
+                    .arg(Arg::with_name("stat-only")
+                        .long("stat-only")
+                        .help("Print only a summary of the changed lines"))
+                    .arg(Arg::with_name("show-whitespace")
+                        .long("show-whitespace")
+                        .overrides_with("no-show-whitespace")
+                        .help("Show tabs as \u{2192} and trailing spaces as \u{b7}"))
+                    .arg(Arg::with_name("no-show-whitespace")
+                        .long("no-show-whitespace")
+                        .overrides_with("show-whitespace")
+                        .help("Show whitespace as it is, even if show-whitespace is set in the config file"))
+                    .arg(Arg::with_name("show-line-endings")
+                        .long("show-line-endings")
+                        .conflicts_with("ignore-cr-at-eol")
+                        .help("End each line with \u{240d}\u{240a} for a CRLF line ending or \u{240a} for LF"))
+                    .arg(Arg::with_name("no-intraline")
+                        .long("no-intraline")
+                        .alias("no-highlight")
+                        .help("Disables highlighting of changes within lines"))
+                    .arg(Arg::with_name("align-threshold")
+                        .long("align-threshold")
+                        .value_name("RATIO")
+                        .takes_value(true)
+                        .help("Only pair up replaced lines differing by at most RATIO (0.0-1.0)"))
+                    .arg(Arg::with_name("max-align-lines")
+                        .long("max-align-lines")
+                        .value_name("LINES")
+                        .takes_value(true)
+                        .help("Don't pair up the lines of replacements longer than LINES, or 0 for no limit (default: 500)"))
+                    .arg(Arg::with_name("max-line-length")
+                        .long("max-line-length")
+                        .value_name("CHARS")
+                        .takes_value(true)
+                        .help("Don't pair up or highlight the changes within lines longer than CHARS, or 0 for no limit (default: 4000)"))
+                    .arg(Arg::with_name("no-highlight-whitespace-only-changes")
+                        .long("no-highlight-whitespace-only-changes")
+                        .conflicts_with("no-intraline")
+                        .help("Don't highlight the changes within replaced lines which differ only in whitespace"))
+                    .arg(Arg::with_name("inline-context")
+                        .long("inline-context")
+                        .value_name("CHARS")
+                        .takes_value(true)
+                        .conflicts_with("no-intraline")
+                        .help("Within replaced lines, only show CHARS of unchanged text either side of each change"))
+                    .arg(Arg::with_name("inline")
+                        .long("inline")
+                        .alias("combined")
+                        .overrides_with("no-inline")
+                        .help("Show single line replacements as one line with the changes interleaved"))
+                    .arg(Arg::with_name("no-inline")
+                        .long("no-inline")
+                        .overrides_with("inline")
+                        .help("Show replacements as removed and added lines, even if inline is set in the config file"))
+                    .arg(Arg::with_name("emphasis")
+                        .long("emphasis")
+                        .value_name("MODE")
+                        .takes_value(true)
+                        .possible_values(&["changes", "line"])
+                        .help("Tint only the changes within replaced lines, or the whole line"))
+                    .arg(Arg::with_name("theme")
+                        .long("theme")
+                        .value_name("THEME")
+                        .takes_value(true)
+                        .possible_values(&["dark", "light", "mono"])
+                        .help("Color the diff to suit a dark or light background, or in bold and underline only (default: dark)"))
+                    .arg(Arg::with_name("highlight-style")
+                        .long("highlight-style")
+                        .value_name("STYLE")
+                        .takes_value(true)
+                        .possible_values(&["fg", "bg", "reverse", "underline"])
+                        .help("Highlight changes within lines in bold, on a background, reversed or underlined, alike in unified and side-by-side output"))
+                    .arg(Arg::with_name("syntax")
+                        .long("syntax")
+                        .conflicts_with_all(&["no-color", "format", "chars"])
+                        .help("Syntax highlight unchanged lines in the language of the file's extension"))
+                    .arg(Arg::with_name("word-diff")
+                        .long("word-diff")
+                        .conflicts_with("no-intraline")
+                        .help("Highlight the changes within lines by whole word rather than by character"))
                     .arg(Arg::with_name("no-color")
                         .long("no-color")
                         .help("Disables colorization of the output"))
+                    .arg(Arg::with_name("color")
+                        .long("color")
+                        .value_name("WHEN")
+                        .takes_value(true)
+                        .possible_values(&["auto", "always", "never"])
+                        .conflicts_with("no-color")
+                        .help("Color the output always, never, or unless writing it to a file with --output (default: auto)"))
+                    .arg(Arg::with_name("output")
+                        .short("o")
+                        .long("output")
+                        .value_name("PATH")
+                        .takes_value(true)
+                        .help("Write the output to the file at PATH rather than stdout"))
+                    .arg(Arg::with_name("quiet")
+                        .short("q")
+                        .long("quiet")
+                        .conflicts_with_all(&["report-identical-files", "stat", "stat-only",
+                                              "apply-format"])
+                        .help("Print nothing, only exit with whether the files differ"))
+                    .arg(Arg::with_name("report-identical-files")
+                        .long("report-identical-files")
+                        .help("Report when files are identical"))
+                    .arg(Arg::with_name("from-list")
+                        .long("from-list")
+                        .value_name("LIST")
+                        .takes_value(true)
+                        .conflicts_with_all(&["file1", "file2"])
+                        .help("Diff each \"left<TAB>right\" pair listed in LIST (- for stdin)"))
+                    .arg(Arg::with_name("apply-format")
+                        .long("apply-format")
+                        .value_name("FORMAT")
+                        .takes_value(true)
+                        .possible_values(&["unified"])
+                        .conflicts_with_all(&["from-list", "file2"])
+                        .help("Read an existing diff in FORMAT from file1 (- for stdin) and print it"))
+                    .arg(Arg::with_name("skip-glob")
+                        .long("skip-glob")
+                        .value_name("GLOB")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .help("When diffing directories, only report whether files matching GLOB differ"))
+                    .arg(Arg::with_name("exclude")
+                        .long("exclude")
+                        .short("x")
+                        .value_name("GLOB")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .help("When diffing directories, leave out files and directories whose path or name matches GLOB"))
+                    .arg(Arg::with_name("sort-by")
+                        .long("sort-by")
+                        .value_name("ORDER")
+                        .takes_value(true)
+                        .possible_values(&["path", "size", "size-asc"])
+                        .help("When diffing directories, print the files in path order, with the most changed lines first (size) or with the fewest first (size-asc)"))
+                    .arg(Arg::with_name("rename-detection")
+                        .long("rename-detection")
+                        .overrides_with("no-rename-detection")
+                        .help("When diffing directories, pair up similar files only found on either side as renames"))
+                    .arg(Arg::with_name("no-rename-detection")
+                        .long("no-rename-detection")
+                        .overrides_with("rename-detection")
+                        .help("Show moved files in directories as removed and added (the default)"))
+                    .arg(Arg::with_name("rename-threshold")
+                        .long("rename-threshold")
+                        .value_name("RATIO")
+                        .takes_value(true)
+                        .help("Only treat files with at least RATIO of their lines in common as renamed (default: 0.5)"))
+                    .arg(Arg::with_name("jobs")
+                        .short("j")
+                        .long("jobs")
+                        .value_name("NUM")
+                        .takes_value(true)
+                        .help("When diffing directories, diff NUM files at once"))
+                    .arg(Arg::with_name("paginate-threshold")
+                        .long("paginate-threshold")
+                        .value_name("LINES")
+                        .takes_value(true)
+                        .help("Page the output once it's longer than LINES (default: the terminal's height)"))
+                    .arg(Arg::with_name("no-pager")
+                        .long("no-pager")
+                        .conflicts_with("paginate-threshold")
+                        .help("Never page the output"))
+                    .arg(Arg::with_name("git-rev")
+                        .long("git-rev")
+                        .value_name("REV")
+                        .takes_value(true)
+                        .conflicts_with_all(&["from-list", "apply-format", "file2", "streaming"])
+                        .requires("file1")
+                        .help("Diff file1 as it was at git revision REV against the working tree"))
+                    .arg(Arg::with_name("encoding")
+                        .long("encoding")
+                        .value_name("ENCODING")
+                        .takes_value(true)
+                        .conflicts_with_all(&["streaming", "zip", "git-rev"])
+                        .help("Read the files as ENCODING (such as windows-1252) rather than UTF-8, printing them as UTF-8"))
+                    .arg(Arg::with_name("no-decompress")
+                        .long("no-decompress")
+                        .conflicts_with_all(&["streaming", "zip", "git-rev"])
+                        .help("Diff gzipped files as they are rather than decompressing them"))
+                    .arg(Arg::with_name("zip")
+                        .long("zip")
+                        .conflicts_with_all(&["from-list", "apply-format", "git-rev", "streaming"])
+                        .requires_all(&["file1", "file2"])
+                        .help("Diff the files in two zip archives, or just the entry named by the third argument"))
+                    .arg(Arg::with_name("no-header")
+                        .long("no-header")
+                        .help("Don't head each file's diff with --- and +++ lines naming the files"))
+                    .arg(Arg::with_name("header-format")
+                        .long("header-format")
+                        .value_name("TEMPLATE")
+                        .takes_value(true)
+                        .help("Head each file's diff in directory mode with TEMPLATE, where {path} is the file's path in the directories and {left} and {right} are its paths on each side [default: \"diff {left} {right}\"]"))
+                    .arg(Arg::with_name("dump-alignment")
+                        .long("dump-alignment")
+                        .hidden(true)
+                        .help("Print the alignment matrix and path of each replacement to stderr, for debugging"))
                     .arg(Arg::with_name("file1")
-                        .required(true)
-                        .help("Left file"))
+                        .help("Left file (- for stdin), or a unified diff to colorize if alone"))
                     .arg(Arg::with_name("file2")
-                        .required(true)
-                        .help("Right file"))
+                        .help("Right file (- for stdin)"))
+                    .arg(Arg::with_name("entry")
+                        .requires("zip")
+                        .help("The path inside the archives to diff, with --zip"))
                     .get_matches();
-    let lpath = matches.value_of("file1").expect("file1 is required");
-    let rpath = matches.value_of("file2").expect("file2 is required");
-    let color = !matches.is_present("no-color");
-    let side_by_side = matches.is_present("side-by-side");
-    let lfile = read_file_or_die(lpath);
-    let rfile = read_file_or_die(rpath);
-    let max_line_count = max(lfile.matches('\n').count(), rfile.matches('\n').count());
-    //println!("lpath: {}\n{}\nrpath: {}\n{}\n", lpath, lfile, rpath, rfile);
-
-    // Calculate the changeset.
-    let diffs = diff::calculate_line_diff(&lfile, &rfile);
-
-    // Print the changeset.
-    if side_by_side {
-        diff::print_diffs_side_by_side(&diffs, max_line_count, 0, color);
-    } else {
-        diff::print_diffs(&diffs, 0, color);
+    let config = config::load().unwrap_or_else(|error| {
+        eprintln!("Invalid config file {}", error);
+        pager::exit(2);
+    });
+    let parse_count = |name: &str| matches.value_of(name).map(|count| {
+        count.parse::<usize>().unwrap_or_else(|_| {
+            eprintln!("Invalid --{} value: {}", name, count);
+            pager::exit(2);
+        })
+    });
+    let context = if matches.is_present("only-changes") {
+        Some(0)
+    } else {
+        parse_count("context").or(config.context)
+    };
+    let head = parse_count("head").unwrap_or(0);
+    let collapse_over = parse_count("collapse-over").unwrap_or(0);
+    let tail = parse_count("tail").unwrap_or(0);
+    let width = parse_count("width").or(config.width);
+    let encoding = matches.value_of("encoding").map(|label| {
+        Encoding::for_label(label.as_bytes()).unwrap_or_else(|| {
+            eprintln!("Unknown --encoding: {}", label);
+            pager::exit(2);
+        })
+    });
+    let tab_width = match parse_count("tab-width").or(config.tab_width) {
+        Some(0) => {
+            eprintln!("Invalid --tab-width value: 0");
+            pager::exit(2);
+        },
+        tab_width => tab_width.unwrap_or(diff::DEFAULT_TAB_WIDTH),
+    };
+    let ignore_matching_lines = matches.value_of("ignore-matching-lines").map(|pattern| {
+        Regex::new(pattern).unwrap_or_else(|error| {
+            eprintln!("Invalid --ignore-matching-lines pattern: {}", error);
+            pager::exit(2);
+        })
+    });
+    let only_matching_lines = matches.value_of("only-matching-lines").map(|pattern| {
+        Regex::new(pattern).unwrap_or_else(|error| {
+            eprintln!("Invalid --only-matching-lines pattern: {}", error);
+            pager::exit(2);
+        })
+    });
+    let function_context = if matches.is_present("function-context")
+            || matches.is_present("function-regex") {
+        let pattern = matches.value_of("function-regex").unwrap_or(DEFAULT_FUNCTION_REGEX);
+        Some(Regex::new(pattern).unwrap_or_else(|error| {
+            eprintln!("Invalid --function-regex pattern: {}", error);
+            pager::exit(2);
+        }))
+    } else {
+        None
+    };
+    let parse_ratio = |name: &str, default: f64| matches.value_of(name).map_or(default, |ratio| {
+        match ratio.parse::<f64>() {
+            Ok(ratio) if (0.0..=1.0).contains(&ratio) => ratio,
+            _ => {
+                eprintln!("Invalid --{} value: {}", name, ratio);
+                pager::exit(2);
+            },
+        }
+    });
+    let align_threshold = parse_ratio("align-threshold", 1.0);
+    let rename_threshold = if matches.is_present("rename-detection") {
+        Some(parse_ratio("rename-threshold", 0.5))
+    } else {
+        None
+    };
+    let stat = if matches.is_present("stat-only") {
+        Stat::Only
+    } else if matches.is_present("stat") {
+        Stat::On
+    } else {
+        Stat::Off
+    };
+    let format = match matches.value_of("format") {
+        Some("jsonpatch") => Format::JsonPatch,
+        Some("numstat") => Format::Numstat,
+        _ => Format::Text,
+    };
+    if matches.is_present("offsets") && format != Format::JsonPatch {
+        eprintln!("--offsets requires --format jsonpatch");
+        pager::exit(2);
+    }
+    let mut render_opts = RenderOptions::new()
+        .color(match matches.value_of("color") {
+            Some("always") => true,
+            Some("never") => false,
+            _ => !matches.is_present("no-color") && config.color.unwrap_or(true)
+                 && !matches.is_present("output"),
+        })
+        .context(context)
+        .collapse_over(collapse_over)
+        .edges(head, tail)
+        .width(width)
+        .wrap_indent(parse_count("wrap-indent").unwrap_or(0))
+        .tab_width(tab_width)
+        .tabs(if matches.is_present("expand-tabs") {
+            Some(Tabs::Expand)
+        } else if matches.is_present("preserve-tabs") {
+            Some(Tabs::Preserve)
+        } else {
+            None
+        })
+        .max_changes(parse_count("max-changes"))
+        .offsets(matches.is_present("offsets"))
+        .show_whitespace(switch(&matches, "show-whitespace", config.show_whitespace))
+        .show_line_endings(matches.is_present("show-line-endings"))
+        .number_changes_only(matches.is_present("number-changes-only"))
+        .intraline(!matches.is_present("no-intraline"))
+        .inline(switch(&matches, "inline", config.inline))
+        .side(if matches.is_present("left-only") {
+            Some(Side::Left)
+        } else if matches.is_present("right-only") {
+            Some(Side::Right)
+        } else {
+            None
+        })
+        .align_threshold(align_threshold)
+        .max_align_lines(match parse_count("max-align-lines") {
+            Some(0) => None,
+            Some(max_lines) => Some(max_lines),
+            None => Some(diff::DEFAULT_MAX_ALIGN_LINES),
+        })
+        .max_line_length(match parse_count("max-line-length") {
+            Some(0) => None,
+            Some(max_length) => Some(max_length),
+            None => Some(diff::DEFAULT_MAX_LINE_LENGTH),
+        })
+        .inline_context(parse_count("inline-context"))
+        .highlight_whitespace_changes(!matches.is_present("no-highlight-whitespace-only-changes"))
+        .hide_unmatched(matches.is_present("hide-unmatched"))
+        .function_context(function_context)
+        .granularity(if matches.is_present("word-diff") { Granularity::Word } else { Granularity::Char })
+        .emphasis(match matches.value_of("emphasis") {
+            Some("line") => Emphasis::Line,
+            _ => Emphasis::Changes,
+        });
+    let value_of = |name: &str, default: &Option<String>| -> Option<String> {
+        matches.value_of(name).map(str::to_string).or_else(|| default.clone())
+    };
+    render_opts = render_opts.theme(match value_of("theme", &config.theme).as_deref() {
+        None | Some("dark") => Theme::Dark,
+        Some("light") => Theme::Light,
+        Some("mono") => Theme::Mono,
+        Some(theme) => {
+            eprintln!("Invalid theme in config file: {}", theme);
+            pager::exit(2);
+        },
+    });
+    let highlight_style = value_of("highlight-style", &config.highlight_style);
+    render_opts = render_opts.highlight_style(match highlight_style.as_deref() {
+        None => None,
+        Some("fg") => Some(HighlightStyle::Fg),
+        Some("bg") => Some(HighlightStyle::Bg),
+        Some("reverse") => Some(HighlightStyle::Reverse),
+        Some("underline") => Some(HighlightStyle::Underline),
+        Some(highlight_style) => {
+            eprintln!("Invalid highlight-style in config file: {}", highlight_style);
+            pager::exit(2);
+        },
+    });
+    render_opts = render_opts.color_moved(match matches.value_of("color-moved") {
+        Some("zebra") => ColorMoved::Zebra,
+        Some("plain") => ColorMoved::Plain,
+        _ => ColorMoved::Blocks,
+    });
+    // The default separator is a box-drawing character, which legacy terminals
+    // can't show.
+    if let Some(separator) = value_of("separator", &config.separator) {
+        render_opts = render_opts.separator(&separator);
+    } else if !diff::utf8_locale() {
+        render_opts = render_opts.separator("|");
+    }
+    render_opts = render_opts
+        .context_marker(value_of("context-marker", &config.context_marker).as_deref());
+    render_opts = render_opts.markers(
+        &value_of("marker-add", &config.marker_add).unwrap_or_else(|| "+".to_string()),
+        &value_of("marker-remove", &config.marker_remove).unwrap_or_else(|| "-".to_string()),
+        &value_of("marker-same", &config.marker_same).unwrap_or_else(|| " ".to_string()));
+    render_opts = render_opts
+        .annotate(matches.is_present("annotate"))
+        .tags(matches.value_of("tag-left").unwrap_or("L:"),
+              matches.value_of("tag-right").unwrap_or("R:"),
+              matches.value_of("tag-same").unwrap_or("="));
+    // Output written to a file is never paged, so stdout is redirected to it
+    // first.
+    if let Some(path) = matches.value_of("output") {
+        let file = File::create(path).unwrap_or_else(|error| {
+            eprintln!("Could not create {}: {}", path, error);
+            pager::exit(2);
+        });
+        if let Err(error) = pager::redirect(file) {
+            eprintln!("Could not write to {}: {}", path, error);
+            pager::exit(2);
+        }
+    }
+    // Page the output if it's going to a terminal and is too long for it. Once
+    // stdout is redirected to the pager its width can't be queried, so fix the
+    // width the side-by-side output fits to first.
+    if !matches.is_present("no-pager") && config.pager.unwrap_or(true) {
+        if let Some((term_width, term_height)) = term_size::dimensions_stdout() {
+            if render_opts.width.is_none() {
+                render_opts = render_opts.width(Some(term_width));
+            }
+            if let Err(error) = pager::start(parse_count("paginate-threshold").unwrap_or(term_height)) {
+                eprintln!("Could not start the pager: {}", error);
+            }
+        }
+    }
+    let settings = Settings {
+        format,
+        side_by_side: switch(&matches, "side-by-side", config.side_by_side)
+            || matches.is_present("force-side-by-side"),
+        force_side_by_side: matches.is_present("force-side-by-side"),
+        chars: matches.is_present("chars"),
+        report_identical: matches.is_present("report-identical-files"),
+        quiet: matches.is_present("quiet"),
+        syntax: matches.is_present("syntax"),
+        encoding,
+        decompress: !matches.is_present("no-decompress"),
+        header: !matches.is_present("no-header"),
+        header_format: matches.value_of("header-format").unwrap_or("diff {left} {right}").to_string(),
+        dump_alignment: matches.is_present("dump-alignment"),
+        moves_only: matches.is_present("moves-only"),
+        reverse: matches.is_present("reverse"),
+        streaming: matches.is_present("streaming"),
+        stat,
+        left_range: matches.value_of("left-range")
+            .map(|range| parse_range_or_die(range, "--left-range")),
+        right_range: matches.value_of("right-range")
+            .map(|range| parse_range_or_die(range, "--right-range")),
+        jobs: parse_count("jobs"),
+        rename_threshold,
+        skip_globs: matches.values_of("skip-glob").into_iter().flatten().map(|glob| {
+            Pattern::new(glob).unwrap_or_else(|error| {
+                eprintln!("Invalid --skip-glob pattern {}: {}", glob, error);
+                pager::exit(2);
+            })
+        }).collect(),
+        sort_by: match matches.value_of("sort-by") {
+            Some("size") => SortBy::Size,
+            Some("size-asc") => SortBy::SizeAscending,
+            _ => SortBy::Path,
+        },
+        exclude_globs: matches.values_of("exclude").into_iter().flatten().map(|glob| {
+            Pattern::new(glob).unwrap_or_else(|error| {
+                eprintln!("Invalid --exclude pattern {}: {}", glob, error);
+                pager::exit(2);
+            })
+        }).collect(),
+        diff_opts: DiffOptions::new()
+            .algorithm(match matches.value_of("algorithm") {
+                _ if matches.is_present("patience") => Algorithm::Patience,
+                Some("myers") => Algorithm::Myers,
+                Some("patience") => Algorithm::Patience,
+                _ => Algorithm::Lcs,
+            })
+            .minimal(matches.is_present("minimal"))
+            .ignore_cr(matches.is_present("ignore-cr-at-eol"))
+            .ignore_blank_lines(matches.is_present("ignore-blank-lines"))
+            .ignore_columns(matches.values_of("ignore-columns").into_iter().flatten()
+                .map(|columns| parse_range_or_die(columns, "--ignore-columns"))
+                .collect())
+            .ignore_matching_lines(ignore_matching_lines)
+            .only_matching_lines(only_matching_lines),
+        render_opts,
+    };
+
+    if matches.is_present("git-diff") {
+        page_git_output(&settings);
+    }
+    if matches.is_present("apply-format") {
+        let path = matches.value_of("file1").unwrap_or("-");
+        print_patch(&read_file_or_die(path), path, &settings);
+        pager::exit(0);
+    }
+
+    // Diff either every pair in the list or the two given files or
+    // directories. Like diff, exit with 1 if any pair differed.
+    let any_differ = if matches.is_present("zip") {
+        diff_zips(matches.value_of("file1").expect("file1 is required"),
+                  matches.value_of("file2").expect("file2 is required"),
+                  matches.value_of("entry"), &settings)
+    } else if let Some(rev) = matches.value_of("git-rev") {
+        diff_git_revision(rev, matches.value_of("file1").expect("file1 is required"), &settings)
+    } else if matches.is_present("literal") {
+        diff_literals(matches.value_of("file1").expect("file1 is required"),
+                      matches.value_of("file2").expect("file2 is required"), &settings)
+    } else if let Some(list) = matches.value_of("from-list") {
+        let mut any_differ = false;
+        for (i, (lpath, rpath)) in read_pair_list_or_die(list).iter().enumerate() {
+            if settings.quiet {
+                // One difference settles the exit status.
+                if diff_files(lpath, rpath, &settings) {
+                    any_differ = true;
+                    break;
+                }
+                continue;
+            }
+            if i > 0 {
+                println!();
+            }
+            println!("diff {} {}", lpath, rpath);
+            any_differ |= diff_files(lpath, rpath, &settings);
+        }
+        any_differ
+    } else if let (Some(lpath), Some(rpath)) = (matches.value_of("file1"), matches.value_of("file2")) {
+        if Path::new(lpath).is_dir() && Path::new(rpath).is_dir() {
+            diff_dirs(lpath, rpath, &settings)
+        } else {
+            diff_files(lpath, rpath, &settings)
+        }
+    } else {
+        // Given a single file (or just stdin), there's nothing to diff it with,
+        // but it may be a diff already, to be colorized. A diff is only read
+        // from stdin when one is piped in, rather than waiting on the terminal.
+        if matches.value_of("file1").is_none() && pager::stdin_is_terminal() {
+            eprintln!("error: The following required arguments were not provided:\n    <file1>\n\n{}\n\n\
+                       For more information try --help", matches.usage());
+            pager::exit(1);
+        }
+        let path = matches.value_of("file1").unwrap_or("-");
+        let patch = read_file_or_die(path);
+        if !diff::is_unified_diff(&patch) {
+            eprintln!("{} isn't a unified diff, so needs a second file to diff it against",
+                      if path == "-" { "stdin" } else { path });
+            pager::exit(2);
+        }
+        print_patch(&patch, path, &settings);
+        pager::exit(0);
+    };
+    pager::exit(if any_differ { 1 } else { 0 });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn switch_overrides_config() {
+        let matches = |args: &[&str]| {
+            App::new("jiff")
+                .arg(Arg::with_name("inline").long("inline").overrides_with("no-inline"))
+                .arg(Arg::with_name("no-inline").long("no-inline").overrides_with("inline"))
+                .get_matches_from(std::iter::once("jiff").chain(args.iter().cloned()))
+        };
+        assert!(!switch(&matches(&[]), "inline", None));
+        assert!(switch(&matches(&[]), "inline", Some(true)));
+        assert!(!switch(&matches(&["--no-inline"]), "inline", Some(true)));
+        assert!(switch(&matches(&["--inline"]), "inline", Some(false)));
+        assert!(switch(&matches(&["--no-inline", "--inline"]), "inline", None));
+        assert!(!switch(&matches(&["--inline", "--no-inline"]), "inline", Some(true)));
+    }
+
+    #[test]
+    fn sort_reports_by_changed_lines() {
+        let report = |name: &str, left: &str, right: &str| {
+            let diffs = diff::calculate_line_diff(left, right, &DiffOptions::new());
+            DirReport::Diff(name.to_string(), name.to_string(), diffs, Box::new(RenderOptions::new()))
+        };
+        let names = |reports: &[DirReport]| -> Vec<String> {
+            reports.iter().map(|report| match report {
+                DirReport::Diff(name, ..) => name.clone(),
+                _ => String::new(),
+            }).collect()
+        };
+        let mut reports = vec![report("a", "x\n", "y\n"),
+                               report("b", "x\ny\nz\n", "1\n2\n3\n4\n"),
+                               report("c", "x\ny\n", "x\n1\n2\n")];
+        sort_reports_by_size(&mut reports, false);
+        assert_eq!(vec!["b", "c", "a"], names(&reports));
+        sort_reports_by_size(&mut reports, true);
+        assert_eq!(vec!["a", "c", "b"], names(&reports));
+    }
+
+    #[test]
+    fn format_header_placeholders() {
+        assert_eq!("diff a/x.txt b/x.txt",
+                   format_header("diff {left} {right}", "x.txt", "a/x.txt", "b/x.txt"));
+        assert_eq!("=== x.txt ===", format_header("=== {path} ===", "x.txt", "a/x.txt", "b/x.txt"));
+        assert_eq!("{name} {x.txt} {", format_header("{name} {{path}} {", "x.txt", "a", "b"));
     }
 }
@@ -0,0 +1,163 @@
+// Pages stdout through $PAGER, but only once the output runs past a number of
+// lines, so that output which fits on the screen is printed directly just as
+// git does. Stdout can only be redirected on Unix, so elsewhere the output is
+// never paged.
+#[cfg(unix)]
+use std::env;
+use std::fs::File;
+use std::io::{self, Write};
+#[cfg(unix)]
+use std::io::{BufRead, BufReader};
+#[cfg(unix)]
+use std::os::unix::io::{AsRawFd, FromRawFd};
+#[cfg(unix)]
+use std::panic;
+use std::process;
+#[cfg(unix)]
+use std::process::{Command, Stdio};
+#[cfg(unix)]
+use std::sync::Mutex;
+#[cfg(unix)]
+use std::thread::{self, JoinHandle};
+
+#[cfg(unix)]
+static PAGER: Mutex<Option<Pager>> = Mutex::new(None);
+
+#[cfg(unix)]
+struct Pager {
+    relay: JoinHandle<()>,
+}
+
+// Redirects stdout into a pipe which is relayed to the terminal, or to the
+// pager once more than `threshold` lines have been written.
+#[cfg(unix)]
+pub fn start(threshold: usize) -> io::Result<()> {
+    let mut fds = [0; 2];
+    // The pipe is read by a thread of this process and the terminal is kept to
+    // hand to the pager, so all three descriptors are owned here.
+    let terminal = unsafe {
+        if libc::pipe(fds.as_mut_ptr()) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let terminal = libc::dup(libc::STDOUT_FILENO);
+        if terminal < 0 || libc::dup2(fds[1], libc::STDOUT_FILENO) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        libc::close(fds[1]);
+        File::from_raw_fd(terminal)
+    };
+    let input = BufReader::new(unsafe { File::from_raw_fd(fds[0]) });
+    let relay = thread::spawn(move || relay(input, terminal, threshold));
+    *PAGER.lock().unwrap() = Some(Pager { relay });
+    // A panic (such as failing to print) ends the process without going
+    // through exit, which would lose whatever is still in the pipe.
+    let report = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        report(info);
+        finish();
+    }));
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn start(_threshold: usize) -> io::Result<()> {
+    Ok(())
+}
+
+// Redirects stdout into the file, so that everything printed is written there
+// instead. Once redirected, stdout is no longer a terminal and so isn't paged.
+#[cfg(unix)]
+pub fn redirect(file: File) -> io::Result<()> {
+    // The file's own descriptor is closed when it's dropped, leaving stdout
+    // as the only one open on it.
+    if unsafe { libc::dup2(file.as_raw_fd(), libc::STDOUT_FILENO) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn redirect(_file: File) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "stdout can't be redirected here"))
+}
+
+// Whether stdin is a terminal rather than a pipe or a file.
+#[cfg(unix)]
+pub fn stdin_is_terminal() -> bool {
+    unsafe { libc::isatty(libc::STDIN_FILENO) == 1 }
+}
+
+#[cfg(not(unix))]
+pub fn stdin_is_terminal() -> bool {
+    use std::io::IsTerminal;
+    io::stdin().is_terminal()
+}
+
+// Exits once everything written to stdout has been shown, waiting for the user
+// to quit the pager if one was started.
+pub fn exit(code: i32) -> ! {
+    let _ = io::stdout().flush();
+    #[cfg(unix)]
+    finish();
+    process::exit(code);
+}
+
+// Waits for the relay to pass on the rest of the output, if a pager was
+// started. The lock is only tried, so that a panic while it's held can't
+// deadlock.
+#[cfg(unix)]
+fn finish() {
+    let _ = io::stdout().flush();
+    let pager = match PAGER.try_lock() {
+        Ok(mut pager) => pager.take(),
+        Err(_) => None,
+    };
+    if let Some(pager) = pager {
+        // Closing the write end of the pipe lets the relay see the end of the
+        // output.
+        unsafe {
+            libc::close(libc::STDOUT_FILENO);
+        }
+        let _ = pager.relay.join();
+    }
+}
+
+#[cfg(unix)]
+fn relay(mut input: BufReader<File>, mut terminal: File, threshold: usize) {
+    // Hold back the output until it's known to be too long to fit.
+    let mut held = Vec::new();
+    let mut lines = 0;
+    while lines <= threshold {
+        match input.read_until(b'\n', &mut held) {
+            Ok(0) | Err(_) => {
+                let _ = terminal.write_all(&held);
+                return;
+            },
+            Ok(_) => lines += 1,
+        }
+    }
+    let pager = env::var("PAGER").unwrap_or_else(|_| "less".to_string());
+    let child = Command::new("sh")
+        .arg("-c")
+        .arg(&pager)
+        .env("LESS", env::var("LESS").unwrap_or_else(|_| "FRX".to_string()))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::from(terminal.try_clone().expect("stdout can be duplicated")))
+        .spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(_) => {
+            let _ = terminal.write_all(&held);
+            let _ = io::copy(&mut input, &mut terminal);
+            return;
+        },
+    };
+    let mut stdin = child.stdin.take().expect("pager stdin is piped");
+    if stdin.write_all(&held).is_err() || io::copy(&mut input, &mut stdin).is_err() {
+        // The pager was quit early, so throw the rest of the output away
+        // rather than leave jiff blocked writing to a full pipe.
+        let _ = io::copy(&mut input, &mut io::sink());
+    }
+    drop(stdin);
+    let _ = child.wait();
+}